@@ -1,17 +1,27 @@
 use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use directories::ProjectDirs;
 use gtk4 as gtk;
 use gtk::gdk;
 use gtk::prelude::*;
+#[cfg(feature = "layer-shell")]
+use gtk4_layer_shell::LayerShell;
 use serde::{Deserialize, Serialize};
 use vte4::prelude::*;
-use vte4::{Format, PtyFlags, Terminal};
+use vte4::{Format, PtyFlags, Regex, Terminal};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SshServer {
@@ -37,6 +47,283 @@ struct Passwords {
     passwords: Vec<Password>,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Session {
+    #[serde(default)]
+    windows: Vec<SessionWindow>,
+    // Sessions saved before multiple windows were supported hold a single
+    // window's tabs at the top level.
+    #[serde(default, skip_serializing)]
+    active_tab: Option<u32>,
+    #[serde(default, skip_serializing)]
+    tabs: Vec<SessionTab>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SessionWindow {
+    active_tab: Option<u32>,
+    #[serde(default)]
+    tabs: Vec<SessionTab>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionTab {
+    title: String,
+    root: SessionPane,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SessionPane {
+    Terminal {
+        cwd: Option<PathBuf>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        profile: Option<String>,
+    },
+    Split {
+        direction: String,
+        ratio: f64,
+        start: Box<SessionPane>,
+        end: Box<SessionPane>,
+    },
+}
+
+const DEFAULT_SESSION: &str = "default";
+const DROPDOWN_WINDOW_NAME: &str = "termilyon-dropdown";
+const RESIZE_BORDER: f64 = 6.0;
+const TAB_GROUP_NAME: &str = "termilyon-tabs";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Layout {
+    name: String,
+    #[serde(default)]
+    tabs: Vec<LayoutTab>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LayoutTab {
+    title: Option<String>,
+    #[serde(flatten)]
+    pane: LayoutPane,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LayoutPane {
+    command: Option<String>,
+    cwd: Option<String>,
+    split: Option<String>,
+    ratio: Option<f64>,
+    #[serde(default)]
+    panes: Vec<LayoutPane>,
+}
+
+// A custom clickable pattern. `$0` is the whole match and `$1`..`$9` are
+// capture groups; either `url` is opened or `command` is run through `sh -c`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HyperlinkRule {
+    pattern: String,
+    url: Option<String>,
+    command: Option<String>,
+}
+
+// Overrides for the panes of one profile; unset fields keep the global value.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    scrollback_lines: Option<i32>,
+    font: Option<String>,
+    font_size: Option<i32>,
+    shell: Option<String>,
+    theme: Option<String>,
+    theme_file: Option<PathBuf>,
+    new_tab_cwd: Option<String>,
+    tab_color: Option<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    // `theme` or `theme_file`, resolved when the config is loaded.
+    #[serde(skip)]
+    theme_source: Option<ThemeSource>,
+}
+
+enum HyperlinkTarget {
+    Uri(String),
+    Command(String),
+}
+
+// What a `match_add_regex` tag of a terminal stands for. Custom rules keep
+// the regex VTE matched with, so captures come from the same engine.
+#[derive(Clone)]
+enum HyperlinkMatch {
+    Rule(HyperlinkRule, Regex),
+    Url,
+    FileLine,
+    Email,
+}
+
+// Requests accepted on the control socket. The same type backs the
+// `termilyon msg` subcommands, so the CLI and the protocol stay in sync.
+#[derive(Debug, Subcommand, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum ControlRequest {
+    /// List windows, tabs and panes
+    List,
+    /// Open a new tab, optionally running a command
+    NewTab {
+        #[arg(long)]
+        cwd: Option<PathBuf>,
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        #[serde(default)]
+        argv: Vec<String>,
+    },
+    /// Split a pane (vertical or horizontal)
+    Split {
+        #[arg(long)]
+        pane: Option<u32>,
+        #[arg(long, default_value = "vertical")]
+        direction: String,
+        #[arg(long)]
+        cwd: Option<PathBuf>,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        #[serde(default)]
+        argv: Vec<String>,
+    },
+    /// Send text to a pane as if it was typed
+    SendText {
+        #[arg(long)]
+        pane: Option<u32>,
+        text: String,
+    },
+    /// Print the visible text of a pane
+    GetText {
+        #[arg(long)]
+        pane: Option<u32>,
+    },
+    /// Rename the tab containing a pane
+    RenameTab {
+        #[arg(long)]
+        pane: Option<u32>,
+        title: String,
+    },
+    /// Focus a pane and its window
+    Focus { pane: u32 },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ControlResponse {
+    ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ControlWindow {
+    id: u32,
+    focused: bool,
+    tabs: Vec<ControlTab>,
+}
+
+#[derive(Debug, Serialize)]
+struct ControlTab {
+    index: u32,
+    title: String,
+    active: bool,
+    panes: Vec<ControlPane>,
+}
+
+#[derive(Debug, Serialize)]
+struct ControlPane {
+    id: u32,
+    focused: bool,
+    title: Option<String>,
+    cwd: Option<PathBuf>,
+}
+
+const SEARCH_POPOVER_NAME: &str = "termilyon-search";
+const CONFIG_NOTICE_NAME: &str = "termilyon-config-notice";
+const CONFIG_RELOAD_DELAY: Duration = Duration::from_millis(300);
+
+// PCRE2 compile flags understood by `Regex::for_search`.
+const PCRE2_CASELESS: u32 = 0x0000_0008;
+const PCRE2_MULTILINE: u32 = 0x0000_0400;
+const PCRE2_UTF: u32 = 0x0008_0000;
+const PCRE2_NO_UTF_CHECK: u32 = 0x4000_0000;
+// Match options for `Regex::substitute`.
+const PCRE2_ENDANCHORED: u32 = 0x2000_0000;
+const PCRE2_ANCHORED: u32 = 0x8000_0000;
+
+const MATCH_URL_PATTERN: &str = r#"(?:https?|ftp|file)://[^\s<>"'`]*[^\s<>"'`.,;:!?)\]]"#;
+const MATCH_EMAIL_PATTERN: &str = r"(?:mailto:)?[\w.%+-]+@[\w-]+(?:\.[\w-]+)+";
+const MATCH_FILE_LINE_PATTERN: &str = r"(?:~|\.{1,2})?/?(?:[\w.-]+/)*[\w.-]+\.\w+:\d+(?::\d+)?";
+
+const PANE_NAME_PREFIX: &str = "termilyon-pane-";
+
+static NEXT_PANE_ID: AtomicU32 = AtomicU32::new(1);
+static CONTROL_SOCKET: OnceLock<PathBuf> = OnceLock::new();
+
+const PROFILE_MARKER_NAME: &str = "termilyon-profile-marker";
+
+// Marker colors for profiles without a `tab_color` or theme of their own.
+const PROFILE_MARKER_COLORS: &[&str] = &[
+    "#3584e4", "#2ec27e", "#e66100", "#9141ac", "#f5c211", "#c01c28", "#1c71d8", "#865e3c",
+];
+
+thread_local! {
+    // Profile of each pane that was started with or switched to one, by
+    // pane id.
+    static PANE_PROFILES: RefCell<HashMap<u32, PaneProfile>> = RefCell::new(HashMap::new());
+    // Hyperlink match tags of each pane, by pane id.
+    static HYPERLINK_MATCHES: RefCell<HashMap<u32, HashMap<i32, HyperlinkMatch>>> =
+        RefCell::new(HashMap::new());
+    // Session of each window, by window id.
+    static WINDOW_SESSIONS: RefCell<HashMap<u32, String>> = RefCell::new(HashMap::new());
+    // Sessions that are saved when one of their windows closes.
+    static SAVED_SESSIONS: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+#[derive(Debug, Clone)]
+struct PaneProfile {
+    name: String,
+    color: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SpawnOptions {
+    cwd: Option<PathBuf>,
+    command: Option<Vec<String>>,
+    hold: bool,
+    profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct WindowState {
+    width: i32,
+    height: i32,
+    #[serde(default)]
+    maximized: bool,
+    #[serde(default)]
+    fullscreen: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decorations {
+    None,
+    System,
+    HeaderBar,
+}
+
+#[derive(Debug, Clone)]
+enum NewTabCwd {
+    Home,
+    Current,
+    Path(PathBuf),
+}
+
 #[derive(Debug, Clone)]
 struct Config {
     scrollback_lines: i32,
@@ -45,12 +332,32 @@ struct Config {
     shell: String,
     tab_title: String,
     tab_bar_position: gtk::PositionType,
-    theme_file: Option<PathBuf>,
+    theme: Option<ThemeSource>,
     keybindings: KeyBindings,
     secret: String,
+    restore_session: bool,
+    layouts: Vec<Layout>,
+    new_tab_cwd: NewTabCwd,
+    hyperlinks: Vec<HyperlinkRule>,
+    dropdown: bool,
+    dropdown_width: i32,
+    dropdown_height: i32,
+    dropdown_hide_on_focus_loss: bool,
+    decorations: Decorations,
+    columns: Option<u32>,
+    rows: Option<u32>,
+    remember_window_state: bool,
+    chord_timeout_ms: u32,
+    auto_reload: bool,
+    env: BTreeMap<String, String>,
+    profiles: BTreeMap<String, Profile>,
+    problems: Vec<ConfigProblem>,
+    // config.toml and the files it includes, in the order they were read.
+    files: Vec<PathBuf>,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct RawConfig {
     scrollback_lines: Option<i32>,
     font: Option<String>,
@@ -58,9 +365,64 @@ struct RawConfig {
     shell: Option<String>,
     tab_title: Option<String>,
     tab_bar_position: Option<String>,
+    theme: Option<String>,
     theme_file: Option<String>,
     keybindings: Option<RawKeyBindings>,
     secret: Option<String>,
+    restore_session: Option<bool>,
+    layouts: Option<Vec<Layout>>,
+    new_tab_cwd: Option<String>,
+    hyperlinks: Option<Vec<HyperlinkRule>>,
+    dropdown: Option<bool>,
+    dropdown_width: Option<i32>,
+    dropdown_height: Option<i32>,
+    dropdown_hide_on_focus_loss: Option<bool>,
+    decorations: Option<String>,
+    columns: Option<u32>,
+    rows: Option<u32>,
+    remember_window_state: Option<bool>,
+    chord_timeout_ms: Option<u32>,
+    auto_reload: Option<bool>,
+    env: Option<BTreeMap<String, String>>,
+    profiles: Option<BTreeMap<String, Profile>>,
+}
+
+// Top-level keys of `RawConfig`, for reporting unknown keys. Taken from
+// the field list serde passes to `deserialize_struct`, so it cannot drift
+// from the struct.
+fn config_keys() -> &'static [&'static str] {
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> serde::Deserializer<'de> for FieldNames<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: serde::de::Visitor<'de>>(
+            self,
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            Err(serde::de::Error::custom("only structs have field names"))
+        }
+
+        fn deserialize_struct<V: serde::de::Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            self.deserialize_any(visitor)
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = RawConfig::deserialize(FieldNames(&mut fields));
+    fields
 }
 
 #[derive(Debug, Parser)]
@@ -68,62 +430,275 @@ struct RawConfig {
 struct CliArgs {
     #[arg(long)]
     theme_file: Option<PathBuf>,
+    #[arg(long)]
+    session: Option<String>,
+    #[arg(long)]
+    layout: Option<String>,
+    /// Start the first tab with a profile from the config
+    #[arg(long)]
+    profile: Option<String>,
+    /// Run a command in the first tab instead of the shell; takes the rest of the command line
+    #[arg(
+        short = 'e',
+        long = "command",
+        num_args = 1..,
+        allow_hyphen_values = true,
+        value_name = "ARGV"
+    )]
+    command: Vec<String>,
+    #[arg(long)]
+    working_directory: Option<PathBuf>,
+    #[arg(long)]
+    title: Option<String>,
+    /// Keep the pane open after the command exits
+    #[arg(long)]
+    hold: bool,
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Open a new tab in the running instance
+    #[arg(long)]
+    new_tab: bool,
+    /// Split the focused pane of the running instance (vertical or horizontal)
+    #[arg(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "vertical",
+        value_parser = ["vertical", "horizontal"],
+        value_name = "DIRECTION"
+    )]
+    split: Option<String>,
+    /// Run a shell command in a new tab (or split) of the running instance
+    #[arg(long, value_name = "COMMAND")]
+    run: Option<String>,
+    /// Open the window as a drop-down terminal at the top of the screen
+    #[arg(long)]
+    dropdown: bool,
+    /// Show or hide the drop-down window of the running instance
+    #[arg(long)]
+    toggle: bool,
+    /// Initial window size in character cells, e.g. 120x40
+    #[arg(long, value_name = "COLSxROWS")]
+    geometry: Option<String>,
+    /// Check the config file and exit non-zero if it has errors
+    #[arg(long)]
+    check_config: bool,
+    #[command(subcommand)]
+    action: Option<CliCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+enum CliCommand {
+    /// Send a request to the running instance over its control socket
+    Msg {
+        /// Socket path (defaults to $TERMILYON_SOCKET)
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        #[command(subcommand)]
+        request: ControlRequest,
+    },
+    /// Work with theme files
+    Theme {
+        #[command(subcommand)]
+        command: ThemeCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ThemeCommand {
+    /// Convert an iTerm2, Alacritty, Kitty, Windows Terminal or Xresources
+    /// color scheme into a Termilyon theme
+    Import {
+        file: PathBuf,
+        /// Write the theme to this file instead of standard output
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// The scheme to take from a Windows Terminal settings.json
+        #[arg(long)]
+        scheme: Option<String>,
+    },
 }
 
+// Bindings are kept per action, in the order they are listed in the
+// keybindings dialog. An action can have several bindings or none.
 #[derive(Debug, Clone)]
 struct KeyBindings {
-    new_tab: KeyBinding,
-    close_tab: KeyBinding,
-    rename_tab: KeyBinding,
-    close_panel: KeyBinding,
-    split_vertical: KeyBinding,
-    split_horizontal: KeyBinding,
-    copy: KeyBinding,
-    paste: KeyBinding,
-    reload_config: KeyBinding,
-    show_keybindings: KeyBinding,
-    focus_left: KeyBinding,
-    focus_right: KeyBinding,
-    focus_up: KeyBinding,
-    focus_down: KeyBinding,
-    ssh_manager: KeyBinding,
-    password_manager: KeyBinding,
-    tab_switch: Vec<KeyBinding>,
-}
-
-#[derive(Debug, Clone, Copy)]
+    entries: Vec<(Action, Vec<KeyChord>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 struct KeyBinding {
     key: gdk::Key,
     modifiers: gdk::ModifierType,
 }
 
+// Keys pressed one after another, e.g. `Ctrl+A, c`. Most bindings are a
+// single key.
+type KeyChord = Vec<KeyBinding>;
+
+enum ChordMatch {
+    Action(Action),
+    Prefix,
+    None,
+}
+
+// The keys typed so far of an unfinished chord, shown in the corner of the
+// window until the chord completes or times out.
+struct PendingChord {
+    keys: RefCell<Vec<KeyBinding>>,
+    timeout: RefCell<Option<gtk::glib::SourceId>>,
+    indicator: gtk::Label,
+}
+
+// Reloads the config when config.toml, an included file or a theme changes. Editors
+// often save in several steps, so changes settle for a moment first.
+struct ConfigWatcher {
+    app: gtk::Application,
+    config: Rc<RefCell<Config>>,
+    theme_override: Option<PathBuf>,
+    monitors: RefCell<Vec<gtk::gio::FileMonitor>>,
+    pending: RefCell<Option<gtk::glib::SourceId>>,
+}
+
+// Something wrong with the config file. Errors mean a setting was ignored,
+// warnings point at settings that work but are likely not what was meant.
+#[derive(Debug, Clone)]
+struct ConfigProblem {
+    severity: Severity,
+    // The file the problem is in and the 1-based line and column in it,
+    // when they are known.
+    file: Option<PathBuf>,
+    position: Option<(usize, usize)>,
+    message: String,
+}
+
+impl ConfigProblem {
+    fn error(message: String) -> Self {
+        ConfigProblem {
+            severity: Severity::Error,
+            file: None,
+            position: None,
+            message,
+        }
+    }
+
+    // Points the problem at where `key` was set, if it is known.
+    fn at(mut self, locations: &ConfigLocations, key: &str) -> Self {
+        if let Some(location) = locations.get(key) {
+            self.file = Some(location.file.clone());
+            self.position = Some(location.position);
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ConfigLocation {
+    file: PathBuf,
+    position: (usize, usize),
+}
+
+// Where settings were read from, by dotted key such as `decorations`,
+// `keybindings.new_tab` or `profiles.work.theme`. A file overriding a key
+// also replaces its location.
+type ConfigLocations = HashMap<String, ConfigLocation>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+// Readline and job control shortcuts that a binding takes away from the
+// shell, checked against the first key of user-defined bindings.
+const SHELL_SHORTCUTS: &[(&str, &str)] = &[
+    ("Ctrl+A", "beginning of line"),
+    ("Ctrl+B", "back one character"),
+    ("Ctrl+C", "interrupt"),
+    ("Ctrl+D", "end of input"),
+    ("Ctrl+E", "end of line"),
+    ("Ctrl+F", "forward one character"),
+    ("Ctrl+K", "kill to end of line"),
+    ("Ctrl+L", "clear screen"),
+    ("Ctrl+N", "next history entry"),
+    ("Ctrl+P", "previous history entry"),
+    ("Ctrl+R", "reverse history search"),
+    ("Ctrl+U", "kill to start of line"),
+    ("Ctrl+W", "kill previous word"),
+    ("Ctrl+Y", "yank"),
+    ("Ctrl+Z", "suspend"),
+    ("Ctrl+backslash", "quit"),
+    ("Alt+B", "back one word"),
+    ("Alt+F", "forward one word"),
+    ("Alt+D", "kill next word"),
+    ("Alt+period", "insert last argument"),
+    ("Tab", "completion"),
+    ("Return", "run command"),
+    ("BackSpace", "delete character"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    NewTab,
+    NewWindow,
+    CloseTab,
+    RenameTab,
+    ClosePanel,
+    SplitVertical,
+    SplitHorizontal,
+    Copy,
+    Paste,
+    ReloadConfig,
+    ShowKeybindings,
+    SshManager,
+    PasswordManager,
+    SaveSession,
+    LayoutPicker,
+    ProfilePicker,
+    NewTabWithProfile(String),
+    SwitchTabProfile,
+    SwitchPaneProfile,
+    CommandPalette,
+    Preferences,
+    ThemePicker,
+    Search,
+    ToggleFullscreen,
+    Focus(FocusDirection),
+    SelectTab(u32),
+    NextTab,
+    PreviousTab,
+    // Raw bytes, so `\xHH` escapes can send 8-bit sequences.
+    SendText(Vec<u8>),
+    RunCommand(String),
+    ScrollLines(i32),
+    ScrollPages(i32),
+    ScrollToTop,
+    ScrollToBottom,
+    IncreaseFontSize,
+    DecreaseFontSize,
+    ResetFontSize,
+}
+
+// `[keybindings]` maps action names to one key combination, a list of them,
+// or "none" to unbind the action.
 #[derive(Debug, Deserialize)]
-struct RawKeyBindings {
-    new_tab: Option<String>,
-    close_tab: Option<String>,
-    rename_tab: Option<String>,
-    close_panel: Option<String>,
-    split_vertical: Option<String>,
-    split_horizontal: Option<String>,
-    copy: Option<String>,
-    paste: Option<String>,
-    reload_config: Option<String>,
-    show_keybindings: Option<String>,
-    focus_left: Option<String>,
-    focus_right: Option<String>,
-    focus_up: Option<String>,
-    focus_down: Option<String>,
-    ssh_manager: Option<String>,
-    password_manager: Option<String>,
-    tab_1: Option<String>,
-    tab_2: Option<String>,
-    tab_3: Option<String>,
-    tab_4: Option<String>,
-    tab_5: Option<String>,
-    tab_6: Option<String>,
-    tab_7: Option<String>,
-    tab_8: Option<String>,
-    tab_9: Option<String>,
+#[serde(untagged)]
+enum RawKeys {
+    One(String),
+    Many(Vec<String>),
+}
+
+type RawKeyBindings = BTreeMap<String, RawKeys>;
+
+// Everything an action needs to act on the window it was triggered in.
+#[derive(Clone)]
+struct WindowContext {
+    app: gtk::Application,
+    window: gtk::ApplicationWindow,
+    notebook: gtk::Notebook,
+    config: Rc<RefCell<Config>>,
+    counter: Rc<Cell<u32>>,
+    theme_override: Option<PathBuf>,
+    session_name: String,
 }
 
 impl Config {
@@ -136,14 +711,41 @@ impl Config {
             shell: default_shell,
             tab_title: "Terminal".to_string(),
             tab_bar_position: gtk::PositionType::Top,
-            theme_file: None,
+            theme: None,
             keybindings: default_keybindings(),
             secret: String::new(),
+            restore_session: false,
+            layouts: Vec::new(),
+            new_tab_cwd: NewTabCwd::Current,
+            hyperlinks: Vec::new(),
+            dropdown: false,
+            dropdown_width: 100,
+            dropdown_height: 40,
+            dropdown_hide_on_focus_loss: false,
+            decorations: Decorations::None,
+            columns: None,
+            rows: None,
+            remember_window_state: true,
+            chord_timeout_ms: 1500,
+            auto_reload: true,
+            env: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            problems: Vec::new(),
+            files: Vec::new(),
         };
 
-        if let Some(path) = config_path() {
-            if let Ok(contents) = fs::read_to_string(&path) {
-                if let Ok(raw) = toml::from_str::<RawConfig>(&contents) {
+        // The key the global theme came from, for reporting problems with it.
+        let mut theme_key = "theme";
+        let mut locations = ConfigLocations::new();
+        if let Some(path) = config_path().filter(|path| path.exists()) {
+            let table = parse_config_file(
+                &path,
+                &mut config.problems,
+                &mut config.files,
+                &mut locations,
+            );
+            match toml::Value::Table(table).try_into::<RawConfig>() {
+                Ok(raw) => {
                     if let Some(lines) = raw.scrollback_lines {
                         config.scrollback_lines = lines;
                     }
@@ -160,20 +762,149 @@ impl Config {
                         config.tab_title = tab_title;
                     }
                     if let Some(position) = raw.tab_bar_position {
-                        if let Some(parsed) = parse_tab_bar_position(&position) {
-                            config.tab_bar_position = parsed;
+                        match parse_tab_bar_position(&position) {
+                            Some(parsed) => config.tab_bar_position = parsed,
+                            None => config.problems.push(invalid_value_problem(
+                                &locations,
+                                "tab_bar_position",
+                                &position,
+                                "\"top\" or \"bottom\"",
+                            )),
+                        }
+                    }
+                    // `theme_file` wins when both are set.
+                    if let Some(name) = raw.theme {
+                        match resolve_theme_name(&name) {
+                            Ok(source) => config.theme = Some(source),
+                            Err(err) => config.problems.push(
+                                ConfigProblem::error(format!("theme: {err}"))
+                                    .at(&locations, "theme"),
+                            ),
                         }
                     }
                     if let Some(theme_file) = raw.theme_file {
-                        config.theme_file = resolve_theme_path(&path, &theme_file);
+                        theme_key = "theme_file";
+                        config.theme =
+                            resolve_theme_path(&path, &theme_file).map(ThemeSource::File);
                     }
                     if let Some(raw_keys) = raw.keybindings {
-                        apply_keybindings(&mut config.keybindings, raw_keys);
+                        let problems =
+                            apply_keybindings(&mut config.keybindings, raw_keys, &locations);
+                        config.problems.extend(problems);
                     }
                     if let Some(secret) = raw.secret {
                         config.secret = secret;
                     }
+                    if let Some(restore) = raw.restore_session {
+                        config.restore_session = restore;
+                    }
+                    if let Some(layouts) = raw.layouts {
+                        config.layouts = layouts;
+                    }
+                    if let Some(cwd) = raw.new_tab_cwd {
+                        config.new_tab_cwd = parse_new_tab_cwd(&cwd);
+                    }
+                    if let Some(hyperlinks) = raw.hyperlinks {
+                        config.hyperlinks = hyperlinks;
+                    }
+                    if let Some(dropdown) = raw.dropdown {
+                        config.dropdown = dropdown;
+                    }
+                    if let Some(width) = raw.dropdown_width {
+                        config.dropdown_width = width.clamp(10, 100);
+                    }
+                    if let Some(height) = raw.dropdown_height {
+                        config.dropdown_height = height.clamp(10, 100);
+                    }
+                    if let Some(hide) = raw.dropdown_hide_on_focus_loss {
+                        config.dropdown_hide_on_focus_loss = hide;
+                    }
+                    if let Some(decorations) = raw.decorations {
+                        match parse_decorations(&decorations) {
+                            Some(parsed) => config.decorations = parsed,
+                            None => config.problems.push(invalid_value_problem(
+                                &locations,
+                                "decorations",
+                                &decorations,
+                                "\"none\", \"system\" or \"headerbar\"",
+                            )),
+                        }
+                    }
+                    if let Some(columns) = raw.columns {
+                        config.columns = Some(columns);
+                    }
+                    if let Some(rows) = raw.rows {
+                        config.rows = Some(rows);
+                    }
+                    if let Some(remember) = raw.remember_window_state {
+                        config.remember_window_state = remember;
+                    }
+                    if let Some(timeout) = raw.chord_timeout_ms {
+                        config.chord_timeout_ms = timeout;
+                    }
+                    if let Some(auto_reload) = raw.auto_reload {
+                        config.auto_reload = auto_reload;
+                    }
+                    if let Some(env) = raw.env {
+                        config.env = env;
+                    }
+                    if let Some(mut profiles) = raw.profiles {
+                        for (name, profile) in profiles.iter_mut() {
+                            if let Some(theme) = &profile.theme {
+                                match resolve_theme_name(theme) {
+                                    Ok(source) => profile.theme_source = Some(source),
+                                    Err(err) => {
+                                        let key = format!("profiles.{name}.theme");
+                                        config.problems.push(
+                                            ConfigProblem::error(format!("{key}: {err}"))
+                                                .at(&locations, &key),
+                                        );
+                                    }
+                                }
+                            }
+                            if let Some(file) = &profile.theme_file {
+                                profile.theme_source =
+                                    resolve_theme_path(&path, &file.to_string_lossy())
+                                        .map(ThemeSource::File);
+                            }
+                        }
+                        config.profiles = profiles;
+                    }
                 }
+                Err(err) => config
+                    .problems
+                    .push(ConfigProblem::error(err.message().to_string())),
+            }
+        }
+
+        if let Some(Err(err)) = config.theme.as_ref().map(ThemeSource::load) {
+            config.problems.push(
+                ConfigProblem::error(format!("{theme_key}: {err}")).at(&locations, theme_key),
+            );
+        }
+        for (name, profile) in &config.profiles {
+            if let Some(Err(err)) = profile.theme_source.as_ref().map(ThemeSource::load) {
+                let key = match profile.theme_file {
+                    Some(_) => "theme_file",
+                    None => "theme",
+                };
+                let key = format!("profiles.{name}.{key}");
+                config
+                    .problems
+                    .push(ConfigProblem::error(format!("{key}: {err}")).at(&locations, &key));
+            }
+            if let Some(color) = profile
+                .tab_color
+                .as_ref()
+                .filter(|color| !is_hex_color(color))
+            {
+                let key = format!("profiles.{name}.tab_color");
+                config.problems.push(
+                    ConfigProblem::error(format!(
+                        "{key}: invalid color \"{color}\", expected \"#rrggbb\""
+                    ))
+                    .at(&locations, &key),
+                );
             }
         }
 
@@ -181,7 +912,12 @@ impl Config {
     }
 }
 
+static CONFIG_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
 fn config_path() -> Option<PathBuf> {
+    if let Some(path) = CONFIG_PATH_OVERRIDE.get() {
+        return Some(path.clone());
+    }
     ProjectDirs::from("io", "termilyon", "termilyon")
         .map(|dirs| dirs.config_dir().join("config.toml"))
 }
@@ -197,254 +933,1070 @@ fn resolve_theme_path(config_path: &PathBuf, theme_file: &str) -> Option<PathBuf
 
 fn main() {
     let args = CliArgs::parse();
+    if let Some(path) = args.config.as_ref() {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+        let _ = CONFIG_PATH_OVERRIDE.set(path);
+    }
+    match args.action {
+        Some(CliCommand::Msg { socket, request }) => {
+            std::process::exit(run_control_client(socket, request));
+        }
+        Some(CliCommand::Theme {
+            command:
+                ThemeCommand::Import {
+                    file,
+                    output,
+                    scheme,
+                },
+        }) => std::process::exit(run_theme_import(
+            &file,
+            output.as_deref(),
+            scheme.as_deref(),
+        )),
+        None => {}
+    }
+    if args.check_config {
+        std::process::exit(run_config_check());
+    }
     let app = gtk::Application::new(
         Some("io.termilyon.app"),
-        gtk::gio::ApplicationFlags::FLAGS_NONE,
+        gtk::gio::ApplicationFlags::HANDLES_COMMAND_LINE,
     );
 
-    app.connect_activate(move |app| build_ui(app, &args));
+    // Every launch forwards its command line to the primary instance, which
+    // either acts on its existing window or opens a new one.
+    let config = Rc::new(RefCell::new(Config::load()));
+    let tab_counter = Rc::new(Cell::new(1));
+    {
+        let config = config.clone();
+        let tab_counter = tab_counter.clone();
+        app.connect_startup(move |app| start_control_server(app, &config, &tab_counter));
+    }
+    app.connect_shutdown(|_| {
+        if let Some(path) = CONTROL_SOCKET.get() {
+            let _ = fs::remove_file(path);
+        }
+    });
+    app.connect_command_line(move |app, command_line| {
+        // Messages go to the terminal that ran this invocation, which may
+        // not be the primary instance's.
+        let args = match CliArgs::try_parse_from(command_line.arguments()) {
+            Ok(args) => args,
+            Err(err) if err.use_stderr() => {
+                command_line.printerr_literal(&err.to_string());
+                return gtk::glib::ExitCode::FAILURE;
+            }
+            Err(err) => {
+                command_line.print_literal(&err.to_string());
+                return gtk::glib::ExitCode::SUCCESS;
+            }
+        };
+        let cwd = command_line.cwd();
+        if let Err(err) = check_command_line(&args, cwd.as_deref(), &config.borrow()) {
+            command_line.printerr_literal(&format!("termilyon: {err}\n"));
+            return gtk::glib::ExitCode::FAILURE;
+        }
+        handle_command_line(app, &args, cwd, &config, &tab_counter);
+        gtk::glib::ExitCode::SUCCESS
+    });
     app.run();
 }
 
-fn build_ui(app: &gtk::Application, args: &CliArgs) {
-    let config = Rc::new(RefCell::new(Config::load()));
-    let tab_counter = Rc::new(Cell::new(1));
+// Arguments that only the primary instance can judge, checked before any
+// window is touched.
+fn check_command_line(args: &CliArgs, cwd: Option<&Path>, config: &Config) -> Result<(), String> {
+    // The config is loaded once by the primary instance; a different one
+    // cannot be applied to a single window.
+    if let Some(path) = args.config.as_ref() {
+        let path = match cwd {
+            Some(cwd) => cwd.join(path),
+            None => std::path::absolute(path).unwrap_or_else(|_| path.clone()),
+        };
+        let current = config_path();
+        if current.as_deref() != Some(path.as_path()) {
+            let current = current
+                .map(|current| current.display().to_string())
+                .unwrap_or_else(|| "the default config".to_string());
+            return Err(format!(
+                "--config {}: the running instance uses {current}; quit it to switch configs",
+                path.display()
+            ));
+        }
+    }
+    if let Some(value) = args.geometry.as_deref()
+        && parse_geometry(value).is_none()
+    {
+        return Err(format!("invalid geometry: {value} (expected COLSxROWS)"));
+    }
+    if let Some(name) = args.layout.as_deref()
+        && find_layout(config, name).is_none()
+    {
+        return Err(format!("layout not found: {name}"));
+    }
+    if let Some(name) = args.profile.as_deref()
+        && !config.profiles.contains_key(name)
+    {
+        let hint = closest_name(name, config.profiles.keys().map(String::as_str))
+            .map(|name| format!("; did you mean `{name}`?"))
+            .unwrap_or_default();
+        return Err(format!("profile not found: {name}{hint}"));
+    }
+    Ok(())
+}
+
+fn handle_command_line(
+    app: &gtk::Application,
+    args: &CliArgs,
+    cwd: Option<PathBuf>,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+) {
+    if let Some(window) = dropdown_window(app).filter(|_| args.toggle) {
+        toggle_dropdown_window(app, &window, &config.borrow());
+        return;
+    }
 
+    let remote_action = args.new_tab || args.split.is_some() || args.run.is_some();
+    let window = app
+        .active_window()
+        .and_then(|window| window.downcast::<gtk::ApplicationWindow>().ok());
+    let Some(window) = window.filter(|_| remote_action) else {
+        build_ui(app, args, cwd.as_deref(), config, counter);
+        return;
+    };
+    let Some(notebook) = window_notebook(window.upcast_ref()) else { return };
+
+    let options = startup_spawn_options(args, cwd.as_deref(), &config.borrow());
+    if let Some(direction) = args.split.as_deref() {
+        let Some(orientation) = parse_split_direction(direction) else { return };
+        split_current_tab(&notebook, config, orientation, Some(&options));
+    } else {
+        create_tab(&notebook, config, counter, &options);
+        if let Some(title) = args.title.as_deref() {
+            set_current_tab_title(&notebook, title);
+        }
+    }
+    window.present();
+}
+
+fn build_ui(
+    app: &gtk::Application,
+    args: &CliArgs,
+    cwd: Option<&Path>,
+    config: &Rc<RefCell<Config>>,
+    tab_counter: &Rc<Cell<u32>>,
+) {
+    let first_window = app.windows().is_empty();
     if let Some(path) = args.theme_file.as_ref() {
-        config.borrow_mut().theme_file = Some(path.clone());
+        config.borrow_mut().theme = Some(ThemeSource::File(path.clone()));
+    }
+
+    let theme = config.borrow().theme.as_ref().and_then(theme_from_source);
+    let save_on_exit = args.session.is_some() || config.borrow().restore_session;
+    let restore = args.session.is_some() || (first_window && config.borrow().restore_session);
+    let session_name = args
+        .session
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SESSION.to_string());
+    let (window, notebook) = create_window(
+        app,
+        config,
+        tab_counter,
+        args.theme_file.clone(),
+        session_name.clone(),
+    );
+    if first_window {
+        watch_config_files(app, config, args.theme_file.clone());
+        let config = config.borrow();
+        let heading = format!("Problems in {}:", config_problem_files(&config));
+        show_config_problems(window.upcast_ref(), &heading, &config.problems);
+    }
+    let dropdown = args.dropdown || args.toggle || (first_window && config.borrow().dropdown);
+    if dropdown {
+        configure_dropdown_window(app, &window, &config.borrow());
+    }
+    // Set after the drop-down title so an explicit --title wins.
+    if let Some(title) = args.title.as_deref() {
+        window.set_title(Some(title));
     }
+    let startup_options = startup_spawn_options(args, cwd, &config.borrow());
+    let explicit_startup = startup_options.command.is_some() || startup_options.cwd.is_some();
+    let layout = args
+        .layout
+        .as_ref()
+        .and_then(|name| find_layout(&config.borrow(), name));
+    let restored = if let Some(layout) = layout {
+        open_layout(&notebook, config, tab_counter, &layout)
+    } else if restore && !explicit_startup {
+        load_session(&session_name).and_then(|session| {
+            let mut windows = session.into_windows().into_iter();
+            let first = restore_session(&notebook, config, tab_counter, &windows.next()?);
+            for session_window in windows {
+                restore_session_window(
+                    app,
+                    config,
+                    tab_counter,
+                    args,
+                    &session_name,
+                    &session_window,
+                );
+            }
+            first
+        })
+    } else {
+        None
+    };
+    let first_terminal = restored.unwrap_or_else(|| {
+        let terminal = create_tab(&notebook, config, tab_counter, &startup_options);
+        if let Some(title) = args.title.as_deref() {
+            set_current_tab_title(&notebook, title);
+        }
+        terminal
+    });
+    apply_tab_styles(&notebook, theme.as_ref(), Some(&first_terminal));
+    if !dropdown {
+        let geometry = args.geometry.as_deref().and_then(parse_geometry);
+        apply_initial_window_size(&window, &first_terminal, &config.borrow(), geometry);
+    }
+
+    if save_on_exit {
+        SAVED_SESSIONS.with_borrow_mut(|sessions| sessions.insert(session_name));
+    }
+
+    window.present();
+}
 
+fn create_window(
+    app: &gtk::Application,
+    config: &Rc<RefCell<Config>>,
+    tab_counter: &Rc<Cell<u32>>,
+    theme_override: Option<PathBuf>,
+    session_name: String,
+) -> (gtk::ApplicationWindow, gtk::Notebook) {
     let window = gtk::ApplicationWindow::new(app);
     window.set_title(Some("Termilyon"));
     window.set_default_size(1000, 700);
-    window.set_decorated(false);
+    let decorations = config.borrow().decorations;
+    window.set_decorated(decorations != Decorations::None);
 
     let notebook = gtk::Notebook::new();
     notebook.set_hexpand(true);
     notebook.set_vexpand(true);
     notebook.add_css_class("terminal-tabs");
     notebook.set_tab_pos(config.borrow().tab_bar_position);
+    notebook.set_group_name(Some(TAB_GROUP_NAME));
     notebook.connect_switch_page(|notebook, _, page| {
         focus_terminal_in_page(notebook, page);
     });
-    window.set_child(Some(&notebook));
-
-    let theme = config
-        .borrow()
-        .theme_file
-        .as_ref()
-        .and_then(|path| theme_from_file(path));
-    let first_terminal = create_tab(&notebook, &config, &tab_counter);
-    apply_tab_styles(&notebook, theme.as_ref(), Some(&first_terminal));
+    let overlay = gtk::Overlay::new();
+    overlay.set_child(Some(&notebook));
+    let chord_indicator = gtk::Label::new(None);
+    chord_indicator.add_css_class("osd");
+    chord_indicator.set_halign(gtk::Align::End);
+    chord_indicator.set_valign(gtk::Align::End);
+    chord_indicator.set_margin_end(12);
+    chord_indicator.set_margin_bottom(12);
+    chord_indicator.set_can_target(false);
+    chord_indicator.set_visible(false);
+    overlay.add_overlay(&chord_indicator);
+    window.set_child(Some(&overlay));
+
+    WINDOW_SESSIONS.with_borrow_mut(|sessions| sessions.insert(window.id(), session_name.clone()));
+    {
+        let app = app.clone();
+        let session_name = session_name.clone();
+        window.connect_close_request(move |window| {
+            if window.widget_name() != DROPDOWN_WINDOW_NAME {
+                save_window_state(window.upcast_ref());
+            }
+            // The closing window is still part of the session, so closing
+            // one of several windows keeps the others in it.
+            if SAVED_SESSIONS.with_borrow(|sessions| sessions.contains(&session_name)) {
+                save_session(&session_name, &capture_session(&app, &session_name));
+            }
+            WINDOW_SESSIONS.with_borrow_mut(|sessions| sessions.remove(&window.id()));
+            gtk::glib::Propagation::Proceed
+        });
+    }
 
-    let controller = gtk::EventControllerKey::new();
-    controller.set_propagation_phase(gtk::PropagationPhase::Capture);
-    let notebook_clone = notebook.clone();
-    let config_clone = config.clone();
-    let counter_clone = tab_counter.clone();
-    let window_clone = window.clone();
-    let theme_override = args.theme_file.clone();
-    controller.connect_key_pressed(move |_, key, _, state| {
-        if config_clone.borrow().keybindings.new_tab.matches(key, state) {
-            create_tab(&notebook_clone, &config_clone, &counter_clone);
-            return gtk::glib::Propagation::Stop;
-        }
+    let context = WindowContext {
+        app: app.clone(),
+        window: window.clone(),
+        notebook: notebook.clone(),
+        config: config.clone(),
+        counter: tab_counter.clone(),
+        theme_override: theme_override.clone(),
+        session_name: session_name.clone(),
+    };
 
-        if config_clone.borrow().keybindings.close_tab.matches(key, state) {
-            close_current_tab(&notebook_clone, &config_clone, &counter_clone);
-            return gtk::glib::Propagation::Stop;
+    match decorations {
+        Decorations::HeaderBar => {
+            install_window_actions(&context);
+            install_header_bar(&window, &notebook);
         }
+        Decorations::None => install_edge_resizing(&window),
+        Decorations::System => {}
+    }
 
-        if config_clone.borrow().keybindings.rename_tab.matches(key, state) {
-            rename_current_tab(&window_clone, &notebook_clone);
-            return gtk::glib::Propagation::Stop;
-        }
+    // Dropping a tab outside of every window moves it into a new one.
+    {
+        let app = app.clone();
+        let config = config.clone();
+        let tab_counter = tab_counter.clone();
+        let theme_override = theme_override.clone();
+        let session_name = session_name.clone();
+        notebook.connect_create_window(move |_, _| {
+            let (window, notebook) = create_window(
+                &app,
+                &config,
+                &tab_counter,
+                theme_override.clone(),
+                session_name.clone(),
+            );
+            window.present();
+            Some(notebook)
+        });
+    }
 
-        if config_clone.borrow().keybindings.close_panel.matches(key, state) {
-            if let Some(terminal) = focused_terminal(window_clone.upcast_ref()) {
-                terminal.feed_child(b"\x04");
+    // A window whose last tab was dragged away closes. Tabs closed by the
+    // user are replaced synchronously, so only check once that has settled.
+    notebook.connect_page_removed(|notebook, _, _| {
+        let notebook = notebook.clone();
+        gtk::glib::idle_add_local_once(move || {
+            if notebook.n_pages() > 0 {
+                return;
             }
-            return gtk::glib::Propagation::Stop;
-        }
-
-        if config_clone
-            .borrow()
-            .keybindings
-            .split_vertical
-            .matches(key, state)
-        {
-            split_current_tab(&notebook_clone, &config_clone, gtk::Orientation::Horizontal);
-            return gtk::glib::Propagation::Stop;
-        }
+            if let Some(window) = find_root_window(notebook.upcast_ref()) {
+                window.close();
+            }
+        });
+    });
 
-        if config_clone
-            .borrow()
-            .keybindings
-            .split_horizontal
-            .matches(key, state)
-        {
-            split_current_tab(&notebook_clone, &config_clone, gtk::Orientation::Vertical);
-            return gtk::glib::Propagation::Stop;
+    let controller = gtk::EventControllerKey::new();
+    controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+    let chord = Rc::new(PendingChord {
+        keys: RefCell::new(Vec::new()),
+        timeout: RefCell::new(None),
+        indicator: chord_indicator,
+    });
+    controller.connect_key_pressed(move |_, key, _, state| {
+        // Holding a modifier on its way to the next chord key is not a key
+        // of its own.
+        if is_modifier_key(key) {
+            return gtk::glib::Propagation::Proceed;
         }
+        let mut keys = chord.take();
+        let pending = !keys.is_empty();
+        keys.push(KeyBinding {
+            key,
+            modifiers: state,
+        });
 
-        if config_clone.borrow().keybindings.copy.matches(key, state) {
-            if let Some(terminal) = focused_terminal(window_clone.upcast_ref()) {
-                terminal.copy_clipboard_format(Format::Text);
-                return gtk::glib::Propagation::Stop;
+        let found = context.config.borrow().keybindings.lookup(&keys);
+        match found {
+            ChordMatch::Action(action) => {
+                if run_action(&context, &action) || pending {
+                    gtk::glib::Propagation::Stop
+                } else {
+                    gtk::glib::Propagation::Proceed
+                }
             }
-        }
-
-        if config_clone.borrow().keybindings.paste.matches(key, state) {
-            if let Some(terminal) = focused_terminal(window_clone.upcast_ref()) {
-                terminal.paste_clipboard();
-                return gtk::glib::Propagation::Stop;
+            ChordMatch::Prefix => {
+                let timeout = context.config.borrow().chord_timeout_ms;
+                chord.start(keys, timeout);
+                gtk::glib::Propagation::Stop
             }
+            // Like tmux, a key that completes no chord is dropped.
+            ChordMatch::None if pending => gtk::glib::Propagation::Stop,
+            ChordMatch::None => gtk::glib::Propagation::Proceed,
         }
+    });
+    window.add_controller(controller);
 
-        if config_clone.borrow().keybindings.focus_left.matches(key, state) {
-            if focus_adjacent_split(window_clone.upcast_ref(), FocusDirection::Left) {
-                return gtk::glib::Propagation::Stop;
-            }
+    (window, notebook)
+}
+
+fn open_new_window(
+    app: &gtk::Application,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+    theme_override: Option<PathBuf>,
+    session_name: String,
+    source: Option<&Terminal>,
+) {
+    let options = new_terminal_options(&config.borrow(), source);
+    let (window, notebook) = create_window(app, config, counter, theme_override, session_name);
+    let terminal = create_tab(&notebook, config, counter, &options);
+    let theme = config.borrow().theme.as_ref().and_then(theme_from_source);
+    apply_tab_styles(&notebook, theme.as_ref(), Some(&terminal));
+    apply_initial_window_size(&window, &terminal, &config.borrow(), None);
+    window.present();
+}
+
+// An explicit --geometry wins over the size remembered from the last run,
+// which in turn wins over `columns`/`rows` from the config.
+fn apply_initial_window_size(
+    window: &gtk::ApplicationWindow,
+    terminal: &Terminal,
+    config: &Config,
+    geometry: Option<(u32, u32)>,
+) {
+    if let Some((columns, rows)) = geometry {
+        size_window_to_grid(window, terminal, columns, rows);
+        return;
+    }
+    if let Some(state) = load_window_state().filter(|_| config.remember_window_state) {
+        window.set_default_size(state.width, state.height);
+        if state.maximized {
+            window.maximize();
         }
+        if state.fullscreen {
+            window.fullscreen();
+        }
+        return;
+    }
+    if let (Some(columns), Some(rows)) = (config.columns, config.rows) {
+        size_window_to_grid(window, terminal, columns, rows);
+    }
+}
 
-        if config_clone.borrow().keybindings.focus_right.matches(key, state) {
-            if focus_adjacent_split(window_clone.upcast_ref(), FocusDirection::Right) {
-                return gtk::glib::Propagation::Stop;
+// Without a default size the window takes its natural size, which VTE
+// derives from the grid size. The ScrolledWindow around the terminal only
+// passes that on while natural size propagation is enabled, which is turned
+// off again once the window is shown so it does not follow later changes.
+fn size_window_to_grid(
+    window: &gtk::ApplicationWindow,
+    terminal: &Terminal,
+    columns: u32,
+    rows: u32,
+) {
+    window.set_default_size(-1, -1);
+    terminal.set_size(columns.into(), rows.into());
+    let Some(scrolled) = terminal.parent().and_downcast::<gtk::ScrolledWindow>() else { return };
+    scrolled.set_propagate_natural_width(true);
+    scrolled.set_propagate_natural_height(true);
+
+    let handler = Rc::new(RefCell::new(None));
+    let handler_id = {
+        let handler = handler.clone();
+        window.connect_map(move |window| {
+            if let Some(handler_id) = handler.borrow_mut().take() {
+                window.disconnect(handler_id);
             }
+            let scrolled = scrolled.clone();
+            gtk::glib::idle_add_local_once(move || {
+                scrolled.set_propagate_natural_width(false);
+                scrolled.set_propagate_natural_height(false);
+            });
+        })
+    };
+    *handler.borrow_mut() = Some(handler_id);
+}
+
+fn parse_geometry(value: &str) -> Option<(u32, u32)> {
+    let (columns, rows) = value.trim().split_once(['x', 'X'])?;
+    let columns = columns.trim().parse().ok().filter(|columns| *columns > 0)?;
+    let rows = rows.trim().parse().ok().filter(|rows| *rows > 0)?;
+    Some((columns, rows))
+}
+
+fn window_state_path() -> Option<PathBuf> {
+    ProjectDirs::from("io", "termilyon", "termilyon").map(|dirs| {
+        dirs.state_dir()
+            .unwrap_or_else(|| dirs.data_local_dir())
+            .join("window-state.toml")
+    })
+}
+
+fn load_window_state() -> Option<WindowState> {
+    let path = window_state_path()?;
+    let contents = fs::read_to_string(&path).ok()?;
+    match toml::from_str::<WindowState>(&contents) {
+        Ok(state) => Some(state),
+        Err(err) => {
+            eprintln!("window state parse failed: {}: {err}", path.display());
+            None
         }
+    }
+}
 
-        if config_clone.borrow().keybindings.focus_up.matches(key, state) {
-            if focus_adjacent_split(window_clone.upcast_ref(), FocusDirection::Up) {
-                return gtk::glib::Propagation::Stop;
+// The default size tracks the unmaximized size, so it stays useful when the
+// window is closed while maximized or fullscreen.
+fn save_window_state(window: &gtk::Window) {
+    let Some(path) = window_state_path() else { return };
+    let (width, height) = window.default_size();
+    let state = WindowState {
+        width: if width > 0 { width } else { window.width() },
+        height: if height > 0 { height } else { window.height() },
+        maximized: window.is_maximized(),
+        fullscreen: window.is_fullscreen(),
+    };
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(content) = toml::to_string(&state) {
+        let _ = fs::write(&path, content);
+    }
+}
+
+// Window actions back the header bar menu.
+fn install_window_actions(context: &WindowContext) {
+    let actions = [
+        ("new-tab", Action::NewTab),
+        ("new-window", Action::NewWindow),
+        ("command-palette", Action::CommandPalette),
+        ("split-right", Action::SplitVertical),
+        ("split-down", Action::SplitHorizontal),
+        ("search", Action::Search),
+        ("show-keybindings", Action::ShowKeybindings),
+        ("preferences", Action::Preferences),
+        ("theme-picker", Action::ThemePicker),
+        ("reload-config", Action::ReloadConfig),
+    ];
+    for (name, action) in actions {
+        let simple_action = gtk::gio::SimpleAction::new(name, None);
+        let context_clone = context.clone();
+        simple_action.connect_activate(move |_, _| {
+            run_action(&context_clone, &action);
+        });
+        context.window.add_action(&simple_action);
+    }
+}
+
+// Returns false when the action does not apply (e.g. no focused terminal),
+// so the key press reaches the terminal instead.
+fn run_action(context: &WindowContext, action: &Action) -> bool {
+    let window = context.window.upcast_ref::<gtk::Window>();
+    let notebook = &context.notebook;
+    let config = &context.config;
+    let counter = &context.counter;
+    let focused = focused_terminal(window);
+
+    match action {
+        Action::NewTab => {
+            let options = new_terminal_options(&config.borrow(), focused.as_ref());
+            create_tab(notebook, config, counter, &options);
+        }
+        Action::NewWindow => open_new_window(
+            &context.app,
+            config,
+            counter,
+            context.theme_override.clone(),
+            context.session_name.clone(),
+            focused.as_ref(),
+        ),
+        Action::CloseTab => close_current_tab(notebook, config, counter),
+        Action::RenameTab => rename_current_tab(&context.window, notebook),
+        Action::ClosePanel => {
+            if let Some(terminal) = focused {
+                terminal.feed_child(b"\x04");
             }
         }
-
-        if config_clone.borrow().keybindings.focus_down.matches(key, state) {
-            if focus_adjacent_split(window_clone.upcast_ref(), FocusDirection::Down) {
-                return gtk::glib::Propagation::Stop;
+        Action::SplitVertical => {
+            split_current_tab(notebook, config, gtk::Orientation::Horizontal, None)
+        }
+        Action::SplitHorizontal => {
+            split_current_tab(notebook, config, gtk::Orientation::Vertical, None)
+        }
+        Action::Copy => {
+            let Some(terminal) = focused else { return false };
+            terminal.copy_clipboard_format(Format::Text);
+        }
+        Action::Paste => {
+            let Some(terminal) = focused else { return false };
+            terminal.paste_clipboard();
+        }
+        Action::ReloadConfig => reload_config_and_theme(
+            config,
+            Config::load(),
+            &app_notebooks(&context.app),
+            context.theme_override.as_ref(),
+        ),
+        Action::ShowKeybindings => show_keybindings_dialog(context),
+        Action::SshManager => show_ssh_manager_dialog(&context.window, notebook),
+        Action::PasswordManager => {
+            let secret = config.borrow().secret.clone();
+            show_password_manager_dialog(&context.window, notebook, secret);
+        }
+        Action::SaveSession => save_session(
+            &context.session_name,
+            &capture_session(&context.app, &context.session_name),
+        ),
+        Action::LayoutPicker => {
+            show_layout_picker_dialog(&context.window, notebook, config, counter)
+        }
+        Action::ProfilePicker => {
+            show_profile_picker_dialog(&context.window, notebook, config, counter)
+        }
+        Action::NewTabWithProfile(profile) => {
+            if !config.borrow().profiles.contains_key(profile) {
+                return false;
             }
+            let options =
+                profile_terminal_options(&config.borrow(), focused.as_ref(), Some(profile.clone()));
+            create_tab(notebook, config, counter, &options);
         }
-
-        if config_clone
-            .borrow()
-            .keybindings
-            .reload_config
-            .matches(key, state)
-        {
-            reload_config_and_theme(
-                &config_clone,
-                &notebook_clone,
-                theme_override.as_ref(),
-            );
-            return gtk::glib::Propagation::Stop;
+        Action::SwitchTabProfile | Action::SwitchPaneProfile => {
+            let Some(terminal) = focused else { return false };
+            let whole_tab = *action == Action::SwitchTabProfile;
+            show_switch_profile_dialog(&context.window, &terminal, config, whole_tab);
         }
-
-        if config_clone
-            .borrow()
-            .keybindings
-            .show_keybindings
-            .matches(key, state)
-        {
-            let config_ref = config_clone.borrow();
-            show_keybindings_dialog(&window_clone, &config_ref);
-            return gtk::glib::Propagation::Stop;
+        Action::CommandPalette => show_command_palette(context),
+        Action::Preferences => show_preferences_dialog(context),
+        Action::ThemePicker => show_theme_gallery(context),
+        Action::Search => {
+            if let Some(terminal) = focused {
+                show_search_bar(&terminal);
+            }
         }
-
-        if config_clone
-            .borrow()
-            .keybindings
-            .ssh_manager
-            .matches(key, state)
-        {
-            show_ssh_manager_dialog(&window_clone, &notebook_clone);
-            return gtk::glib::Propagation::Stop;
+        Action::ToggleFullscreen => context
+            .window
+            .set_fullscreened(!context.window.is_fullscreen()),
+        Action::Focus(direction) => return focus_adjacent_split(window, *direction),
+        Action::SelectTab(number) => {
+            let target = number.saturating_sub(1);
+            if target >= notebook.n_pages() {
+                return false;
+            }
+            notebook.set_current_page(Some(target));
+            focus_terminal_in_page(notebook, target);
         }
-
-        if config_clone
-            .borrow()
-            .keybindings
-            .password_manager
-            .matches(key, state)
-        {
-            let secret = config_clone.borrow().secret.clone();
-            show_password_manager_dialog(&window_clone, &notebook_clone, secret);
-            return gtk::glib::Propagation::Stop;
+        Action::NextTab | Action::PreviousTab => {
+            let pages = notebook.n_pages();
+            let Some(current) = notebook.current_page().filter(|_| pages > 1) else { return false };
+            let target = if *action == Action::NextTab {
+                (current + 1) % pages
+            } else {
+                (current + pages - 1) % pages
+            };
+            notebook.set_current_page(Some(target));
         }
-
-        for (index, binding) in config_clone
-            .borrow()
-            .keybindings
-            .tab_switch
-            .iter()
-            .enumerate()
-        {
-            if binding.matches(key, state) {
-                let target = index as u32;
-                if target < notebook_clone.n_pages() {
-                    notebook_clone.set_current_page(Some(target));
-                    focus_terminal_in_page(&notebook_clone, target);
-                    return gtk::glib::Propagation::Stop;
+        Action::SendText(text) => {
+            let Some(terminal) = focused else { return false };
+            terminal.feed_child(text);
+        }
+        Action::RunCommand(command) => {
+            let mut options = new_terminal_options(&config.borrow(), focused.as_ref());
+            let shell = config.borrow().shell.clone();
+            options.command = Some(vec![shell, "-c".to_string(), command.clone()]);
+            create_tab(notebook, config, counter, &options);
+        }
+        Action::ScrollLines(_)
+        | Action::ScrollPages(_)
+        | Action::ScrollToTop
+        | Action::ScrollToBottom => {
+            let Some(adjustment) = focused.and_then(|terminal| terminal.vadjustment()) else {
+                return false;
+            };
+            let value = match action {
+                Action::ScrollLines(lines) => adjustment.value() + f64::from(*lines),
+                Action::ScrollPages(pages) => {
+                    adjustment.value() + f64::from(*pages) * adjustment.page_size()
                 }
-                break;
-            }
+                Action::ScrollToTop => adjustment.lower(),
+                _ => adjustment.upper(),
+            };
+            adjustment.set_value(value.clamp(
+                adjustment.lower(),
+                adjustment.upper() - adjustment.page_size(),
+            ));
+        }
+        Action::IncreaseFontSize | Action::DecreaseFontSize | Action::ResetFontSize => {
+            let Some(terminal) = focused else { return false };
+            let scale = match action {
+                Action::IncreaseFontSize => terminal.font_scale() * 1.1,
+                Action::DecreaseFontSize => terminal.font_scale() / 1.1,
+                _ => 1.0,
+            };
+            terminal.set_font_scale(scale.clamp(0.25, 4.0));
         }
+    }
+    true
+}
 
-        gtk::glib::Propagation::Proceed
-    });
-    window.add_controller(controller);
+// The header bar sits at the end of the notebook's tab strip, so tabs and
+// window controls share one row. The window's own titlebar is replaced by an
+// invisible widget to keep client-side decorations without a second bar.
+fn install_header_bar(window: &gtk::ApplicationWindow, notebook: &gtk::Notebook) {
+    notebook.set_tab_pos(gtk::PositionType::Top);
+
+    let titlebar = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    titlebar.set_visible(false);
+    window.set_titlebar(Some(&titlebar));
+
+    let header = gtk::HeaderBar::new();
+    header.add_css_class("terminal-header");
+    header.set_title_widget(Some(&gtk::Box::new(gtk::Orientation::Horizontal, 0)));
+
+    let new_tab_button = gtk::Button::from_icon_name("tab-new-symbolic");
+    new_tab_button.set_tooltip_text(Some("New Tab"));
+    new_tab_button.set_focusable(false);
+    new_tab_button.set_action_name(Some("win.new-tab"));
+    header.pack_start(&new_tab_button);
+
+    let menu = gtk::gio::Menu::new();
+    let windows = gtk::gio::Menu::new();
+    windows.append(Some("New Tab"), Some("win.new-tab"));
+    windows.append(Some("New Window"), Some("win.new-window"));
+    windows.append(Some("Command Palette..."), Some("win.command-palette"));
+    let panes = gtk::gio::Menu::new();
+    panes.append(Some("Split Right"), Some("win.split-right"));
+    panes.append(Some("Split Down"), Some("win.split-down"));
+    panes.append(Some("Search..."), Some("win.search"));
+    let settings = gtk::gio::Menu::new();
+    settings.append(Some("Preferences..."), Some("win.preferences"));
+    settings.append(Some("Themes..."), Some("win.theme-picker"));
+    settings.append(Some("Keybindings"), Some("win.show-keybindings"));
+    settings.append(Some("Reload Configuration"), Some("win.reload-config"));
+    menu.append_section(None, &windows);
+    menu.append_section(None, &panes);
+    menu.append_section(None, &settings);
+
+    let menu_button = gtk::MenuButton::new();
+    menu_button.set_icon_name("open-menu-symbolic");
+    menu_button.set_focusable(false);
+    menu_button.set_menu_model(Some(&menu));
+    header.pack_end(&menu_button);
+
+    notebook.set_action_widget(&header, gtk::PackType::End);
+}
 
-    window.present();
+// Undecorated windows have no resize borders, so start an interactive resize
+// when the pointer is pressed close to an edge.
+fn install_edge_resizing(window: &gtk::ApplicationWindow) {
+    // The widget under the pointer whose cursor was swapped for a resize one.
+    let overridden = Rc::new(RefCell::new(None::<(gtk::Widget, Option<gdk::Cursor>)>));
+
+    let motion = gtk::EventControllerMotion::new();
+    motion.set_propagation_phase(gtk::PropagationPhase::Capture);
+    {
+        let window = window.clone();
+        motion.connect_motion(move |_, x, y| {
+            if let Some((widget, cursor)) = overridden.borrow_mut().take() {
+                widget.set_cursor(cursor.as_ref());
+            }
+            let Some(edge) = resize_edge(window.upcast_ref(), x, y) else { return };
+            let Some(widget) = window.pick(x, y, gtk::PickFlags::DEFAULT) else { return };
+            let cursor = widget.cursor();
+            widget.set_cursor_from_name(Some(resize_cursor_name(edge)));
+            *overridden.borrow_mut() = Some((widget, cursor));
+        });
+    }
+    window.add_controller(motion);
+
+    let click = gtk::GestureClick::new();
+    click.set_button(gdk::BUTTON_PRIMARY);
+    click.set_propagation_phase(gtk::PropagationPhase::Capture);
+    {
+        let window = window.clone();
+        click.connect_pressed(move |gesture, _, x, y| {
+            let Some(edge) = resize_edge(window.upcast_ref(), x, y) else { return };
+            let Some(toplevel) = window.surface().and_downcast::<gdk::Toplevel>() else { return };
+            gesture.set_state(gtk::EventSequenceState::Claimed);
+            toplevel.begin_resize(
+                edge,
+                gesture.device().as_ref(),
+                gdk::BUTTON_PRIMARY as i32,
+                x,
+                y,
+                gesture.current_event_time(),
+            );
+        });
+    }
+    window.add_controller(click);
+}
+
+fn resize_edge(window: &gtk::Window, x: f64, y: f64) -> Option<gdk::SurfaceEdge> {
+    if window.is_maximized() || window.is_fullscreen() {
+        return None;
+    }
+    let left = x < RESIZE_BORDER;
+    let right = x > f64::from(window.width()) - RESIZE_BORDER;
+    let top = y < RESIZE_BORDER;
+    let bottom = y > f64::from(window.height()) - RESIZE_BORDER;
+    match (left, right, top, bottom) {
+        (true, _, true, _) => Some(gdk::SurfaceEdge::NorthWest),
+        (_, true, true, _) => Some(gdk::SurfaceEdge::NorthEast),
+        (true, _, _, true) => Some(gdk::SurfaceEdge::SouthWest),
+        (_, true, _, true) => Some(gdk::SurfaceEdge::SouthEast),
+        (true, _, _, _) => Some(gdk::SurfaceEdge::West),
+        (_, true, _, _) => Some(gdk::SurfaceEdge::East),
+        (_, _, true, _) => Some(gdk::SurfaceEdge::North),
+        (_, _, _, true) => Some(gdk::SurfaceEdge::South),
+        _ => None,
+    }
+}
+
+fn resize_cursor_name(edge: gdk::SurfaceEdge) -> &'static str {
+    match edge {
+        gdk::SurfaceEdge::NorthWest => "nw-resize",
+        gdk::SurfaceEdge::NorthEast => "ne-resize",
+        gdk::SurfaceEdge::SouthWest => "sw-resize",
+        gdk::SurfaceEdge::SouthEast => "se-resize",
+        gdk::SurfaceEdge::West => "w-resize",
+        gdk::SurfaceEdge::East => "e-resize",
+        gdk::SurfaceEdge::North => "n-resize",
+        _ => "s-resize",
+    }
+}
+
+// GTK 4 cannot position toplevels itself. Wayland compositors with the
+// layer shell protocol anchor the window to the top edge; on X11 it is moved
+// there each time it is mapped.
+fn configure_dropdown_window(
+    app: &gtk::Application,
+    window: &gtk::ApplicationWindow,
+    config: &Config,
+) {
+    window.set_widget_name(DROPDOWN_WINDOW_NAME);
+    window.set_title(Some("Termilyon Drop-down"));
+    window.set_decorated(false);
+    if !init_dropdown_layer_shell(window) {
+        let app = app.clone();
+        window.connect_map(move |window| {
+            if let Some(monitor) = dropdown_monitor(&app, window.upcast_ref()) {
+                move_to_top_of_monitor(window.upcast_ref(), &monitor);
+            }
+        });
+    }
+    size_dropdown_window(app, window.upcast_ref(), config);
+
+    if config.dropdown_hide_on_focus_loss {
+        // Dialogs opened from the window take focus too, so only hide once
+        // no window of ours is active.
+        let app = app.clone();
+        window.connect_is_active_notify(move |window| {
+            if window.is_active() {
+                return;
+            }
+            let window = window.clone();
+            let app = app.clone();
+            gtk::glib::idle_add_local_once(move || {
+                if app.windows().iter().all(|window| !window.is_active()) {
+                    window.set_visible(false);
+                }
+            });
+        });
+    }
+}
+
+// Returns false when the compositor has no layer shell or the
+// `layer-shell` feature is off.
+#[cfg(feature = "layer-shell")]
+fn init_dropdown_layer_shell(window: &gtk::ApplicationWindow) -> bool {
+    if !gtk4_layer_shell::is_supported() {
+        return false;
+    }
+    window.init_layer_shell();
+    window.set_layer(gtk4_layer_shell::Layer::Top);
+    window.set_anchor(gtk4_layer_shell::Edge::Top, true);
+    window.set_keyboard_mode(gtk4_layer_shell::KeyboardMode::OnDemand);
+    true
+}
+
+#[cfg(not(feature = "layer-shell"))]
+fn init_dropdown_layer_shell(_window: &gtk::ApplicationWindow) -> bool {
+    false
+}
+
+fn dropdown_window(app: &gtk::Application) -> Option<gtk::Window> {
+    app.windows()
+        .into_iter()
+        .find(|window| window.widget_name() == DROPDOWN_WINDOW_NAME)
+}
+
+// The monitor the user is on: the one under the pointer if it is over one of
+// our windows, else the one of the focused window.
+fn dropdown_monitor(app: &gtk::Application, window: &gtk::Window) -> Option<gdk::Monitor> {
+    let display = gdk::Display::default()?;
+    let pointer_surface = display
+        .default_seat()
+        .and_then(|seat| seat.pointer())
+        .and_then(|pointer| pointer.surface_at_position().0);
+    let active_surface = app
+        .active_window()
+        .filter(|active| active != window)
+        .and_then(|active| active.surface());
+    pointer_surface
+        .or(active_surface)
+        .or_else(|| window.surface())
+        .and_then(|surface| display.monitor_at_surface(&surface))
+        .or_else(|| display.monitors().item(0).and_downcast::<gdk::Monitor>())
+}
+
+fn size_dropdown_window(app: &gtk::Application, window: &gtk::Window, config: &Config) {
+    let Some(monitor) = dropdown_monitor(app, window) else { return };
+    let geometry = monitor.geometry();
+    window.set_default_size(
+        geometry.width() * config.dropdown_width / 100,
+        geometry.height() * config.dropdown_height / 100,
+    );
+    #[cfg(feature = "layer-shell")]
+    if window.is_layer_window() {
+        window.set_monitor(Some(&monitor));
+    }
+}
+
+// Centers an X11 window horizontally at the top edge of `monitor`. Does
+// nothing on other backends or without the `x11` feature.
+#[cfg(feature = "x11")]
+fn move_to_top_of_monitor(window: &gtk::Window, monitor: &gdk::Monitor) {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{ConfigureWindowAux, ConnectionExt};
+
+    let Some(surface) = window.surface().and_downcast::<gdk4_x11::X11Surface>() else {
+        return;
+    };
+    let Ok((connection, _)) = x11rb::connect(None) else { return };
+    let geometry = monitor.geometry();
+    let (width, _) = window.default_size();
+    let position = ConfigureWindowAux::new()
+        .x(geometry.x() + (geometry.width() - width).max(0) / 2)
+        .y(geometry.y());
+    let moved = connection
+        .configure_window(surface.xid() as u32, &position)
+        .is_ok();
+    if moved {
+        let _ = connection.flush();
+    }
+}
+
+#[cfg(not(feature = "x11"))]
+fn move_to_top_of_monitor(_window: &gtk::Window, _monitor: &gdk::Monitor) {}
+
+fn toggle_dropdown_window(app: &gtk::Application, window: &gtk::Window, config: &Config) {
+    if window.is_visible() && window.is_active() {
+        window.set_visible(false);
+    } else {
+        if !window.is_visible() {
+            size_dropdown_window(app, window, config);
+        }
+        window.present();
+        if let Some(terminal) = focused_terminal(window) {
+            terminal.grab_focus();
+        }
+    }
+}
+
+// Relative paths are resolved against the directory the command was launched
+// from, which differs from ours when it came from another instance.
+fn startup_spawn_options(args: &CliArgs, base_dir: Option<&Path>, config: &Config) -> SpawnOptions {
+    let command = if !args.command.is_empty() {
+        Some(args.command.clone())
+    } else {
+        args.run
+            .clone()
+            .map(|command| vec![config.shell.clone(), "-c".to_string(), command])
+    };
+    SpawnOptions {
+        cwd: args.working_directory.as_ref().map(|path| match base_dir {
+            Some(base) => base.join(path),
+            None => std::path::absolute(path).unwrap_or_else(|_| path.clone()),
+        }),
+        command,
+        hold: args.hold,
+        profile: args
+            .profile
+            .clone()
+            .filter(|name| config.profiles.contains_key(name)),
+    }
 }
 
 fn create_tab(
     notebook: &gtk::Notebook,
     config: &Rc<RefCell<Config>>,
     counter: &Rc<Cell<u32>>,
+    options: &SpawnOptions,
 ) -> Terminal {
-    let config_ref = config.borrow();
-    let terminal_widget = create_terminal_widget(&config_ref);
+    let terminal_widget = create_terminal_widget(&config.borrow(), options);
+    append_tab(notebook, config, counter, terminal_widget.scrolled.upcast_ref(), None);
+    terminal_widget.terminal.grab_focus();
+    attach_terminal_handlers(&terminal_widget.terminal, config);
+
+    terminal_widget.terminal.clone()
+}
+
+fn append_tab(
+    notebook: &gtk::Notebook,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+    child: &gtk::Widget,
+    title: Option<&str>,
+) {
     let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
     content.set_hexpand(true);
     content.set_vexpand(true);
-    content.append(&terminal_widget.scrolled);
+    content.append(child);
 
     let tab_index = counter.get();
     counter.set(tab_index + 1);
-    let label_text = format!("{} {}", config_ref.tab_title, tab_index);
+    let label_text = match title {
+        Some(title) => title.to_string(),
+        None => format!("{} {}", config.borrow().tab_title, tab_index),
+    };
     let label = gtk::Label::new(Some(&label_text));
     label.add_css_class("terminal-tab-label");
+    let marker = gtk::Label::new(None);
+    marker.set_widget_name(PROFILE_MARKER_NAME);
+    marker.set_visible(false);
     let tab_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
     tab_box.add_css_class("terminal-tab");
+    tab_box.append(&marker);
     tab_box.append(&label);
 
     let close_button = gtk::Button::from_icon_name("window-close-symbolic");
     close_button.set_focusable(false);
     close_button.add_css_class("flat");
 
-    let notebook_clone = notebook.clone();
+    // Tabs can be dragged to other windows, so look the notebook up when
+    // the button is clicked.
     let content_clone = content.clone();
     let config_clone = Rc::clone(config);
     let counter_clone = Rc::clone(counter);
     close_button.connect_clicked(move |_| {
-        let page = notebook_clone.page_num(&content_clone);
+        let Some(notebook) = find_parent_notebook(content_clone.upcast_ref()) else { return };
+        let page = notebook.page_num(&content_clone);
         if let Some(page) = page {
-            notebook_clone.remove_page(Some(page));
-            focus_previous_tab(&notebook_clone, page);
+            notebook.remove_page(Some(page));
+            focus_previous_tab(&notebook, page);
         }
 
-        if notebook_clone.n_pages() == 0 {
-            create_tab(&notebook_clone, &config_clone, &counter_clone);
+        if notebook.n_pages() == 0 {
+            create_tab(
+                &notebook,
+                &config_clone,
+                &counter_clone,
+                &SpawnOptions::default(),
+            );
         }
     });
 
     tab_box.append(&close_button);
 
-    notebook.append_page(&content, Some(&tab_box));
-    notebook.set_current_page(Some(tab_index - 1));
-    terminal_widget.terminal.grab_focus();
-    attach_font_scroll_handler(&terminal_widget.terminal, config);
+    let tab_click = gtk::GestureClick::new();
+    tab_click.set_button(gdk::BUTTON_SECONDARY);
+    let content_clone = content.clone();
+    let config_clone = Rc::clone(config);
+    let counter_clone = Rc::clone(counter);
+    tab_click.connect_pressed(move |gesture, _, x, y| {
+        gesture.set_state(gtk::EventSequenceState::Claimed);
+        let Some(tab_box) = gesture.widget() else { return };
+        show_tab_context_menu(
+            &tab_box,
+            &content_clone,
+            &config_clone,
+            &counter_clone,
+            x,
+            y,
+        );
+    });
+    tab_box.add_controller(tab_click);
 
-    terminal_widget.terminal.clone()
+    let page = notebook.append_page(&content, Some(&tab_box));
+    notebook.set_tab_reorderable(&content, true);
+    notebook.set_tab_detachable(&content, true);
+    notebook.set_current_page(Some(page));
+    update_profile_marker(content.upcast_ref());
 }
 
 fn close_current_tab(
@@ -458,7 +2010,7 @@ fn close_current_tab(
     }
 
     if notebook.n_pages() == 0 {
-        create_tab(notebook, config, counter);
+        create_tab(notebook, config, counter, &SpawnOptions::default());
     }
 }
 
@@ -497,11 +2049,31 @@ fn rename_current_tab(window: &gtk::ApplicationWindow, notebook: &gtk::Notebook)
     dialog.present();
 }
 
+fn set_current_tab_title(notebook: &gtk::Notebook, title: &str) {
+    if let Some(page) = notebook.current_page() {
+        set_tab_title(notebook, page, title);
+    }
+}
+
+fn set_tab_title(notebook: &gtk::Notebook, page: u32, title: &str) {
+    let Some(child) = notebook.nth_page(Some(page)) else { return };
+    let Some(tab_widget) = notebook.tab_label(&child) else { return };
+    if let Some(label) = find_tab_label(&tab_widget) {
+        label.set_text(title);
+    }
+}
+
 fn find_tab_label(tab_widget: &gtk::Widget) -> Option<gtk::Label> {
     let mut child = tab_widget.first_child();
     while let Some(widget) = child {
-        if let Ok(label) = widget.clone().downcast::<gtk::Label>() {
-            return Some(label);
+        // The profile marker in front of the title is a label as well.
+        let label = widget
+            .clone()
+            .downcast::<gtk::Label>()
+            .ok()
+            .filter(|label| label.widget_name() != PROFILE_MARKER_NAME);
+        if label.is_some() {
+            return label;
         }
         child = widget.next_sibling();
     }
@@ -512,6 +2084,7 @@ fn split_current_tab(
     notebook: &gtk::Notebook,
     config: &Rc<RefCell<Config>>,
     orientation: gtk::Orientation,
+    options: Option<&SpawnOptions>,
 ) {
     let Some(page) = notebook.current_page() else { return };
     let Some(root) = notebook.nth_page(Some(page)) else { return };
@@ -525,18 +2098,56 @@ fn split_current_tab(
     let Some(existing_child) = existing_child else { return };
 
     let config_ref = config.borrow();
-    let new_terminal = create_terminal_widget(&config_ref);
-    let paned = gtk::Paned::new(orientation);
-    paned.set_wide_handle(true);
-    paned.set_hexpand(true);
-    paned.set_vexpand(true);
+    let options = options.cloned().unwrap_or_else(|| {
+        let source = find_terminal_in_widget(&existing_child);
+        new_terminal_options(&config_ref, source.as_ref())
+    });
+    let new_terminal = create_terminal_widget(&config_ref, &options);
+    let paned = new_split_paned(orientation);
 
     replace_widget_in_parent(&existing_child, paned.upcast_ref());
 
     paned.set_start_child(Some(&existing_child));
     paned.set_end_child(Some(&new_terminal.scrolled));
     new_terminal.terminal.grab_focus();
-    attach_font_scroll_handler(&new_terminal.terminal, config);
+    attach_terminal_handlers(&new_terminal.terminal, config);
+    update_profile_marker(root_box.upcast_ref());
+}
+
+fn new_split_paned(orientation: gtk::Orientation) -> gtk::Paned {
+    let paned = gtk::Paned::new(orientation);
+    paned.set_wide_handle(true);
+    paned.set_hexpand(true);
+    paned.set_vexpand(true);
+    paned
+}
+
+fn paned_ratio(paned: &gtk::Paned) -> f64 {
+    let size = match paned.orientation() {
+        gtk::Orientation::Horizontal => paned.width(),
+        _ => paned.height(),
+    };
+    if size <= 0 {
+        return 0.5;
+    }
+    (paned.position() as f64 / size as f64).clamp(0.05, 0.95)
+}
+
+// The position can only be derived from a ratio once the paned has been
+// allocated, so wait for the first frame where it has a real size.
+fn set_paned_ratio(paned: &gtk::Paned, ratio: f64) {
+    let ratio = ratio.clamp(0.05, 0.95);
+    paned.add_tick_callback(move |paned, _| {
+        let size = match paned.orientation() {
+            gtk::Orientation::Horizontal => paned.width(),
+            _ => paned.height(),
+        };
+        if size <= 0 {
+            return gtk::glib::ControlFlow::Continue;
+        }
+        paned.set_position((size as f64 * ratio).round() as i32);
+        gtk::glib::ControlFlow::Break
+    });
 }
 
 fn close_focused_panel(window: &gtk::Window, notebook: &gtk::Notebook) -> bool {
@@ -546,7 +2157,7 @@ fn close_focused_panel(window: &gtk::Window, notebook: &gtk::Notebook) -> bool {
     true
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum FocusDirection {
     Left,
     Right,
@@ -651,7 +2262,11 @@ fn close_scrolled_widget(
     }
 
     let Ok(paned) = parent.downcast::<gtk::Paned>() else { return };
+    let page = find_notebook_page(notebook, scrolled.upcast_ref());
     collapse_paned(paned, scrolled);
+    if let Some(page) = page {
+        update_profile_marker(&page);
+    }
 }
 
 fn collapse_paned(paned: gtk::Paned, removed: &gtk::ScrolledWindow) {
@@ -701,17 +2316,39 @@ fn find_scrolled_ancestor(widget: &gtk::Widget) -> Option<gtk::ScrolledWindow> {
     None
 }
 
-fn spawn_shell(terminal: &Terminal, config: &Config) {
-    let shell = config.shell.clone();
-    let argv = [shell.as_str()];
-    let cwd = env::var("HOME").unwrap_or_else(|_| "/".to_string());
+fn spawn_shell(terminal: &Terminal, config: &Config, options: &SpawnOptions) {
+    let argv = options
+        .command
+        .clone()
+        .unwrap_or_else(|| vec![config.shell.clone()]);
+    let argv: Vec<&str> = argv.iter().map(String::as_str).collect();
+    let cwd = options
+        .cwd
+        .as_deref()
+        .filter(|path| path.is_dir())
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|| env::var("HOME").unwrap_or_else(|_| "/".to_string()));
+
+    // Let programs in the pane talk back to us through the control socket.
+    let mut envv = Vec::new();
+    if let Some(socket) = CONTROL_SOCKET.get() {
+        envv.push(format!("TERMILYON_SOCKET={}", socket.display()));
+    }
+    if let Some(id) = terminal_pane_id(terminal) {
+        envv.push(format!("TERMILYON_PANE_ID={id}"));
+    }
+    for (name, value) in &config.env {
+        envv.push(format!("{name}={value}"));
+    }
+    let envv: Vec<&str> = envv.iter().map(String::as_str).collect();
 
     terminal.spawn_async(
         PtyFlags::DEFAULT,
         Some(&cwd),
         &argv,
-        &[],
-        gtk::glib::SpawnFlags::DEFAULT,
+        &envv,
+        // `-e htop` should work without a full path.
+        gtk::glib::SpawnFlags::SEARCH_PATH,
         || {},
         -1,
         None::<&gtk::gio::Cancellable>,
@@ -762,8 +2399,18 @@ fn attach_font_scroll_handler(terminal: &Terminal, config: &Rc<RefCell<Config>>)
     terminal_ac.add_controller(ctrl);
 }
 
-fn create_terminal_widget(config: &Config) -> TerminalWidget {
+fn create_terminal_widget(config: &Config, options: &SpawnOptions) -> TerminalWidget {
+    let config = &config.with_profile(options.profile.as_deref());
     let terminal = Terminal::new();
+    let pane_id = NEXT_PANE_ID.fetch_add(1, Ordering::Relaxed);
+    terminal.set_widget_name(&format!("{PANE_NAME_PREFIX}{pane_id}"));
+    set_terminal_profile(&terminal, config, options.profile.as_deref());
+    terminal.connect_destroy(|terminal| {
+        if let Some(id) = terminal_pane_id(terminal) {
+            PANE_PROFILES.with_borrow_mut(|profiles| profiles.remove(&id));
+            HYPERLINK_MATCHES.with_borrow_mut(|matches| matches.remove(&id));
+        }
+    });
     terminal.set_scrollback_lines(config.scrollback_lines.into());
 
     let mut font_desc = gtk::pango::FontDescription::from_string(&config.font);
@@ -772,13 +2419,14 @@ fn create_terminal_widget(config: &Config) -> TerminalWidget {
     }
     terminal.set_font(Some(&font_desc));
 
-    if let Some(theme_path) = config.theme_file.as_ref() {
-        if let Some(theme) = theme_from_file(theme_path) {
-            apply_theme(&terminal, &theme);
-        }
+    if let Some(theme) = config.theme.as_ref().and_then(theme_from_source) {
+        apply_theme(&terminal, &theme);
     }
 
-    spawn_shell(&terminal, config);
+    terminal.set_allow_hyperlink(true);
+    register_hyperlink_matches(&terminal, config);
+
+    spawn_shell(&terminal, config, options);
 
     let scrolled = gtk::ScrolledWindow::new();
     scrolled.set_child(Some(&terminal));
@@ -786,1003 +2434,4910 @@ fn create_terminal_widget(config: &Config) -> TerminalWidget {
     scrolled.set_vexpand(true);
 
     let scrolled_clone = scrolled.clone();
-    terminal.connect_child_exited(move |_, _| {
+    let hold = options.hold;
+    terminal.connect_child_exited(move |terminal, status| {
+        if hold {
+            let message = format!("\r\n[process {}]\r\n", describe_wait_status(status));
+            terminal.feed(message.as_bytes());
+            return;
+        }
         close_scrolled_widget_auto(&scrolled_clone);
     });
 
     TerminalWidget { scrolled, terminal }
 }
 
-fn close_scrolled_widget_auto(scrolled: &gtk::ScrolledWindow) {
-    let widget = scrolled.clone().upcast::<gtk::Widget>();
-    let Some(notebook) = find_parent_notebook(&widget) else { return };
-    let Some(window) = find_root_window(&widget) else { return };
-    close_scrolled_widget(&window, &notebook, scrolled);
+// `status` is a raw wait status as returned by waitpid(): the exit code is
+// in the second byte, a terminating signal in the low seven bits.
+fn describe_wait_status(status: i32) -> String {
+    let signal = status & 0x7f;
+    if signal == 0 {
+        format!("exited with status {}", (status >> 8) & 0xff)
+    } else if signal != 0x7f {
+        format!("killed by signal {signal}")
+    } else {
+        format!("exited with wait status {status}")
+    }
 }
 
-fn find_parent_notebook(widget: &gtk::Widget) -> Option<gtk::Notebook> {
-    let mut current = widget.parent();
-    while let Some(node) = current {
-        if let Ok(notebook) = node.clone().downcast::<gtk::Notebook>() {
-            return Some(notebook);
+// The search bar is a popover parented to the terminal, so it floats over the
+// pane without changing the ScrolledWindow/Paned structure of the tab.
+fn show_search_bar(terminal: &Terminal) {
+    let popover = find_search_popover(terminal).unwrap_or_else(|| build_search_bar(terminal));
+    let width = terminal.width().max(1);
+    popover.set_pointing_to(Some(&gdk::Rectangle::new(width - 8, 4, 1, 1)));
+    popover.popup();
+    if let Some(entry) = popover.child().and_then(|child| child.first_child()) {
+        entry.grab_focus();
+    }
+}
+
+fn find_search_popover(terminal: &Terminal) -> Option<gtk::Popover> {
+    let mut child = terminal.first_child();
+    while let Some(widget) = child {
+        if widget.widget_name() == SEARCH_POPOVER_NAME {
+            return widget.downcast::<gtk::Popover>().ok();
         }
-        current = node.parent();
+        child = widget.next_sibling();
     }
     None
 }
 
-fn find_root_window(widget: &gtk::Widget) -> Option<gtk::Window> {
-    widget
-        .root()
-        .and_then(|root| root.downcast::<gtk::Window>().ok())
-}
+fn build_search_bar(terminal: &Terminal) -> gtk::Popover {
+    let popover = gtk::Popover::new();
+    popover.set_widget_name(SEARCH_POPOVER_NAME);
+    popover.set_has_arrow(false);
+    popover.set_autohide(false);
+    popover.set_position(gtk::PositionType::Bottom);
+    popover.set_halign(gtk::Align::End);
+    popover.set_parent(terminal);
+
+    let row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let entry = gtk::SearchEntry::new();
+    entry.set_placeholder_text(Some("Search..."));
+    entry.set_width_chars(24);
+    let case_button = gtk::ToggleButton::with_label("Aa");
+    case_button.set_tooltip_text(Some("Case sensitive"));
+    let regex_button = gtk::ToggleButton::with_label(".*");
+    regex_button.set_tooltip_text(Some("Regular expression"));
+    let wrap_button = gtk::ToggleButton::new();
+    wrap_button.set_icon_name("media-playlist-repeat-symbolic");
+    wrap_button.set_tooltip_text(Some("Wrap around"));
+    wrap_button.set_active(true);
+    let count_label = gtk::Label::new(None);
+    count_label.set_width_chars(10);
+    let previous_button = gtk::Button::from_icon_name("go-up-symbolic");
+    previous_button.set_tooltip_text(Some("Previous match (Enter)"));
+    let next_button = gtk::Button::from_icon_name("go-down-symbolic");
+    next_button.set_tooltip_text(Some("Next match (Shift+Enter)"));
+    let close_button = gtk::Button::from_icon_name("window-close-symbolic");
+    close_button.set_tooltip_text(Some("Close (Esc)"));
+
+    row.append(&entry);
+    row.append(&case_button);
+    row.append(&regex_button);
+    row.append(&wrap_button);
+    row.append(&count_label);
+    row.append(&previous_button);
+    row.append(&next_button);
+    row.append(&close_button);
+    popover.set_child(Some(&row));
+
+    let weak_terminal = terminal.downgrade();
+    let update = {
+        let entry = entry.clone();
+        let case_button = case_button.clone();
+        let regex_button = regex_button.clone();
+        let count_label = count_label.clone();
+        let weak_terminal = weak_terminal.clone();
+        move || {
+            let Some(terminal) = weak_terminal.upgrade() else { return };
+            update_search(
+                &terminal,
+                &entry,
+                &count_label,
+                case_button.is_active(),
+                regex_button.is_active(),
+            );
+        }
+    };
+    let update = Rc::new(update);
 
-#[derive(Debug, Clone)]
-struct Theme {
-    background: gdk::RGBA,
-    foreground: gdk::RGBA,
-    cursor: gdk::RGBA,
-    palette: [gdk::RGBA; 16],
-    tab_active_bg: Option<gdk::RGBA>,
-    tab_active_fg: Option<gdk::RGBA>,
-    tab_inactive_bg: Option<gdk::RGBA>,
-    tab_inactive_fg: Option<gdk::RGBA>,
-}
-
-#[derive(Debug, Deserialize)]
-struct ThemeConfig {
-    background: String,
-    foreground: String,
-    cursor: String,
-    palette: Vec<String>,
-    tab_active_bg: Option<String>,
-    tab_active_fg: Option<String>,
-    tab_inactive_bg: Option<String>,
-    tab_inactive_fg: Option<String>,
-}
-
-fn apply_theme(terminal: &Terminal, theme: &Theme) {
-    let palette_refs: Vec<&gdk::RGBA> = theme.palette.iter().collect();
-    terminal.set_colors(
-        Some(&theme.foreground),
-        Some(&theme.background),
-        &palette_refs,
-    );
-    terminal.set_color_cursor(Some(&theme.cursor));
-}
-
-fn theme_from_file(path: &PathBuf) -> Option<Theme> {
-    let Ok(contents) = fs::read_to_string(path) else {
-        eprintln!("theme load failed: {}", path.display());
-        return None;
-    };
-    let Ok(raw) = toml::from_str::<ThemeConfig>(&contents) else {
-        eprintln!("theme parse failed: {}", path.display());
-        return None;
-    };
+    {
+        let update = update.clone();
+        entry.connect_search_changed(move |_| update());
+    }
+    {
+        let update = update.clone();
+        case_button.connect_toggled(move |_| update());
+    }
+    {
+        let update = update.clone();
+        regex_button.connect_toggled(move |_| update());
+    }
+    {
+        let weak_terminal = weak_terminal.clone();
+        wrap_button.connect_toggled(move |button| {
+            if let Some(terminal) = weak_terminal.upgrade() {
+                terminal.search_set_wrap_around(button.is_active());
+            }
+        });
+    }
 
-    let palette = parse_palette(&raw.palette)?;
-    Some(Theme {
-        background: rgba(&raw.background),
-        foreground: rgba(&raw.foreground),
-        cursor: rgba(&raw.cursor),
-        palette,
-        tab_active_bg: raw.tab_active_bg.as_deref().map(rgba),
-        tab_active_fg: raw.tab_active_fg.as_deref().map(rgba),
-        tab_inactive_bg: raw.tab_inactive_bg.as_deref().map(rgba),
-        tab_inactive_fg: raw.tab_inactive_fg.as_deref().map(rgba),
-    })
-}
+    // Terminal output grows at the bottom, so Enter walks back through older
+    // matches.
+    {
+        let weak_terminal = weak_terminal.clone();
+        previous_button.connect_clicked(move |_| {
+            if let Some(terminal) = weak_terminal.upgrade() {
+                terminal.search_find_previous();
+            }
+        });
+    }
+    {
+        let weak_terminal = weak_terminal.clone();
+        next_button.connect_clicked(move |_| {
+            if let Some(terminal) = weak_terminal.upgrade() {
+                terminal.search_find_next();
+            }
+        });
+    }
+    {
+        let weak_terminal = weak_terminal.clone();
+        entry.connect_activate(move |_| {
+            if let Some(terminal) = weak_terminal.upgrade() {
+                terminal.search_find_previous();
+            }
+        });
+    }
+    let shift_enter = gtk::EventControllerKey::new();
+    shift_enter.set_propagation_phase(gtk::PropagationPhase::Capture);
+    {
+        let weak_terminal = weak_terminal.clone();
+        shift_enter.connect_key_pressed(move |_, key, _, state| {
+            let is_enter = key == gdk::Key::Return || key == gdk::Key::KP_Enter;
+            if !is_enter || !state.contains(gdk::ModifierType::SHIFT_MASK) {
+                return gtk::glib::Propagation::Proceed;
+            }
+            if let Some(terminal) = weak_terminal.upgrade() {
+                terminal.search_find_next();
+            }
+            gtk::glib::Propagation::Stop
+        });
+    }
+    entry.add_controller(shift_enter);
 
-fn rgba(hex: &str) -> gdk::RGBA {
-    let hex = hex.trim_start_matches('#');
-    let (r, g, b) = match hex.len() {
-        6 => {
-            let r = u8::from_str_radix(&hex[0..2], 16).unwrap_or(0);
-            let g = u8::from_str_radix(&hex[2..4], 16).unwrap_or(0);
-            let b = u8::from_str_radix(&hex[4..6], 16).unwrap_or(0);
-            (r, g, b)
+    {
+        let popover = popover.clone();
+        entry.connect_stop_search(move |_| popover.popdown());
+    }
+    {
+        let popover = popover.clone();
+        close_button.connect_clicked(move |_| popover.popdown());
+    }
+    popover.connect_closed(move |_| {
+        if let Some(terminal) = weak_terminal.upgrade() {
+            terminal.search_set_regex(None, 0);
+            terminal.grab_focus();
         }
-        _ => (0, 0, 0),
-    };
-    gdk::RGBA::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0)
+    });
+
+    terminal.search_set_wrap_around(true);
+    {
+        let popover = popover.clone();
+        terminal.connect_destroy(move |_| popover.unparent());
+    }
+    popover
 }
 
-fn apply_tab_styles(
-    notebook: &gtk::Notebook,
-    theme: Option<&Theme>,
-    terminal: Option<&Terminal>,
+fn update_search(
+    terminal: &Terminal,
+    entry: &gtk::SearchEntry,
+    count_label: &gtk::Label,
+    case_sensitive: bool,
+    use_regex: bool,
 ) {
-    let background = terminal
-        .map(|terminal| terminal.color_background_for_draw())
-        .or_else(|| theme.map(|theme| theme.background.clone()));
-    let Some(background) = background else { return };
+    entry.remove_css_class("error");
+    let text = entry.text();
+    if text.is_empty() {
+        terminal.search_set_regex(None, 0);
+        count_label.set_text("");
+        return;
+    }
 
-    let base_fg = theme
-        .map(|theme| theme.foreground.clone())
-        .unwrap_or_else(|| contrast_text_color(&background));
+    let pattern = if use_regex {
+        text.to_string()
+    } else {
+        gtk::glib::Regex::escape_string(text.as_str()).to_string()
+    };
+    let mut flags = PCRE2_UTF | PCRE2_NO_UTF_CHECK | PCRE2_MULTILINE;
+    if !case_sensitive {
+        flags |= PCRE2_CASELESS;
+    }
+    let regex = match Regex::for_search(&pattern, flags) {
+        Ok(regex) => regex,
+        Err(_) => {
+            terminal.search_set_regex(None, 0);
+            entry.add_css_class("error");
+            count_label.set_text("Invalid");
+            return;
+        }
+    };
+    terminal.search_set_regex(Some(&regex), 0);
 
-    let active_bg = theme
-        .and_then(|theme| theme.tab_active_bg.clone())
-        .unwrap_or_else(|| background.clone());
-    let inactive_bg = theme
-        .and_then(|theme| theme.tab_inactive_bg.clone())
-        .unwrap_or_else(|| adjust_luma(&background, 0.92));
+    let count = count_search_matches(terminal, &pattern, case_sensitive);
+    count_label.set_text(&match count {
+        0 => "No matches".to_string(),
+        1 => "1 match".to_string(),
+        n => format!("{n} matches"),
+    });
 
-    let active_fg = theme
-        .and_then(|theme| theme.tab_active_fg.clone())
-        .unwrap_or_else(|| base_fg.clone());
-    let inactive_fg = theme
-        .and_then(|theme| theme.tab_inactive_fg.clone())
-        .unwrap_or_else(|| with_alpha(&base_fg, 0.7));
+    // Start again from the bottom so refining the query finds the most
+    // recent match instead of moving further up.
+    terminal.unselect_all();
+    terminal.search_find_previous();
+}
 
-    let mut css = format!(
-        ".terminal-tabs > header {{ background-color: {}; }}",
-        background.to_str()
+// VTE does not expose a match count, so run the same pattern with GRegex
+// (also PCRE2) over the scrollback and the visible screen.
+fn count_search_matches(terminal: &Terminal, pattern: &str, case_sensitive: bool) -> usize {
+    let mut flags = gtk::glib::RegexCompileFlags::MULTILINE;
+    if !case_sensitive {
+        flags |= gtk::glib::RegexCompileFlags::CASELESS;
+    }
+    let Ok(Some(regex)) =
+        gtk::glib::Regex::new(pattern, flags, gtk::glib::RegexMatchFlags::empty())
+    else {
+        return 0;
+    };
+    let Some(adjustment) = terminal.vadjustment() else { return 0 };
+    let (text, _) = terminal.text_range_format(
+        Format::Text,
+        adjustment.lower() as std::ffi::c_long,
+        0,
+        adjustment.upper() as std::ffi::c_long,
+        terminal.column_count(),
     );
-    css.push_str(&format!(
-        ".terminal-tabs tab {{ background-color: {}; background-image: none; border-image: none; }}",
-        inactive_bg.to_str()
-    ));
-    css.push_str(&format!(
-        ".terminal-tabs tab:checked {{ background-color: {}; background-image: none; border-image: none; }}",
-        active_bg.to_str()
-    ));
-    css.push_str(".terminal-tabs tab > * { background-color: transparent; }");
-    css.push_str(&format!(
-        ".terminal-tabs tab label, .terminal-tabs tab button {{ color: {}; }}",
-        inactive_fg.to_str()
-    ));
-    css.push_str(&format!(
-        ".terminal-tabs tab:checked label, .terminal-tabs tab:checked button {{ color: {}; }}",
-        active_fg.to_str()
-    ));
+    let Some(text) = text else { return 0 };
+    let Ok(info) = regex.match_(text.as_gstr(), gtk::glib::RegexMatchFlags::empty()) else {
+        return 0;
+    };
 
-    let provider = gtk::CssProvider::new();
-    provider.load_from_data(&css);
-    if let Some(display) = gdk::Display::default() {
-        gtk::style_context_add_provider_for_display(
-            &display,
-            &provider,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-        );
+    let mut count = 0;
+    while info.matches() {
+        count += 1;
+        if info.next().is_err() {
+            break;
+        }
     }
-    notebook.queue_draw();
+    count
 }
 
-fn reload_config_and_theme(
-    config: &Rc<RefCell<Config>>,
-    notebook: &gtk::Notebook,
-    theme_override: Option<&PathBuf>,
-) {
-    let mut updated = Config::load();
-    if let Some(path) = theme_override {
-        updated.theme_file = Some(path.clone());
+// Custom rules are registered first so they win over the built-in patterns
+// when both match the same text.
+fn register_hyperlink_matches(terminal: &Terminal, config: &Config) {
+    terminal.match_remove_all();
+    let rules = config
+        .hyperlinks
+        .iter()
+        .map(|rule| (rule.pattern.as_str(), Some(rule)));
+    let builtin = [
+        (MATCH_URL_PATTERN, None),
+        (MATCH_FILE_LINE_PATTERN, None),
+        (MATCH_EMAIL_PATTERN, None),
+    ];
+    let mut matches = HashMap::new();
+    for (index, (pattern, rule)) in rules.chain(builtin).enumerate() {
+        let flags = PCRE2_UTF | PCRE2_NO_UTF_CHECK | PCRE2_MULTILINE;
+        let regex = match Regex::for_match(pattern, flags) {
+            Ok(regex) => regex,
+            Err(err) => {
+                eprintln!("invalid hyperlink pattern {pattern}: {err}");
+                continue;
+            }
+        };
+        let tag = terminal.match_add_regex(&regex, 0);
+        terminal.match_set_cursor_name(tag, "pointer");
+        let kind = match rule {
+            Some(rule) => HyperlinkMatch::Rule(rule.clone(), regex),
+            None => match index - config.hyperlinks.len() {
+                0 => HyperlinkMatch::Url,
+                1 => HyperlinkMatch::FileLine,
+                _ => HyperlinkMatch::Email,
+            },
+        };
+        matches.insert(tag, kind);
     }
-    let theme = updated
-        .theme_file
-        .as_ref()
-        .and_then(|path| theme_from_file(path));
-    *config.borrow_mut() = updated.clone();
+    if let Some(id) = terminal_pane_id(terminal) {
+        HYPERLINK_MATCHES.with_borrow_mut(|all| all.insert(id, matches));
+    }
+}
 
-    notebook.set_tab_pos(updated.tab_bar_position);
-    let sample_terminal = find_first_terminal_in_notebook(notebook);
-    apply_tab_styles(notebook, theme.as_ref(), sample_terminal.as_ref());
-    apply_config_to_terminals(notebook, &updated, theme.as_ref());
+fn terminal_hyperlink_match(terminal: &Terminal, tag: i32) -> Option<HyperlinkMatch> {
+    let id = terminal_pane_id(terminal)?;
+    HYPERLINK_MATCHES.with_borrow(|all| all.get(&id)?.get(&tag).cloned())
 }
 
-fn find_first_terminal_in_notebook(notebook: &gtk::Notebook) -> Option<Terminal> {
-    for index in 0..notebook.n_pages() {
-        let Some(page) = notebook.nth_page(Some(index)) else { continue };
-        if let Some(terminal) = find_terminal_in_widget(&page) {
-            return Some(terminal);
+// Every pane gets the same mouse handlers, wherever it is created.
+fn attach_terminal_handlers(terminal: &Terminal, config: &Rc<RefCell<Config>>) {
+    attach_font_scroll_handler(terminal, config);
+    attach_hyperlink_handler(terminal);
+    attach_context_menu(terminal, config);
+}
+
+fn attach_hyperlink_handler(terminal: &Terminal) {
+    let click = gtk::GestureClick::new();
+    click.set_button(gdk::BUTTON_PRIMARY);
+    click.set_propagation_phase(gtk::PropagationPhase::Capture);
+    click.connect_pressed(move |gesture, _, x, y| {
+        if !gesture
+            .current_event_state()
+            .contains(gdk::ModifierType::CONTROL_MASK)
+        {
+            return;
         }
-    }
-    None
+        let Some(terminal) = gesture.widget().and_downcast::<Terminal>() else { return };
+        let cwd = terminal_working_directory(&terminal);
+        let target = terminal
+            .check_hyperlink_at(x, y)
+            .map(|uri| HyperlinkTarget::Uri(uri.to_string()))
+            .or_else(|| {
+                let (text, tag) = terminal.check_match_at(x, y);
+                let kind = terminal_hyperlink_match(&terminal, tag)?;
+                resolve_hyperlink(&kind, &text?, cwd.as_deref())
+            });
+        let Some(target) = target else { return };
+        gesture.set_state(gtk::EventSequenceState::Claimed);
+        open_hyperlink(&target, cwd.as_deref());
+    });
+    terminal.add_controller(click);
 }
 
-fn apply_config_to_terminals(
-    notebook: &gtk::Notebook,
-    config: &Config,
-    theme: Option<&Theme>,
-) {
-    for index in 0..notebook.n_pages() {
-        let Some(page) = notebook.nth_page(Some(index)) else { continue };
-        let mut terminals = Vec::new();
-        collect_terminals(&page, &mut terminals);
-        for terminal in terminals {
-            terminal.set_scrollback_lines(config.scrollback_lines.into());
-            let mut font_desc = gtk::pango::FontDescription::from_string(&config.font);
-            if config.font_size > 0 {
-                font_desc.set_size(config.font_size * gtk::pango::SCALE);
-            }
-            terminal.set_font(Some(&font_desc));
-            if let Some(theme) = theme {
-                apply_theme(&terminal, theme);
+// `text` is what VTE matched for the tag `kind` was registered with.
+fn resolve_hyperlink(
+    kind: &HyperlinkMatch,
+    text: &str,
+    cwd: Option<&Path>,
+) -> Option<HyperlinkTarget> {
+    match kind {
+        HyperlinkMatch::Rule(rule, regex) => {
+            if let Some(url) = rule.url.as_deref() {
+                let uri = expand_hyperlink_template(url, regex, text, false);
+                return Some(HyperlinkTarget::Uri(uri));
             }
+            let command = rule.command.as_deref()?;
+            let command = expand_hyperlink_template(command, regex, text, true);
+            Some(HyperlinkTarget::Command(command))
+        }
+        HyperlinkMatch::Url => Some(HyperlinkTarget::Uri(text.to_string())),
+        HyperlinkMatch::Email => {
+            let address = text.strip_prefix("mailto:").unwrap_or(text);
+            Some(HyperlinkTarget::Uri(format!("mailto:{address}")))
         }
+        HyperlinkMatch::FileLine => resolve_file_link(text, cwd),
     }
 }
 
-fn collect_terminals(widget: &gtk::Widget, terminals: &mut Vec<Terminal>) {
-    if let Ok(terminal) = widget.clone().downcast::<Terminal>() {
-        terminals.push(terminal);
-        return;
-    }
-    if let Ok(scrolled) = widget.clone().downcast::<gtk::ScrolledWindow>() {
-        if let Some(child) = scrolled.child() {
-            collect_terminals(&child, terminals);
+// file:line[:column] opens the file; the line is only useful to custom
+// rules that pass it to an editor.
+fn resolve_file_link(text: &str, cwd: Option<&Path>) -> Option<HyperlinkTarget> {
+    let mut path = text;
+    for _ in 0..2 {
+        match path.rsplit_once(':') {
+            Some((head, tail)) if tail.chars().all(|c| c.is_ascii_digit()) => path = head,
+            _ => break,
         }
-        return;
     }
-    if let Ok(paned) = widget.clone().downcast::<gtk::Paned>() {
-        if let Some(child) = paned.start_child() {
-            collect_terminals(&child, terminals);
-        }
-        if let Some(child) = paned.end_child() {
-            collect_terminals(&child, terminals);
-        }
-        return;
+    let mut path = expand_home(path);
+    if path.is_relative() {
+        path = cwd?.join(path);
     }
+    path.exists()
+        .then(|| HyperlinkTarget::Uri(gtk::gio::File::for_path(&path).uri().to_string()))
+}
 
-    let mut child = widget.first_child();
-    while let Some(node) = child {
-        collect_terminals(&node, terminals);
-        child = node.next_sibling();
+// Captures spliced into a shell command are quoted so terminal output
+// cannot inject extra commands; captures in a URL are percent-encoded.
+fn expand_hyperlink_template(template: &str, regex: &Regex, text: &str, quote: bool) -> String {
+    let mut expanded = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let group = chars.peek().and_then(|next| next.to_digit(10));
+        match group {
+            Some(group) if ch == '$' => {
+                chars.next();
+                // `text` is exactly one match, so the anchored substitution
+                // yields just the capture.
+                let anchored = PCRE2_ANCHORED | PCRE2_ENDANCHORED;
+                let value = regex
+                    .substitute(text, &format!("${group}"), anchored)
+                    .unwrap_or_default();
+                if quote {
+                    expanded.push_str(&gtk::glib::shell_quote(value.as_str()).to_string_lossy());
+                } else {
+                    expanded.push_str(&gtk::glib::Uri::escape_string(&value, None, false));
+                }
+            }
+            _ => expanded.push(ch),
+        }
     }
+    expanded
 }
 
-fn show_keybindings_dialog(window: &gtk::ApplicationWindow, config: &Config) {
-    let dialog = gtk::Dialog::new();
-    dialog.set_title(Some("Keybindings"));
-    dialog.set_modal(true);
-    dialog.set_transient_for(Some(window));
-    dialog.add_button("Close", gtk::ResponseType::Close);
+fn open_hyperlink(target: &HyperlinkTarget, cwd: Option<&Path>) {
+    match target {
+        HyperlinkTarget::Uri(uri) => {
+            if let Err(err) =
+                gtk::gio::AppInfo::launch_default_for_uri(uri, None::<&gtk::gio::AppLaunchContext>)
+            {
+                eprintln!("failed to open {uri}: {err}");
+            }
+        }
+        HyperlinkTarget::Command(command) => {
+            let launcher = gtk::gio::SubprocessLauncher::new(gtk::gio::SubprocessFlags::NONE);
+            if let Some(cwd) = cwd {
+                launcher.set_cwd(cwd);
+            }
+            let argv = ["sh".as_ref(), "-c".as_ref(), std::ffi::OsStr::new(command)];
+            if let Err(err) = launcher.spawn(&argv) {
+                eprintln!("failed to run {command}: {err}");
+            }
+        }
+    }
+}
 
-    let content = dialog.content_area();
-    let list = gtk::Box::new(gtk::Orientation::Vertical, 6);
-    list.set_margin_top(12);
-    list.set_margin_bottom(12);
-    list.set_margin_start(12);
-    list.set_margin_end(12);
-
-    add_keybinding_row(&list, "New tab", &config.keybindings.new_tab);
-    add_keybinding_row(&list, "Close tab", &config.keybindings.close_tab);
-    add_keybinding_row(&list, "Rename tab", &config.keybindings.rename_tab);
-    add_keybinding_row(&list, "Close panel", &config.keybindings.close_panel);
-    add_keybinding_row(&list, "Split vertical", &config.keybindings.split_vertical);
-    add_keybinding_row(&list, "Split horizontal", &config.keybindings.split_horizontal);
-    add_keybinding_row(&list, "Copy", &config.keybindings.copy);
-    add_keybinding_row(&list, "Paste", &config.keybindings.paste);
-    add_keybinding_row(&list, "Reload config/theme", &config.keybindings.reload_config);
-    add_keybinding_row(&list, "Show keybindings", &config.keybindings.show_keybindings);
-    add_keybinding_row(&list, "SSH manager", &config.keybindings.ssh_manager);
-    add_keybinding_row(&list, "Password manager", &config.keybindings.password_manager);
-    add_keybinding_row(&list, "Focus left", &config.keybindings.focus_left);
-    add_keybinding_row(&list, "Focus right", &config.keybindings.focus_right);
-    add_keybinding_row(&list, "Focus up", &config.keybindings.focus_up);
-    add_keybinding_row(&list, "Focus down", &config.keybindings.focus_down);
-
-    for (index, binding) in config.keybindings.tab_switch.iter().enumerate() {
-        let title = format!("Switch tab {}", index + 1);
-        add_keybinding_row(&list, &title, binding);
-    }
-
-    content.append(&list);
-
-    dialog.connect_response(|dialog, _| {
-        dialog.close();
+// Every right-click on a pane opens the menu, with or without Shift. VTE
+// does not tell whether the running program has mouse reporting on, so the
+// click is not left to the program.
+fn attach_context_menu(terminal: &Terminal, config: &Rc<RefCell<Config>>) {
+    let click = gtk::GestureClick::new();
+    click.set_button(gdk::BUTTON_SECONDARY);
+    let config = config.clone();
+    click.connect_pressed(move |gesture, _, x, y| {
+        let Some(terminal) = gesture.widget().and_downcast::<Terminal>() else { return };
+        gesture.set_state(gtk::EventSequenceState::Claimed);
+        terminal.grab_focus();
+        show_terminal_context_menu(&terminal, &config, x, y);
     });
-    dialog.present();
+    terminal.add_controller(click);
 }
 
-fn add_keybinding_row(container: &gtk::Box, name: &str, binding: &KeyBinding) {
-    let row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
-    let label = gtk::Label::new(Some(name));
-    label.set_xalign(0.0);
-    label.set_hexpand(true);
-    let shortcut = gtk::Label::new(Some(&format_keybinding(binding)));
-    shortcut.set_xalign(1.0);
-    row.append(&label);
-    row.append(&shortcut);
-    container.append(&row);
-}
+fn show_terminal_context_menu(terminal: &Terminal, config: &Rc<RefCell<Config>>, x: f64, y: f64) {
+    let link = terminal
+        .check_hyperlink_at(x, y)
+        .or_else(|| terminal.check_match_at(x, y).0)
+        .map(|link| link.to_string());
 
-fn format_keybinding(binding: &KeyBinding) -> String {
-    let mut parts = Vec::new();
-    let modifiers = binding.modifiers;
-    if modifiers.contains(gdk::ModifierType::CONTROL_MASK) {
-        parts.push("Ctrl".to_string());
-    }
-    if modifiers.contains(gdk::ModifierType::SHIFT_MASK) {
-        parts.push("Shift".to_string());
+    let has_selection = terminal.has_selection();
+
+    let actions = gtk::gio::SimpleActionGroup::new();
+    add_menu_action(&actions, terminal, "copy", has_selection, |terminal| {
+        terminal.copy_clipboard_format(Format::Text);
+    });
+    add_menu_action(&actions, terminal, "paste", true, |terminal| {
+        terminal.paste_clipboard();
+    });
+    let has_link = link.is_some();
+    add_menu_action(&actions, terminal, "copy-link", has_link, move |terminal| {
+        if let Some(link) = link.as_deref() {
+            terminal.clipboard().set_text(link);
+        }
+    });
+    for (name, orientation) in [
+        ("split-right", gtk::Orientation::Horizontal),
+        ("split-down", gtk::Orientation::Vertical),
+    ] {
+        let config = config.clone();
+        add_menu_action(&actions, terminal, name, true, move |terminal| {
+            let Some(notebook) = find_parent_notebook(terminal.upcast_ref()) else { return };
+            terminal.grab_focus();
+            split_current_tab(&notebook, &config, orientation, None);
+        });
     }
-    if modifiers.contains(gdk::ModifierType::ALT_MASK) {
-        parts.push("Alt".to_string());
-    }
-    if modifiers.contains(gdk::ModifierType::SUPER_MASK) {
-        parts.push("Super".to_string());
+    add_menu_action(&actions, terminal, "close", true, |terminal| {
+        let widget = terminal.upcast_ref::<gtk::Widget>();
+        let Some(scrolled) = find_scrolled_ancestor(widget) else { return };
+        let Some(notebook) = find_parent_notebook(widget) else { return };
+        let Some(window) = find_root_window(widget) else { return };
+        close_scrolled_widget(&window, &notebook, &scrolled);
+    });
+    {
+        let config = config.clone();
+        add_menu_action(&actions, terminal, "profile", true, move |terminal| {
+            let Some(window) =
+                find_root_window(terminal.upcast_ref()).and_downcast::<gtk::ApplicationWindow>()
+            else {
+                return;
+            };
+            show_switch_profile_dialog(&window, terminal, &config, false);
+        });
     }
+    add_menu_action(&actions, terminal, "search", true, show_search_bar);
+    add_menu_action(&actions, terminal, "reset", true, |terminal| {
+        terminal.reset(true, true);
+    });
+    add_menu_action(&actions, terminal, "select-all", true, |terminal| {
+        terminal.select_all();
+    });
+    terminal.insert_action_group("pane", Some(&actions));
+
+    let clipboard = gtk::gio::Menu::new();
+    clipboard.append(Some("Copy"), Some("pane.copy"));
+    clipboard.append(Some("Paste"), Some("pane.paste"));
+    clipboard.append(Some("Copy Link"), Some("pane.copy-link"));
+    let layout = gtk::gio::Menu::new();
+    layout.append(Some("Split Right"), Some("pane.split-right"));
+    layout.append(Some("Split Down"), Some("pane.split-down"));
+    layout.append(Some("Close Pane"), Some("pane.close"));
+    let terminal_section = gtk::gio::Menu::new();
+    terminal_section.append(Some("Search..."), Some("pane.search"));
+    terminal_section.append(Some("Profile..."), Some("pane.profile"));
+    terminal_section.append(Some("Select All"), Some("pane.select-all"));
+    terminal_section.append(Some("Reset Terminal"), Some("pane.reset"));
+    let menu = gtk::gio::Menu::new();
+    menu.append_section(None, &clipboard);
+    menu.append_section(None, &layout);
+    menu.append_section(None, &terminal_section);
+
+    popup_context_menu(terminal.upcast_ref(), &menu, x, y);
+}
 
-    let key_name = binding
-        .key
-        .name()
-        .map(|name| name.to_string())
-        .unwrap_or_else(|| format!("{:?}", binding.key));
-    let key_display = if key_name.len() == 1 {
-        key_name.to_ascii_uppercase()
-    } else {
-        key_name
-    };
-    parts.push(key_display);
-    parts.join("+")
+fn show_tab_context_menu(
+    tab_widget: &gtk::Widget,
+    content: &gtk::Box,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+    x: f64,
+    y: f64,
+) {
+    let Some(notebook) = find_parent_notebook(content.upcast_ref()) else { return };
+    let Some(page) = notebook.page_num(content) else { return };
+    let last = notebook.n_pages().saturating_sub(1);
+
+    let actions = gtk::gio::SimpleActionGroup::new();
+    add_menu_action(&actions, content, "rename", true, |content| {
+        let Some(notebook) = find_parent_notebook(content.upcast_ref()) else { return };
+        let Some(window) =
+            find_root_window(content.upcast_ref()).and_downcast::<gtk::ApplicationWindow>()
+        else {
+            return;
+        };
+        notebook.set_current_page(notebook.page_num(content));
+        rename_current_tab(&window, &notebook);
+    });
+    {
+        let config = config.clone();
+        let counter = counter.clone();
+        add_menu_action(&actions, content, "duplicate", true, move |content| {
+            duplicate_tab(content, &config, &counter);
+        });
+    }
+    {
+        let config = config.clone();
+        let counter = counter.clone();
+        add_menu_action(&actions, content, "close", true, move |content| {
+            let Some(notebook) = find_parent_notebook(content.upcast_ref()) else { return };
+            notebook.set_current_page(notebook.page_num(content));
+            close_current_tab(&notebook, &config, &counter);
+        });
+    }
+    {
+        let config = config.clone();
+        add_menu_action(&actions, content, "profile", true, move |content| {
+            let Some(window) =
+                find_root_window(content.upcast_ref()).and_downcast::<gtk::ApplicationWindow>()
+            else {
+                return;
+            };
+            let Some(terminal) = find_terminal_in_widget(content.upcast_ref()) else { return };
+            show_switch_profile_dialog(&window, &terminal, &config, true);
+        });
+    }
+    add_menu_action(&actions, content, "close-others", last > 0, |content| {
+        close_tabs_around(content, true);
+    });
+    add_menu_action(&actions, content, "close-right", page < last, |content| {
+        close_tabs_around(content, false);
+    });
+    add_menu_action(&actions, content, "move-left", page > 0, |content| {
+        move_tab(content, -1);
+    });
+    add_menu_action(&actions, content, "move-right", page < last, |content| {
+        move_tab(content, 1);
+    });
+    tab_widget.insert_action_group("tab", Some(&actions));
+
+    let edit = gtk::gio::Menu::new();
+    edit.append(Some("Rename..."), Some("tab.rename"));
+    edit.append(Some("Duplicate"), Some("tab.duplicate"));
+    edit.append(Some("Profile..."), Some("tab.profile"));
+    let order = gtk::gio::Menu::new();
+    order.append(Some("Move Left"), Some("tab.move-left"));
+    order.append(Some("Move Right"), Some("tab.move-right"));
+    let close = gtk::gio::Menu::new();
+    close.append(Some("Close Tab"), Some("tab.close"));
+    close.append(Some("Close Other Tabs"), Some("tab.close-others"));
+    close.append(Some("Close Tabs to the Right"), Some("tab.close-right"));
+    let menu = gtk::gio::Menu::new();
+    menu.append_section(None, &edit);
+    menu.append_section(None, &order);
+    menu.append_section(None, &close);
+
+    popup_context_menu(tab_widget, &menu, x, y);
 }
 
-fn clamp01(value: f32) -> f32 {
-    value.max(0.0).min(1.0)
+// Actions only hold a weak reference to their widget; the action group is
+// installed on a widget that owns it, which would otherwise form a cycle.
+fn add_menu_action<W: IsA<gtk::Widget>>(
+    group: &gtk::gio::SimpleActionGroup,
+    widget: &W,
+    name: &str,
+    enabled: bool,
+    activate: impl Fn(&W) + 'static,
+) {
+    let action = gtk::gio::SimpleAction::new(name, None);
+    action.set_enabled(enabled);
+    let weak_widget = widget.downgrade();
+    action.connect_activate(move |_, _| {
+        if let Some(widget) = weak_widget.upgrade() {
+            activate(&widget);
+        }
+    });
+    group.add_action(&action);
 }
 
-fn adjust_luma(color: &gdk::RGBA, factor: f32) -> gdk::RGBA {
-    gdk::RGBA::new(
-        clamp01(color.red() * factor),
-        clamp01(color.green() * factor),
-        clamp01(color.blue() * factor),
-        color.alpha(),
-    )
+fn popup_context_menu(parent: &gtk::Widget, menu: &gtk::gio::Menu, x: f64, y: f64) {
+    let popover = gtk::PopoverMenu::from_model(Some(menu));
+    popover.set_parent(parent);
+    popover.set_has_arrow(false);
+    popover.set_halign(gtk::Align::Start);
+    popover.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+    // The menu activates its item after closing, so detach it once that
+    // has run.
+    popover.connect_closed(|popover| {
+        let popover = popover.clone();
+        gtk::glib::idle_add_local_once(move || popover.unparent());
+    });
+    popover.popup();
 }
 
-fn with_alpha(color: &gdk::RGBA, alpha: f32) -> gdk::RGBA {
-    gdk::RGBA::new(color.red(), color.green(), color.blue(), clamp01(alpha))
+fn duplicate_tab(content: &gtk::Box, config: &Rc<RefCell<Config>>, counter: &Rc<Cell<u32>>) {
+    let Some(notebook) = find_parent_notebook(content.upcast_ref()) else { return };
+    let Some(root) = content.first_child() else { return };
+    let Some(pane) = capture_session_pane(&root) else { return };
+    let title = notebook
+        .tab_label(content)
+        .and_then(|tab_widget| find_tab_label(&tab_widget))
+        .map(|label| label.text().to_string());
+    let (root, terminal) = build_session_pane(&pane, config);
+    append_tab(&notebook, config, counter, &root, title.as_deref());
+    let new_content = notebook
+        .current_page()
+        .and_then(|new_page| notebook.nth_page(Some(new_page)));
+    if let (Some(page), Some(new_content)) = (notebook.page_num(content), new_content) {
+        notebook.reorder_child(&new_content, Some(page + 1));
+    }
+    terminal.grab_focus();
 }
 
-fn contrast_text_color(background: &gdk::RGBA) -> gdk::RGBA {
-    let luminance = 0.2126 * background.red()
-        + 0.7152 * background.green()
-        + 0.0722 * background.blue();
-    if luminance < 0.5 {
-        gdk::RGBA::new(1.0, 1.0, 1.0, 1.0)
-    } else {
-        gdk::RGBA::new(0.0, 0.0, 0.0, 1.0)
+fn close_tabs_around(content: &gtk::Box, include_left: bool) {
+    let Some(notebook) = find_parent_notebook(content.upcast_ref()) else { return };
+    let Some(page) = notebook.page_num(content) else { return };
+    for index in (0..notebook.n_pages()).rev() {
+        if index > page || (include_left && index < page) {
+            notebook.remove_page(Some(index));
+        }
+    }
+    if let Some(page) = notebook.page_num(content) {
+        notebook.set_current_page(Some(page));
+        focus_terminal_in_page(&notebook, page);
     }
 }
 
-fn parse_palette(values: &[String]) -> Option<[gdk::RGBA; 16]> {
-    if values.len() != 16 {
-        return None;
+fn move_tab(content: &gtk::Box, offset: i32) {
+    let Some(notebook) = find_parent_notebook(content.upcast_ref()) else { return };
+    let Some(page) = notebook.page_num(content) else { return };
+    let Some(target) = page.checked_add_signed(offset) else { return };
+    if target < notebook.n_pages() {
+        notebook.reorder_child(content, Some(target));
     }
-    let mut colors = values.iter().map(|v| rgba(v)).collect::<Vec<_>>();
-    Some([
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-        colors.remove(0),
-    ])
 }
 
-fn derive_key(secret: &str) -> [u8; 32] {
-    use sha2::{Digest, Sha256};
-    let mut hasher = Sha256::new();
-    hasher.update(secret.as_bytes());
-    hasher.finalize().into()
+fn close_scrolled_widget_auto(scrolled: &gtk::ScrolledWindow) {
+    let widget = scrolled.clone().upcast::<gtk::Widget>();
+    let Some(notebook) = find_parent_notebook(&widget) else { return };
+    let Some(window) = find_root_window(&widget) else { return };
+    close_scrolled_widget(&window, &notebook, scrolled);
 }
 
-fn encrypt_password(plaintext: &str, secret: &str) -> Result<String, String> {
-    use aes_gcm::aead::{Aead, KeyInit, OsRng};
-    use aes_gcm::aead::rand_core::RngCore;
-    use aes_gcm::{Aes256Gcm, Key, Nonce};
-    use base64::{engine::general_purpose::STANDARD, Engine};
+// The page of `notebook` that contains `widget`.
+fn find_notebook_page(notebook: &gtk::Notebook, widget: &gtk::Widget) -> Option<gtk::Widget> {
+    (0..notebook.n_pages())
+        .filter_map(|index| notebook.nth_page(Some(index)))
+        .find(|page| widget.is_ancestor(page))
+}
 
-    let key_bytes = derive_key(secret);
-    let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
-    let cipher = Aes256Gcm::new(key);
+fn find_parent_notebook(widget: &gtk::Widget) -> Option<gtk::Notebook> {
+    let mut current = widget.parent();
+    while let Some(node) = current {
+        if let Ok(notebook) = node.clone().downcast::<gtk::Notebook>() {
+            return Some(notebook);
+        }
+        current = node.parent();
+    }
+    None
+}
 
-    let mut nonce_bytes = [0u8; 12];
-    OsRng.fill_bytes(&mut nonce_bytes);
-    let nonce = Nonce::from_slice(&nonce_bytes);
+fn window_notebook(window: &gtk::Window) -> Option<gtk::Notebook> {
+    window
+        .child()
+        .and_then(|child| find_notebook_in_widget(&child))
+}
 
-    let ciphertext = cipher
-        .encrypt(nonce, plaintext.as_bytes())
-        .map_err(|e| e.to_string())?;
+fn find_notebook_in_widget(widget: &gtk::Widget) -> Option<gtk::Notebook> {
+    if let Ok(notebook) = widget.clone().downcast::<gtk::Notebook>() {
+        return Some(notebook);
+    }
+    let mut child = widget.first_child();
+    while let Some(node) = child {
+        if let Some(notebook) = find_notebook_in_widget(&node) {
+            return Some(notebook);
+        }
+        child = node.next_sibling();
+    }
+    None
+}
 
-    let mut combined = nonce_bytes.to_vec();
-    combined.extend_from_slice(&ciphertext);
-    Ok(STANDARD.encode(&combined))
+fn find_root_window(widget: &gtk::Widget) -> Option<gtk::Window> {
+    widget
+        .root()
+        .and_then(|root| root.downcast::<gtk::Window>().ok())
 }
 
-fn decrypt_password(encrypted: &str, secret: &str) -> Result<String, String> {
-    use aes_gcm::aead::{Aead, KeyInit};
-    use aes_gcm::{Aes256Gcm, Key, Nonce};
-    use base64::{engine::general_purpose::STANDARD, Engine};
+// Shells report their directory with OSC 7, which VTE keeps per terminal.
+fn terminal_working_directory(terminal: &Terminal) -> Option<PathBuf> {
+    let uri = terminal.current_directory_uri()?;
+    gtk::gio::File::for_uri(&uri).path()
+}
 
-    let data = STANDARD.decode(encrypted).map_err(|e| e.to_string())?;
-    if data.len() < 13 {
-        return Err("Geçersiz şifreli veri".to_string());
+// New tabs and splits keep the profile of the pane they are opened from.
+fn new_terminal_options(config: &Config, source: Option<&Terminal>) -> SpawnOptions {
+    profile_terminal_options(config, source, source.and_then(terminal_profile))
+}
+
+fn profile_terminal_options(
+    config: &Config,
+    source: Option<&Terminal>,
+    profile: Option<String>,
+) -> SpawnOptions {
+    let config = config.with_profile(profile.as_deref());
+    let cwd = match &config.new_tab_cwd {
+        NewTabCwd::Home => None,
+        NewTabCwd::Current => source.and_then(terminal_working_directory),
+        NewTabCwd::Path(path) => Some(path.clone()),
+    };
+    SpawnOptions {
+        cwd,
+        profile,
+        ..SpawnOptions::default()
     }
-    let (nonce_bytes, ciphertext) = data.split_at(12);
-    let key_bytes = derive_key(secret);
-    let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
-    let cipher = Aes256Gcm::new(key);
-    let nonce = Nonce::from_slice(nonce_bytes);
+}
 
-    let plaintext = cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|_| "Şifre çözme başarısız — secret anahtarı yanlış olabilir".to_string())?;
-    String::from_utf8(plaintext).map_err(|e| e.to_string())
+fn session_path(name: &str) -> Option<PathBuf> {
+    let config = config_path()?;
+    let dir = config.parent()?;
+    let file_name = if name == DEFAULT_SESSION {
+        "session.toml".to_string()
+    } else {
+        format!("session-{name}.toml")
+    };
+    Some(dir.join(file_name))
 }
 
-fn first_visible_index(list_box: &gtk::ListBox) -> Option<i32> {
-    let mut idx = 0;
-    loop {
-        match list_box.row_at_index(idx) {
-            None => return None,
-            Some(row) if row.is_visible() => return Some(idx),
-            _ => idx += 1,
+fn load_session(name: &str) -> Option<Session> {
+    let path = session_path(name)?;
+    let contents = fs::read_to_string(&path).ok()?;
+    match toml::from_str::<Session>(&contents) {
+        Ok(session) => Some(session),
+        Err(err) => {
+            eprintln!("session parse failed: {}: {err}", path.display());
+            None
         }
     }
 }
 
-fn select_first_visible(list_box: &gtk::ListBox) {
-    let mut idx = 0;
-    loop {
-        match list_box.row_at_index(idx) {
-            None => {
-                list_box.select_row(None::<&gtk::ListBoxRow>);
-                break;
-            }
-            Some(row) if row.is_visible() => {
-                list_box.select_row(Some(&row));
-                break;
-            }
-            _ => idx += 1,
-        }
+fn save_session(name: &str, session: &Session) {
+    let Some(path) = session_path(name) else { return };
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(content) = toml::to_string(session) {
+        let _ = fs::write(&path, content);
     }
 }
 
-fn passwords_path() -> Option<PathBuf> {
-    ProjectDirs::from("", "", "termilyon")
-        .map(|dirs| dirs.config_dir().join("passwords.toml"))
+impl Session {
+    fn into_windows(self) -> Vec<SessionWindow> {
+        if self.windows.is_empty() && !self.tabs.is_empty() {
+            return vec![SessionWindow {
+                active_tab: self.active_tab,
+                tabs: self.tabs,
+            }];
+        }
+        self.windows
+    }
 }
 
-fn load_passwords(secret: &str) -> Vec<Password> {
-    let Some(path) = passwords_path() else { return Vec::new() };
-    let Ok(content) = fs::read_to_string(&path) else { return Vec::new() };
-    let encrypted_list = toml::from_str::<Passwords>(&content)
-        .map(|p| p.passwords)
-        .unwrap_or_default();
-    encrypted_list
+// Every window of the session, in the order they were opened.
+fn capture_session(app: &gtk::Application, name: &str) -> Session {
+    let mut windows: Vec<(u32, gtk::Notebook)> = app
+        .windows()
         .into_iter()
-        .filter_map(|p| {
-            decrypt_password(&p.password, secret)
-                .ok()
-                .map(|plaintext| Password { name: p.name, password: plaintext })
+        .filter_map(|window| window.downcast::<gtk::ApplicationWindow>().ok())
+        .filter(|window| {
+            WINDOW_SESSIONS
+                .with_borrow(|sessions| sessions.get(&window.id()).is_some_and(|n| n == name))
         })
-        .collect()
+        .filter_map(|window| Some((window.id(), window_notebook(window.upcast_ref())?)))
+        .collect();
+    windows.sort_by_key(|(id, _)| *id);
+    Session {
+        windows: windows
+            .iter()
+            .map(|(_, notebook)| capture_session_window(notebook))
+            .collect(),
+        ..Session::default()
+    }
 }
 
-fn save_passwords(passwords: &[Password], secret: &str) {
-    let Some(path) = passwords_path() else { return };
-    if let Some(parent) = path.parent() {
-        let _ = fs::create_dir_all(parent);
+fn capture_session_window(notebook: &gtk::Notebook) -> SessionWindow {
+    let mut tabs = Vec::new();
+    for index in 0..notebook.n_pages() {
+        let Some(page) = notebook.nth_page(Some(index)) else { continue };
+        let Some(root) = page.first_child() else { continue };
+        let Some(pane) = capture_session_pane(&root) else { continue };
+        let title = notebook
+            .tab_label(&page)
+            .and_then(|tab_widget| find_tab_label(&tab_widget))
+            .map(|label| label.text().to_string())
+            .unwrap_or_default();
+        tabs.push(SessionTab { title, root: pane });
     }
-    let encrypted: Vec<Password> = passwords
-        .iter()
-        .filter_map(|p| {
-            encrypt_password(&p.password, secret)
-                .ok()
-                .map(|enc| Password { name: p.name.clone(), password: enc })
-        })
-        .collect();
-    let data = Passwords { passwords: encrypted };
-    if let Ok(content) = toml::to_string(&data) {
-        let _ = fs::write(&path, content);
+    SessionWindow {
+        active_tab: notebook.current_page(),
+        tabs,
     }
 }
 
-fn populate_password_list(list_box: &gtk::ListBox, passwords: &[Password]) {
-    while let Some(row) = list_box.row_at_index(0) {
-        list_box.remove(&row);
-    }
-    for pwd in passwords {
-        let label = gtk::Label::new(Some(&pwd.name));
-        label.set_xalign(0.0);
-        label.set_margin_top(6);
-        label.set_margin_bottom(6);
-        label.set_margin_start(8);
-        label.set_margin_end(8);
-        let row = gtk::ListBoxRow::new();
-        row.set_child(Some(&label));
-        list_box.append(&row);
+fn capture_session_pane(widget: &gtk::Widget) -> Option<SessionPane> {
+    if let Ok(paned) = widget.clone().downcast::<gtk::Paned>() {
+        let start = paned.start_child().and_then(|child| capture_session_pane(&child));
+        let end = paned.end_child().and_then(|child| capture_session_pane(&child));
+        return match (start, end) {
+            (Some(start), Some(end)) => Some(SessionPane::Split {
+                direction: split_direction_name(paned.orientation()).to_string(),
+                ratio: paned_ratio(&paned),
+                start: Box::new(start),
+                end: Box::new(end),
+            }),
+            (start, end) => start.or(end),
+        };
     }
-    if let Some(first) = list_box.row_at_index(0) {
-        list_box.select_row(Some(&first));
+
+    let terminal = find_terminal_in_widget(widget)?;
+    Some(SessionPane::Terminal {
+        cwd: terminal_working_directory(&terminal),
+        profile: terminal_profile(&terminal),
+    })
+}
+
+// Windows after the first are opened next to it, with the same options.
+fn restore_session_window(
+    app: &gtk::Application,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+    args: &CliArgs,
+    session_name: &str,
+    session: &SessionWindow,
+) {
+    if session.tabs.is_empty() {
+        return;
     }
+    let (window, notebook) = create_window(
+        app,
+        config,
+        counter,
+        args.theme_file.clone(),
+        session_name.to_string(),
+    );
+    let Some(terminal) = restore_session(&notebook, config, counter, session) else { return };
+    let theme = config.borrow().theme.as_ref().and_then(theme_from_source);
+    apply_tab_styles(&notebook, theme.as_ref(), Some(&terminal));
+    apply_initial_window_size(&window, &terminal, &config.borrow(), None);
+    window.present();
 }
 
-fn password_paste_selected(
-    dialog: &gtk::Dialog,
+fn restore_session(
     notebook: &gtk::Notebook,
-    list_box: &gtk::ListBox,
-    passwords: &Rc<RefCell<Vec<Password>>>,
-) {
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+    session: &SessionWindow,
+) -> Option<Terminal> {
+    let mut first_terminal = None;
+    for tab in &session.tabs {
+        let (root, terminal) = build_session_pane(&tab.root, config);
+        let title = Some(tab.title.as_str()).filter(|title| !title.is_empty());
+        append_tab(notebook, config, counter, &root, title);
+        first_terminal.get_or_insert(terminal);
+    }
+
+    let active = session.active_tab.unwrap_or(0);
+    if active < notebook.n_pages() {
+        notebook.set_current_page(Some(active));
+        focus_terminal_in_page(notebook, active);
+    }
+    first_terminal
+}
+
+fn build_session_pane(pane: &SessionPane, config: &Rc<RefCell<Config>>) -> (gtk::Widget, Terminal) {
+    match pane {
+        SessionPane::Terminal { cwd, profile } => {
+            let options = SpawnOptions {
+                cwd: cwd.clone(),
+                profile: profile.clone(),
+                ..SpawnOptions::default()
+            };
+            let terminal_widget = create_terminal_widget(&config.borrow(), &options);
+            attach_terminal_handlers(&terminal_widget.terminal, config);
+            (terminal_widget.scrolled.upcast(), terminal_widget.terminal)
+        }
+        SessionPane::Split {
+            direction,
+            ratio,
+            start,
+            end,
+        } => {
+            let orientation =
+                parse_split_direction(direction).unwrap_or(gtk::Orientation::Horizontal);
+            let (start, terminal) = build_session_pane(start, config);
+            let (end, _) = build_session_pane(end, config);
+            let paned = new_split_paned(orientation);
+            paned.set_start_child(Some(&start));
+            paned.set_end_child(Some(&end));
+            set_paned_ratio(&paned, *ratio);
+            (paned.upcast(), terminal)
+        }
+    }
+}
+
+// Where a theme comes from: a TOML file, or one of the themes built into
+// the binary.
+#[derive(Debug, Clone, PartialEq)]
+enum ThemeSource {
+    File(PathBuf),
+    Bundled(&'static str),
+}
+
+// Themes built into the binary, selectable with `theme = "name"`. They are
+// the theme files in `examples/themes`.
+const BUNDLED_THEMES: &[(&str, &str)] = &[
+    (
+        "catppuccin-latte",
+        include_str!("../examples/themes/catppuccin-latte.toml"),
+    ),
+    (
+        "catppuccin-frappe",
+        include_str!("../examples/themes/catppuccin-frappe.toml"),
+    ),
+    (
+        "catppuccin-macchiato",
+        include_str!("../examples/themes/catppuccin-macchiato.toml"),
+    ),
+    (
+        "catppuccin-mocha",
+        include_str!("../examples/themes/catppuccin-mocha.toml"),
+    ),
+    ("darcula", include_str!("../examples/themes/darcula.toml")),
+    ("dracula", include_str!("../examples/themes/dracula.toml")),
+    (
+        "gruvbox-dark",
+        include_str!("../examples/themes/gruvbox-dark.toml"),
+    ),
+    (
+        "gruvbox-light",
+        include_str!("../examples/themes/gruvbox-light.toml"),
+    ),
+    ("nord", include_str!("../examples/themes/nord.toml")),
+    (
+        "solarized-dark",
+        include_str!("../examples/themes/solarized-dark.toml"),
+    ),
+    (
+        "solarized-light",
+        include_str!("../examples/themes/solarized-light.toml"),
+    ),
+];
+
+#[derive(Debug, Clone)]
+struct Theme {
+    background: gdk::RGBA,
+    foreground: gdk::RGBA,
+    cursor: gdk::RGBA,
+    palette: [gdk::RGBA; 16],
+    tab_active_bg: Option<gdk::RGBA>,
+    tab_active_fg: Option<gdk::RGBA>,
+    tab_inactive_bg: Option<gdk::RGBA>,
+    tab_inactive_fg: Option<gdk::RGBA>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ThemeConfig {
+    background: String,
+    foreground: String,
+    cursor: String,
+    palette: Vec<String>,
+    tab_active_bg: Option<String>,
+    tab_active_fg: Option<String>,
+    tab_inactive_bg: Option<String>,
+    tab_inactive_fg: Option<String>,
+}
+
+fn apply_theme(terminal: &Terminal, theme: &Theme) {
+    let palette_refs: Vec<&gdk::RGBA> = theme.palette.iter().collect();
+    terminal.set_colors(
+        Some(&theme.foreground),
+        Some(&theme.background),
+        &palette_refs,
+    );
+    terminal.set_color_cursor(Some(&theme.cursor));
+}
+
+fn theme_from_source(source: &ThemeSource) -> Option<Theme> {
+    match source.load() {
+        Ok(theme) => Some(theme),
+        Err(err) => {
+            eprintln!("theme load failed: {err}");
+            None
+        }
+    }
+}
+
+impl ThemeSource {
+    fn load(&self) -> Result<Theme, String> {
+        match self {
+            ThemeSource::File(path) => load_theme(path),
+            ThemeSource::Bundled(name) => {
+                let contents = BUNDLED_THEMES
+                    .iter()
+                    .find(|(bundled, _)| bundled == name)
+                    .map(|(_, contents)| *contents)
+                    .unwrap_or_default();
+                parse_theme(name, contents)
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            ThemeSource::File(path) => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.display().to_string()),
+            ThemeSource::Bundled(name) => name.to_string(),
+        }
+    }
+
+    fn file(&self) -> Option<PathBuf> {
+        match self {
+            ThemeSource::File(path) => Some(path.clone()),
+            ThemeSource::Bundled(_) => None,
+        }
+    }
+}
+
+fn load_theme(path: &Path) -> Result<Theme, String> {
+    let raw = read_theme_config(path, None)?;
+    theme_from_config(&path.display().to_string(), raw)
+}
+
+// A Termilyon theme, or a color scheme of another terminal converted on the
+// fly.
+// `scheme` picks a color scheme from files holding several.
+fn read_theme_config(path: &Path, scheme: Option<&str>) -> Result<ThemeConfig, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    ThemeFormat::detect(path, &contents)
+        .parse(&contents, scheme)
+        .map_err(|err| format!("{}: {err}", path.display()))
+}
+
+// `origin` names the file or bundled theme in error messages.
+fn parse_theme(origin: &str, contents: &str) -> Result<Theme, String> {
+    let raw = ThemeFormat::Termilyon
+        .parse(contents, None)
+        .map_err(|err| format!("{origin}: {err}"))?;
+    theme_from_config(origin, raw)
+}
+
+fn theme_from_config(origin: &str, raw: ThemeConfig) -> Result<Theme, String> {
+    let colors = [&raw.background, &raw.foreground, &raw.cursor]
+        .into_iter()
+        .chain(&raw.palette)
+        .chain(raw.tab_active_bg.iter())
+        .chain(raw.tab_active_fg.iter())
+        .chain(raw.tab_inactive_bg.iter())
+        .chain(raw.tab_inactive_fg.iter());
+    for color in colors {
+        if !is_hex_color(color) {
+            return Err(format!(
+                "{origin}: invalid color \"{color}\", expected \"#rrggbb\""
+            ));
+        }
+    }
+    let palette = parse_palette(&raw.palette).ok_or_else(|| {
+        format!(
+            "{origin}: palette has {} colors, expected 16",
+            raw.palette.len()
+        )
+    })?;
+    Ok(Theme {
+        background: rgba(&raw.background),
+        foreground: rgba(&raw.foreground),
+        cursor: rgba(&raw.cursor),
+        palette,
+        tab_active_bg: raw.tab_active_bg.as_deref().map(rgba),
+        tab_active_fg: raw.tab_active_fg.as_deref().map(rgba),
+        tab_inactive_bg: raw.tab_inactive_bg.as_deref().map(rgba),
+        tab_inactive_fg: raw.tab_inactive_fg.as_deref().map(rgba),
+    })
+}
+
+fn is_hex_color(value: &str) -> bool {
+    let hex = value.trim_start_matches('#');
+    hex.len() == 6 && hex.chars().all(|ch| ch.is_ascii_hexdigit())
+}
+
+fn rgba(hex: &str) -> gdk::RGBA {
+    let hex = hex.trim_start_matches('#');
+    let (r, g, b) = match hex.len() {
+        6 => {
+            let r = u8::from_str_radix(&hex[0..2], 16).unwrap_or(0);
+            let g = u8::from_str_radix(&hex[2..4], 16).unwrap_or(0);
+            let b = u8::from_str_radix(&hex[4..6], 16).unwrap_or(0);
+            (r, g, b)
+        }
+        _ => (0, 0, 0),
+    };
+    gdk::RGBA::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0)
+}
+
+// Color scheme formats of other terminals that can be used as `theme_file`
+// or converted with `termilyon theme import`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ThemeFormat {
+    Termilyon,
+    ITerm2,
+    AlacrittyToml,
+    AlacrittyYaml,
+    Kitty,
+    WindowsTerminal,
+    Xresources,
+}
+
+impl ThemeFormat {
+    // Goes by the file extension, and by the contents for files without a
+    // known one (`~/.Xresources`, Kitty themes without `.conf`, ...).
+    fn detect(path: &Path, contents: &str) -> ThemeFormat {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_deref() {
+            Some("itermcolors") => return ThemeFormat::ITerm2,
+            Some("json") => return ThemeFormat::WindowsTerminal,
+            Some("yml" | "yaml") => return ThemeFormat::AlacrittyYaml,
+            Some("conf") => return ThemeFormat::Kitty,
+            Some("xresources" | "xdefaults") => return ThemeFormat::Xresources,
+            // Alacritty themes keep their colors in a `[colors]` table.
+            Some("toml") => {
+                let alacritty = toml::from_str::<toml::Table>(contents)
+                    .is_ok_and(|table| table.contains_key("colors"));
+                return if alacritty {
+                    ThemeFormat::AlacrittyToml
+                } else {
+                    ThemeFormat::Termilyon
+                };
+            }
+            _ => {}
+        }
+        if file_name.contains("xresources") || file_name.contains("xdefaults") {
+            return ThemeFormat::Xresources;
+        }
+
+        let trimmed = contents.trim_start();
+        if trimmed.starts_with("<?xml") || trimmed.starts_with("<plist") {
+            return ThemeFormat::ITerm2;
+        }
+        if trimmed.starts_with('{') {
+            return ThemeFormat::WindowsTerminal;
+        }
+        let lines = || contents.lines().map(str::trim);
+        if lines().any(|line| line.starts_with("colors:")) {
+            ThemeFormat::AlacrittyYaml
+        } else if lines().any(|line| line.contains('*') && line.contains("color0")) {
+            ThemeFormat::Xresources
+        } else if lines().any(|line| line.split_whitespace().next() == Some("color0")) {
+            ThemeFormat::Kitty
+        } else {
+            ThemeFormat::Termilyon
+        }
+    }
+
+    fn parse(self, contents: &str, scheme: Option<&str>) -> Result<ThemeConfig, String> {
+        if scheme.is_some() && self != ThemeFormat::WindowsTerminal {
+            return Err("only Windows Terminal files have named schemes".to_string());
+        }
+        let colors = match self {
+            ThemeFormat::Termilyon => {
+                return toml::from_str::<ThemeConfig>(contents)
+                    .map_err(|err| describe_toml_error(contents, &err));
+            }
+            ThemeFormat::ITerm2 => import_iterm2(contents)?,
+            ThemeFormat::AlacrittyToml => {
+                let table = toml::from_str::<toml::Table>(contents)
+                    .map_err(|err| describe_toml_error(contents, &err))?;
+                let mut values = BTreeMap::new();
+                flatten_toml(&table, "", &mut values);
+                import_alacritty(&values)?
+            }
+            ThemeFormat::AlacrittyYaml => import_alacritty(&flatten_yaml(contents))?,
+            ThemeFormat::Kitty => import_kitty(contents)?,
+            ThemeFormat::WindowsTerminal => import_windows_terminal(contents, scheme)?,
+            ThemeFormat::Xresources => import_xresources(contents)?,
+        };
+        colors.finish()
+    }
+}
+
+// Colors found by an importer, by Termilyon name: `background`,
+// `foreground`, `cursor`, `color0`..`color15` and the `tab_*` colors.
+#[derive(Default)]
+struct ImportedColors {
+    colors: HashMap<String, String>,
+}
+
+impl ImportedColors {
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match normalize_color(value) {
+            Some(color) => {
+                self.colors.insert(name.to_string(), color);
+                Ok(())
+            }
+            // Cursor colors like Kitty's `none` or Alacritty's
+            // `CellForeground` follow the text; the foreground is the
+            // closest match.
+            None if name == "cursor" => Ok(()),
+            None => Err(format!("invalid color \"{value}\" for {name}")),
+        }
+    }
+
+    fn finish(mut self) -> Result<ThemeConfig, String> {
+        let mut take = |name: &str| self.colors.remove(name);
+        let background = take("background").ok_or("no background color")?;
+        let foreground = take("foreground").ok_or("no foreground color")?;
+        let cursor = take("cursor").unwrap_or_else(|| foreground.clone());
+        let mut palette = Vec::new();
+        for index in 0..16 {
+            palette.push(take(&format!("color{index}")).ok_or(format!("no color{index}"))?);
+        }
+        Ok(ThemeConfig {
+            background,
+            foreground,
+            cursor,
+            palette,
+            tab_active_bg: take("tab_active_bg"),
+            tab_active_fg: take("tab_active_fg"),
+            tab_inactive_bg: take("tab_inactive_bg"),
+            tab_inactive_fg: take("tab_inactive_fg"),
+        })
+    }
+}
+
+// Accepts `#rrggbb`, `#rgb`, `0xrrggbb` and X11's `rgb:rr/gg/bb`; returns
+// `#rrggbb` in lowercase.
+fn normalize_color(value: &str) -> Option<String> {
+    let value = value.trim().trim_matches(|ch| ch == '"' || ch == '\'');
+    let hex = if let Some(rgb) = value.strip_prefix("rgb:") {
+        let parts: Vec<&str> = rgb.split('/').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        // X11 allows 1 to 4 hex digits per channel, scaled to 8 bits.
+        let mut hex = String::new();
+        for part in parts {
+            let channel = u16::from_str_radix(part, 16).ok()?;
+            let bits = 4 * part.len() as u32;
+            if !(4..=16).contains(&bits) {
+                return None;
+            }
+            let max = (1u32 << bits) - 1;
+            let scaled = (u32::from(channel) * 255 + max / 2) / max;
+            hex.push_str(&format!("{scaled:02x}"));
+        }
+        hex
+    } else {
+        let digits = value
+            .strip_prefix('#')
+            .or_else(|| value.strip_prefix("0x"))
+            .unwrap_or(value);
+        match digits.len() {
+            3 => digits.chars().flat_map(|ch| [ch, ch]).collect(),
+            _ => digits.to_string(),
+        }
+    };
+    let color = format!("#{}", hex.to_lowercase());
+    is_hex_color(&color).then_some(color)
+}
+
+const ANSI_COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+// iTerm2 `.itermcolors` files are plists with one dict of float color
+// components per color:
+//
+//   <key>Ansi 1 Color</key>
+//   <dict>
+//     <key>Blue Component</key>
+//     <real>0.3</real>
+//     ...
+//   </dict>
+fn import_iterm2(contents: &str) -> Result<ImportedColors, String> {
+    let mut colors = ImportedColors::default();
+    let mut rest = contents;
+    while let Some(start) = rest.find("<key>") {
+        rest = &rest[start + "<key>".len()..];
+        let Some(end) = rest.find("</key>") else { break };
+        let key = rest[..end].trim();
+        rest = rest[end + "</key>".len()..].trim_start();
+        if !rest.starts_with("<dict>") {
+            continue;
+        }
+        let Some(close) = rest.find("</dict>") else { break };
+        let body = &rest[..close];
+        rest = &rest[close..];
+
+        let name = match key {
+            "Background Color" => "background".to_string(),
+            "Foreground Color" => "foreground".to_string(),
+            "Cursor Color" => "cursor".to_string(),
+            _ => match key
+                .strip_prefix("Ansi ")
+                .and_then(|key| key.strip_suffix(" Color"))
+                .and_then(|index| index.parse::<u8>().ok())
+            {
+                Some(index) if index < 16 => format!("color{index}"),
+                _ => continue,
+            },
+        };
+        let component = |channel: &str| -> Result<u8, String> {
+            let value = plist_value(body, &format!("{channel} Component"))
+                .and_then(|value| value.parse::<f64>().ok())
+                .ok_or_else(|| format!("{key}: missing {channel} Component"))?;
+            Ok((value.clamp(0.0, 1.0) * 255.0).round() as u8)
+        };
+        let (r, g, b) = (component("Red")?, component("Green")?, component("Blue")?);
+        colors.set(&name, &format!("#{r:02x}{g:02x}{b:02x}"))?;
+    }
+    Ok(colors)
+}
+
+// The text of the element after `<key>key</key>` in a plist dict.
+fn plist_value<'a>(body: &'a str, key: &str) -> Option<&'a str> {
+    let start = body.find(&format!("<key>{key}</key>"))? + key.len() + "<key></key>".len();
+    let rest = body[start..].trim_start();
+    let open_end = rest.find('>')?;
+    let value = &rest[open_end + 1..];
+    let close = value.find('<')?;
+    Some(value[..close].trim())
+}
+
+// Alacritty keeps its colors in `colors.primary`, `colors.cursor`,
+// `colors.normal` and `colors.bright`, by color name.
+fn import_alacritty(values: &BTreeMap<String, String>) -> Result<ImportedColors, String> {
+    let mut colors = ImportedColors::default();
+    let get = |key: &str| values.get(&format!("colors.{key}"));
+    if let Some(value) = get("primary.background") {
+        colors.set("background", value)?;
+    }
+    if let Some(value) = get("primary.foreground") {
+        colors.set("foreground", value)?;
+    }
+    if let Some(value) = get("cursor.cursor") {
+        colors.set("cursor", value)?;
+    }
+    for (index, name) in ANSI_COLOR_NAMES.iter().enumerate() {
+        if let Some(value) = get(&format!("normal.{name}")) {
+            colors.set(&format!("color{index}"), value)?;
+        }
+        if let Some(value) = get(&format!("bright.{name}")) {
+            colors.set(&format!("color{}", index + 8), value)?;
+        }
+    }
+    Ok(colors)
+}
+
+fn flatten_toml(table: &toml::Table, prefix: &str, values: &mut BTreeMap<String, String>) {
+    for (key, value) in table {
+        let path = format!("{prefix}{key}");
+        match value {
+            toml::Value::String(text) => {
+                values.insert(path, text.clone());
+            }
+            toml::Value::Table(table) => flatten_toml(table, &format!("{path}."), values),
+            _ => {}
+        }
+    }
+}
+
+// Just enough YAML for Alacritty color schemes: nested `key: value`
+// mappings, quoted or plain scalars and `#` comments.
+fn flatten_yaml(contents: &str) -> BTreeMap<String, String> {
+    let mut values = BTreeMap::new();
+    // (indent, key) of the enclosing mappings
+    let mut parents: Vec<(usize, String)> = Vec::new();
+    for line in contents.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("---") {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        let Some((key, value)) = trimmed.split_once(':') else { continue };
+        while parents.last().is_some_and(|(parent, _)| *parent >= indent) {
+            parents.pop();
+        }
+        let key = key
+            .trim()
+            .trim_matches(|ch| ch == '"' || ch == '\'')
+            .to_string();
+        let value = value.trim();
+        // A `#` after whitespace starts a comment unless it is quoted.
+        let value = if value.starts_with(['"', '\'']) {
+            let quote = value.chars().next().unwrap_or('"');
+            value[1..].split(quote).next().unwrap_or_default()
+        } else {
+            value.split(" #").next().unwrap_or_default().trim()
+        };
+        if value.is_empty() {
+            parents.push((indent, key));
+            continue;
+        }
+        let path: Vec<&str> = parents
+            .iter()
+            .map(|(_, parent)| parent.as_str())
+            .chain([key.as_str()])
+            .collect();
+        values.insert(path.join("."), value.to_string());
+    }
+    values
+}
+
+// Kitty themes are `name value` lines using the same color names as
+// Termilyon, plus its tab bar colors.
+fn import_kitty(contents: &str) -> Result<ImportedColors, String> {
+    let mut colors = ImportedColors::default();
+    for line in contents.lines() {
+        let mut parts = line.split_whitespace();
+        let (Some(key), Some(value)) = (parts.next(), parts.next()) else { continue };
+        let name = match key {
+            "active_tab_background" => "tab_active_bg",
+            "active_tab_foreground" => "tab_active_fg",
+            "inactive_tab_background" => "tab_inactive_bg",
+            "inactive_tab_foreground" => "tab_inactive_fg",
+            "background" | "foreground" | "cursor" => key,
+            _ if is_palette_name(key) => key,
+            _ => continue,
+        };
+        colors.set(name, value)?;
+    }
+    Ok(colors)
+}
+
+fn is_palette_name(name: &str) -> bool {
+    name.strip_prefix("color")
+        .and_then(|index| index.parse::<u8>().ok())
+        .is_some_and(|index| index < 16)
+}
+
+// A Windows Terminal scheme object, or a whole settings.json whose first
+// entry in `schemes` is used.
+fn import_windows_terminal(contents: &str, name: Option<&str>) -> Result<ImportedColors, String> {
+    let json: serde_json::Value =
+        serde_json::from_str(&strip_json_comments(contents)).map_err(|err| err.to_string())?;
+    let scheme_name = |scheme: &serde_json::Value| {
+        scheme
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let schemes = match json.get("schemes") {
+        Some(schemes) => schemes
+            .as_array()
+            .ok_or("`schemes` is not a list")?
+            .as_slice(),
+        None => std::slice::from_ref(&json),
+    };
+    let names = || {
+        let names: Vec<String> = schemes.iter().map(scheme_name).collect();
+        names.join(", ")
+    };
+    let scheme = match (name, schemes) {
+        (_, []) => return Err("settings.json has no color schemes".to_string()),
+        (Some(name), schemes) => schemes
+            .iter()
+            .find(|scheme| scheme_name(scheme) == name)
+            .ok_or_else(|| format!("no scheme named `{name}`; available: {}", names()))?,
+        (None, [scheme]) => scheme,
+        (None, _) => {
+            return Err(format!(
+                "several color schemes, choose one with --scheme: {}",
+                names()
+            ));
+        }
+    };
+    let mut colors = ImportedColors::default();
+    let mut set = |name: &str, key: &str| -> Result<(), String> {
+        match scheme.get(key).and_then(serde_json::Value::as_str) {
+            Some(value) => colors.set(name, value),
+            None => Ok(()),
+        }
+    };
+    set("background", "background")?;
+    set("foreground", "foreground")?;
+    set("cursor", "cursorColor")?;
+    // Windows Terminal calls magenta purple.
+    for (index, name) in ANSI_COLOR_NAMES.iter().enumerate() {
+        let name = if *name == "magenta" { "purple" } else { name };
+        set(&format!("color{index}"), name)?;
+        let bright = format!("bright{}{}", name[..1].to_uppercase(), &name[1..]);
+        set(&format!("color{}", index + 8), &bright)?;
+    }
+    Ok(colors)
+}
+
+// Windows Terminal writes settings.json as JSON with comments and
+// trailing commas. Comments become spaces so error positions still match.
+fn strip_json_comments(contents: &str) -> String {
+    let mut stripped = String::with_capacity(contents.len());
+    let mut chars = contents.chars().peekable();
+    let mut in_string = false;
+    while let Some(ch) = chars.next() {
+        if in_string {
+            stripped.push(ch);
+            match ch {
+                '\\' => stripped.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (ch, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                stripped.push(ch);
+            }
+            ('/', Some('/')) => {
+                stripped.push(' ');
+                while let Some(ch) = chars.next_if(|ch| *ch != '\n') {
+                    stripped.push(if ch == '\t' { ch } else { ' ' });
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                stripped.push_str("  ");
+                let mut previous = ' ';
+                for ch in chars.by_ref() {
+                    stripped.push(if ch == '\n' { ch } else { ' ' });
+                    if previous == '*' && ch == '/' {
+                        break;
+                    }
+                    previous = ch;
+                }
+            }
+            _ => stripped.push(ch),
+        }
+    }
+
+    // A comma followed only by whitespace before `}` or `]` is dropped.
+    let mut bytes = stripped.into_bytes();
+    let mut in_string = false;
+    let mut escaped = false;
+    for index in 0..bytes.len() {
+        match bytes[index] {
+            _ if escaped => escaped = false,
+            b'\\' if in_string => escaped = true,
+            b'"' => in_string = !in_string,
+            b',' if !in_string => {
+                let next = bytes[index + 1..]
+                    .iter()
+                    .find(|byte| !byte.is_ascii_whitespace());
+                if matches!(next, Some(b'}' | b']')) {
+                    bytes[index] = b' ';
+                }
+            }
+            _ => {}
+        }
+    }
+    String::from_utf8(bytes).unwrap_or_default()
+}
+
+// `*.color0: #282828`, `URxvt*background: ...` and the like. `#define`
+// names are substituted, as many published schemes use them.
+fn import_xresources(contents: &str) -> Result<ImportedColors, String> {
+    let mut defines: HashMap<&str, &str> = HashMap::new();
+    let mut colors = ImportedColors::default();
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('!') {
+            continue;
+        }
+        if let Some(define) = line.strip_prefix("#define") {
+            let mut parts = define.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                defines.insert(name, value);
+            }
+            continue;
+        }
+        let Some((resource, value)) = line.split_once(':') else { continue };
+        let key = resource
+            .rsplit(['*', '.'])
+            .next()
+            .unwrap_or_default()
+            .trim();
+        let name = match key {
+            "background" | "foreground" => key,
+            "cursorColor" => "cursor",
+            _ if is_palette_name(key) => key,
+            _ => continue,
+        };
+        let value = value.trim();
+        colors.set(name, defines.get(value).copied().unwrap_or(value))?;
+    }
+    Ok(colors)
+}
+
+// Writes a theme in the layout of the bundled theme files.
+fn format_theme_config(theme: &ThemeConfig) -> String {
+    let mut text = format!(
+        "background = \"{}\"\nforeground = \"{}\"\ncursor = \"{}\"\npalette = [\n",
+        theme.background, theme.foreground, theme.cursor
+    );
+    let palette: Vec<String> = theme
+        .palette
+        .iter()
+        .map(|color| format!("  \"{color}\""))
+        .collect();
+    text.push_str(&palette.join(",\n"));
+    text.push_str("\n]\n");
+    let tab_colors = [
+        ("tab_active_bg", &theme.tab_active_bg),
+        ("tab_active_fg", &theme.tab_active_fg),
+        ("tab_inactive_bg", &theme.tab_inactive_bg),
+        ("tab_inactive_fg", &theme.tab_inactive_fg),
+    ];
+    for (key, color) in tab_colors {
+        if let Some(color) = color {
+            text.push_str(&format!("{key} = \"{color}\"\n"));
+        }
+    }
+    text
+}
+
+fn run_theme_import(file: &Path, output: Option<&Path>, scheme: Option<&str>) -> i32 {
+    let imported = read_theme_config(file, scheme).and_then(|raw| {
+        theme_from_config(&file.display().to_string(), raw.clone())?;
+        Ok(raw)
+    });
+    let raw = match imported {
+        Ok(raw) => raw,
+        Err(err) => {
+            eprintln!("{err}");
+            return 1;
+        }
+    };
+    let text = format_theme_config(&raw);
+    let Some(output) = output else {
+        print!("{text}");
+        return 0;
+    };
+    match fs::write(output, text) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{}: {err}", output.display());
+            1
+        }
+    }
+}
+
+fn apply_tab_styles(
+    notebook: &gtk::Notebook,
+    theme: Option<&Theme>,
+    terminal: Option<&Terminal>,
+) {
+    let background = terminal
+        .map(|terminal| terminal.color_background_for_draw())
+        .or_else(|| theme.map(|theme| theme.background.clone()));
+    let Some(background) = background else { return };
+
+    let base_fg = theme
+        .map(|theme| theme.foreground.clone())
+        .unwrap_or_else(|| contrast_text_color(&background));
+
+    let active_bg = theme
+        .and_then(|theme| theme.tab_active_bg.clone())
+        .unwrap_or_else(|| background.clone());
+    let inactive_bg = theme
+        .and_then(|theme| theme.tab_inactive_bg.clone())
+        .unwrap_or_else(|| adjust_luma(&background, 0.92));
+
+    let active_fg = theme
+        .and_then(|theme| theme.tab_active_fg.clone())
+        .unwrap_or_else(|| base_fg.clone());
+    let inactive_fg = theme
+        .and_then(|theme| theme.tab_inactive_fg.clone())
+        .unwrap_or_else(|| with_alpha(&base_fg, 0.7));
+
+    let mut css = format!(
+        ".terminal-tabs > header {{ background-color: {}; }}",
+        background.to_str()
+    );
+    css.push_str(&format!(
+        ".terminal-tabs tab {{ background-color: {}; background-image: none; border-image: none; }}",
+        inactive_bg.to_str()
+    ));
+    css.push_str(&format!(
+        ".terminal-tabs tab:checked {{ background-color: {}; background-image: none; border-image: none; }}",
+        active_bg.to_str()
+    ));
+    css.push_str(".terminal-tabs tab > * { background-color: transparent; }");
+    css.push_str(&format!(
+        ".terminal-header {{ background: none; box-shadow: none; min-height: 0; color: {}; }}",
+        base_fg.to_str()
+    ));
+    css.push_str(&format!(
+        ".terminal-tabs tab label, .terminal-tabs tab button {{ color: {}; }}",
+        inactive_fg.to_str()
+    ));
+    css.push_str(&format!(
+        ".terminal-tabs tab:checked label, .terminal-tabs tab:checked button {{ color: {}; }}",
+        active_fg.to_str()
+    ));
+
+    let provider = gtk::CssProvider::new();
+    provider.load_from_data(&css);
+    if let Some(display) = gdk::Display::default() {
+        gtk::style_context_add_provider_for_display(
+            &display,
+            &provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        );
+    }
+    notebook.queue_draw();
+}
+
+fn reload_config_and_theme(
+    config: &Rc<RefCell<Config>>,
+    mut updated: Config,
+    notebooks: &[gtk::Notebook],
+    theme_override: Option<&PathBuf>,
+) {
+    // A config with errors is not applied; the last good one stays active
+    // until the file is fixed.
+    let has_errors = updated
+        .problems
+        .iter()
+        .any(|problem| problem.severity == Severity::Error);
+    if has_errors {
+        for window in notebooks
+            .iter()
+            .filter_map(|notebook| find_root_window(notebook.upcast_ref()))
+        {
+            let files = config_problem_files(&updated);
+            show_config_problems(
+                &window,
+                &format!("{files} not reloaded, keeping the previous settings:"),
+                &updated.problems,
+            );
+        }
+        return;
+    }
+    if let Some(path) = theme_override {
+        updated.theme = Some(ThemeSource::File(path.clone()));
+    }
+    let theme = updated.theme.as_ref().and_then(theme_from_source);
+    *config.borrow_mut() = updated.clone();
+
+    for notebook in notebooks {
+        if let Some(window) = find_root_window(notebook.upcast_ref()) {
+            let heading = format!("Problems in {}:", config_problem_files(&updated));
+            show_config_problems(&window, &heading, &updated.problems);
+        }
+        apply_config_to_window(notebook, &updated, theme.as_ref());
+    }
+}
+
+fn apply_config_to_window(notebook: &gtk::Notebook, config: &Config, theme: Option<&Theme>) {
+    // Windows with a header bar in the tab strip keep their tabs on top.
+    if notebook.action_widget(gtk::PackType::End).is_none() {
+        notebook.set_tab_pos(config.tab_bar_position);
+    }
+    let sample_terminal = find_first_terminal_in_notebook(notebook);
+    apply_tab_styles(notebook, theme, sample_terminal.as_ref());
+    apply_config_to_terminals(notebook, config, theme);
+}
+
+// Lists config problems in a banner at the top of the window, replacing the
+// previous one. The banner is removed once the config is clean again.
+fn show_config_problems(window: &gtk::Window, heading: &str, problems: &[ConfigProblem]) {
+    let Some(overlay) = window.child().and_downcast::<gtk::Overlay>() else { return };
+    let mut child = overlay.first_child();
+    while let Some(widget) = child {
+        child = widget.next_sibling();
+        if widget.widget_name() == CONFIG_NOTICE_NAME {
+            overlay.remove_overlay(&widget);
+        }
+    }
+    if problems.is_empty() {
+        return;
+    }
+
+    let notice = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+    notice.set_widget_name(CONFIG_NOTICE_NAME);
+    notice.add_css_class("osd");
+    notice.set_valign(gtk::Align::Start);
+    notice.set_margin_top(12);
+    notice.set_margin_start(12);
+    notice.set_margin_end(12);
+
+    let lines: Vec<String> = problems.iter().map(format_config_problem).collect();
+    let label = gtk::Label::new(Some(&format!("{heading}\n{}", lines.join("\n"))));
+    label.set_xalign(0.0);
+    label.set_hexpand(true);
+    label.set_wrap(true);
+    label.set_selectable(true);
+    let close_button = gtk::Button::from_icon_name("window-close-symbolic");
+    close_button.add_css_class("flat");
+    close_button.set_valign(gtk::Align::Start);
+    notice.append(&label);
+    notice.append(&close_button);
+
+    let overlay_clone = overlay.clone();
+    let notice_clone = notice.clone();
+    close_button.connect_clicked(move |_| {
+        overlay_clone.remove_overlay(&notice_clone);
+    });
+    overlay.add_overlay(&notice);
+}
+
+// Names the files the problems come from for the banner heading. Problems
+// without a file, such as conflicting keybindings, count for the main one.
+fn config_problem_files(config: &Config) -> String {
+    let Some(main) = config.files.first() else {
+        return "config.toml".to_string();
+    };
+    let names: Vec<String> = config
+        .files
+        .iter()
+        .filter(|path| {
+            config.problems.iter().any(|problem| match &problem.file {
+                Some(file) => file == *path,
+                None => path == &main,
+            })
+        })
+        .map(|path| match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => path.display().to_string(),
+        })
+        .collect();
+    names.join(", ")
+}
+
+fn format_config_problem(problem: &ConfigProblem) -> String {
+    let severity = match problem.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    match (&problem.file, problem.position) {
+        (Some(file), Some((line, column))) => format!(
+            "{}:{line}:{column}: {severity}: {}",
+            file.display(),
+            problem.message
+        ),
+        (Some(file), None) => format!("{}: {severity}: {}", file.display(), problem.message),
+        (None, _) => format!("{severity}: {}", problem.message),
+    }
+}
+
+// `--check-config`: prints every problem and fails if any is an error.
+fn run_config_check() -> i32 {
+    let config = Config::load();
+    let path = config_path()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "config.toml".to_string());
+    for problem in &config.problems {
+        eprintln!("{}", format_config_problem(problem));
+    }
+    if config
+        .problems
+        .iter()
+        .any(|problem| problem.severity == Severity::Error)
+    {
+        return 1;
+    }
+    println!("{path}: ok");
+    0
+}
+
+fn watch_config_files(
+    app: &gtk::Application,
+    config: &Rc<RefCell<Config>>,
+    theme_override: Option<PathBuf>,
+) {
+    let watcher = Rc::new(ConfigWatcher {
+        app: app.clone(),
+        config: config.clone(),
+        theme_override,
+        monitors: RefCell::new(Vec::new()),
+        pending: RefCell::new(None),
+    });
+    watcher.watch();
+}
+
+impl ConfigWatcher {
+    // Each monitor holds on to the watcher, which keeps it alive for as long
+    // as the application runs. Watching again drops the old monitors.
+    fn watch(self: &Rc<Self>) {
+        let mut files: Vec<PathBuf> = config_path().into_iter().collect();
+        {
+            let config = self.config.borrow();
+            files.extend(config.files.iter().cloned());
+            files.extend(config.theme.as_ref().and_then(ThemeSource::file));
+            files.extend(
+                config
+                    .profiles
+                    .values()
+                    .filter_map(|profile| profile.theme_source.as_ref()?.file()),
+            );
+        }
+        files.sort();
+        files.dedup();
+
+        let mut monitors = Vec::new();
+        for path in files {
+            let file = gtk::gio::File::for_path(&path);
+            let monitor = match file.monitor_file(
+                gtk::gio::FileMonitorFlags::NONE,
+                None::<&gtk::gio::Cancellable>,
+            ) {
+                Ok(monitor) => monitor,
+                Err(err) => {
+                    eprintln!("cannot watch {}: {err}", path.display());
+                    continue;
+                }
+            };
+            let watcher = self.clone();
+            monitor.connect_changed(move |_, _, _, event| {
+                let content_changed = !matches!(
+                    event,
+                    gtk::gio::FileMonitorEvent::AttributeChanged
+                        | gtk::gio::FileMonitorEvent::PreUnmount
+                        | gtk::gio::FileMonitorEvent::Unmounted
+                );
+                if content_changed {
+                    watcher.schedule_reload();
+                }
+            });
+            monitors.push(monitor);
+        }
+        *self.monitors.borrow_mut() = monitors;
+    }
+
+    fn schedule_reload(self: &Rc<Self>) {
+        if let Some(source) = self.pending.take() {
+            source.remove();
+        }
+        let watcher = self.clone();
+        let source = gtk::glib::timeout_add_local_once(CONFIG_RELOAD_DELAY, move || {
+            watcher.pending.take();
+            // `auto_reload` is read from the files just saved, so switching
+            // it back on takes effect without a manual reload.
+            let updated = Config::load();
+            if updated.auto_reload {
+                reload_config_and_theme(
+                    &watcher.config,
+                    updated,
+                    &app_notebooks(&watcher.app),
+                    watcher.theme_override.as_ref(),
+                );
+            }
+            // The theme file may have changed.
+            watcher.watch();
+        });
+        *self.pending.borrow_mut() = Some(source);
+    }
+}
+
+fn app_notebooks(app: &gtk::Application) -> Vec<gtk::Notebook> {
+    app.windows().iter().filter_map(window_notebook).collect()
+}
+
+fn find_first_terminal_in_notebook(notebook: &gtk::Notebook) -> Option<Terminal> {
+    for index in 0..notebook.n_pages() {
+        let Some(page) = notebook.nth_page(Some(index)) else { continue };
+        if let Some(terminal) = find_terminal_in_widget(&page) {
+            return Some(terminal);
+        }
+    }
+    None
+}
+
+fn apply_config_to_terminals(
+    notebook: &gtk::Notebook,
+    config: &Config,
+    theme: Option<&Theme>,
+) {
+    for index in 0..notebook.n_pages() {
+        let Some(page) = notebook.nth_page(Some(index)) else { continue };
+        let mut terminals = Vec::new();
+        collect_terminals(&page, &mut terminals);
+        for terminal in terminals {
+            // Refresh the marker color, which may come from the profile.
+            // Panes of a profile that was removed fall back to the global
+            // config and lose their marker.
+            let profile =
+                terminal_profile(&terminal).filter(|profile| config.profiles.contains_key(profile));
+            set_terminal_profile(&terminal, config, profile.as_deref());
+            apply_config_to_terminal(&terminal, config, theme);
+        }
+        update_profile_marker(&page);
+    }
+}
+
+// Applies the global config, or the one of the terminal's profile, to a
+// single terminal. `theme` is the global theme.
+fn apply_config_to_terminal(terminal: &Terminal, config: &Config, theme: Option<&Theme>) {
+    let profile = terminal_profile(terminal);
+    let profile_config = config.with_profile(profile.as_deref());
+    let config = &profile_config;
+    terminal.set_scrollback_lines(config.scrollback_lines.into());
+    let mut font_desc = gtk::pango::FontDescription::from_string(&config.font);
+    if config.font_size > 0 {
+        font_desc.set_size(config.font_size * gtk::pango::SCALE);
+    }
+    terminal.set_font(Some(&font_desc));
+    // Panes of a profile with its own theme load that one instead.
+    let profile_theme = profile
+        .as_deref()
+        .and_then(|name| config.profiles.get(name))
+        .and_then(|profile| profile.theme_source.as_ref())
+        .and_then(theme_from_source);
+    match profile_theme.as_ref().or(theme) {
+        Some(theme) => apply_theme(terminal, theme),
+        // Back to VTE's colors, e.g. after leaving a profile with a theme.
+        None => terminal.set_colors(None, None, &[]),
+    }
+    register_hyperlink_matches(terminal, config);
+}
+
+fn collect_terminals(widget: &gtk::Widget, terminals: &mut Vec<Terminal>) {
+    if let Ok(terminal) = widget.clone().downcast::<Terminal>() {
+        terminals.push(terminal);
+        return;
+    }
+    if let Ok(scrolled) = widget.clone().downcast::<gtk::ScrolledWindow>() {
+        if let Some(child) = scrolled.child() {
+            collect_terminals(&child, terminals);
+        }
+        return;
+    }
+    if let Ok(paned) = widget.clone().downcast::<gtk::Paned>() {
+        if let Some(child) = paned.start_child() {
+            collect_terminals(&child, terminals);
+        }
+        if let Some(child) = paned.end_child() {
+            collect_terminals(&child, terminals);
+        }
+        return;
+    }
+
+    let mut child = widget.first_child();
+    while let Some(node) = child {
+        collect_terminals(&node, terminals);
+        child = node.next_sibling();
+    }
+}
+
+// Clicking a row captures the next key combination for its action;
+// Backspace removes the binding and Escape cancels. Conflicts are shown
+// while editing and the changes are written to `[keybindings]` on save.
+fn show_keybindings_dialog(context: &WindowContext) {
+    let config = context.config.borrow();
+    let dialog = gtk::Dialog::new();
+    dialog.set_title(Some("Keybindings"));
+    dialog.set_modal(true);
+    dialog.set_transient_for(Some(&context.window));
+    dialog.set_default_size(520, 560);
+    dialog.add_button("Reset All", gtk::ResponseType::Reject);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    let save_button = dialog.add_button("Save", gtk::ResponseType::Accept);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_margin_top(12);
+    content.set_margin_bottom(12);
+    content.set_margin_start(12);
+    content.set_margin_end(12);
+
+    let hint = gtk::Label::new(Some(
+        "Click an action and press keys to add a binding; a chord of several keys ends after \
+         a pause or with Enter. Backspace removes all of the action's bindings, Escape cancels.",
+    ));
+    hint.set_wrap(true);
+    hint.set_xalign(0.0);
+    hint.add_css_class("dim-label");
+    content.append(&hint);
+
+    let list_box = gtk::ListBox::new();
+    list_box.set_selection_mode(gtk::SelectionMode::Single);
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_child(Some(&list_box));
+    scrolled.set_vexpand(true);
+    scrolled.set_min_content_height(320);
+    content.append(&scrolled);
+
+    // Every action of the defaults is listed, also when it is unbound, so
+    // it can be bound again.
+    let defaults = default_keybindings();
+    let mut bindings = config.keybindings.clone();
+    for (action, _) in &defaults.entries {
+        if !bindings.entries.iter().any(|(known, _)| known == action) {
+            bindings.entries.push((action.clone(), Vec::new()));
+        }
+    }
+    let mut rows = Vec::new();
+    for (action, _) in &bindings.entries {
+        let (row, shortcut, reset) = add_keybinding_row(&list_box, action);
+        rows.push((row, shortcut, reset));
+    }
+
+    let problems = gtk::Label::new(None);
+    problems.set_wrap(true);
+    problems.set_xalign(0.0);
+    problems.set_selectable(true);
+    content.append(&problems);
+
+    let editor = Rc::new(KeybindingEditor {
+        loaded: config.keybindings.clone(),
+        bindings: RefCell::new(bindings),
+        defaults,
+        includes: config.files.len() > 1,
+        chord_timeout: Duration::from_millis(config.chord_timeout_ms.into()),
+        capturing: Cell::new(None),
+        pending: RefCell::new(Vec::new()),
+        pending_timeout: RefCell::new(None),
+        rows,
+        problems,
+        save_button,
+    });
+    editor.refresh();
+    drop(config);
+
+    for (index, (_, _, reset)) in editor.rows.iter().enumerate() {
+        let weak_editor = Rc::downgrade(&editor);
+        reset.connect_clicked(move |_| {
+            if let Some(editor) = weak_editor.upgrade() {
+                editor.reset(Some(index));
+            }
+        });
+    }
+    {
+        let weak_editor = Rc::downgrade(&editor);
+        list_box.connect_row_activated(move |_, row| {
+            let Some(editor) = weak_editor.upgrade() else { return };
+            editor.finish_capture(false);
+            editor.capturing.set(usize::try_from(row.index()).ok());
+            editor.refresh();
+        });
+    }
+
+    // Keys are taken before the list sees them, so arrows or Enter can be
+    // bound as well.
+    let ctrl = gtk::EventControllerKey::new();
+    ctrl.set_propagation_phase(gtk::PropagationPhase::Capture);
+    {
+        let weak_editor = Rc::downgrade(&editor);
+        ctrl.connect_key_pressed(move |_, key, _, state| {
+            let Some(editor) = weak_editor.upgrade() else {
+                return gtk::glib::Propagation::Proceed;
+            };
+            if editor.capture(key, state) {
+                gtk::glib::Propagation::Stop
+            } else {
+                gtk::glib::Propagation::Proceed
+            }
+        });
+    }
+    dialog.add_controller(ctrl);
+
+    let context = context.clone();
+    dialog.connect_response(move |dialog, response| match response {
+        gtk::ResponseType::Reject => editor.reset(None),
+        gtk::ResponseType::Accept => match editor.save() {
+            Ok(()) => {
+                dialog.close();
+                run_action(&context, &Action::ReloadConfig);
+            }
+            Err(err) => editor
+                .problems
+                .set_text(&format!("Could not save the keybindings: {err}")),
+        },
+        _ => dialog.close(),
+    });
+    dialog.present();
+}
+
+fn add_keybinding_row(
+    list_box: &gtk::ListBox,
+    action: &Action,
+) -> (gtk::ListBoxRow, gtk::Box, gtk::Button) {
+    let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+    row_box.set_margin_top(4);
+    row_box.set_margin_bottom(4);
+    row_box.set_margin_start(8);
+    row_box.set_margin_end(4);
+    let label = gtk::Label::new(Some(&action.label()));
+    label.set_xalign(0.0);
+    label.set_hexpand(true);
+    // Filled by `KeybindingEditor::refresh`.
+    let shortcut = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let reset = gtk::Button::from_icon_name("edit-undo-symbolic");
+    reset.add_css_class("flat");
+    reset.set_focusable(false);
+    reset.set_tooltip_text(Some("Reset to default"));
+    row_box.append(&label);
+    row_box.append(&shortcut);
+    row_box.append(&reset);
+    let row = gtk::ListBoxRow::new();
+    // The tooltip shows the name to use in `[keybindings]`.
+    row.set_tooltip_text(Some(&action.name()));
+    row.set_child(Some(&row_box));
+    list_box.append(&row);
+    (row, shortcut, reset)
+}
+
+// The keybindings dialog while bindings are being edited. Rows are in the
+// order of `bindings.entries`.
+struct KeybindingEditor {
+    loaded: KeyBindings,
+    bindings: RefCell<KeyBindings>,
+    defaults: KeyBindings,
+    // Whether config.toml includes other files, which may bind keys too.
+    includes: bool,
+    chord_timeout: Duration,
+    capturing: Cell<Option<usize>>,
+    // Keys pressed so far for the binding being captured.
+    pending: RefCell<KeyChord>,
+    pending_timeout: RefCell<Option<gtk::glib::SourceId>>,
+    rows: Vec<(gtk::ListBoxRow, gtk::Box, gtk::Button)>,
+    problems: gtk::Label,
+    save_button: gtk::Widget,
+}
+
+impl KeybindingEditor {
+    // Every binding is shown as a button that removes it.
+    fn refresh(self: &Rc<Self>) {
+        let bindings = self.bindings.borrow();
+        for (index, ((action, chords), (_, shortcuts, reset))) in
+            bindings.entries.iter().zip(&self.rows).enumerate()
+        {
+            while let Some(child) = shortcuts.first_child() {
+                shortcuts.remove(&child);
+            }
+            for (position, chord) in chords.iter().enumerate() {
+                let conflict = bindings
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .flat_map(|(_, (_, others))| others)
+                    .any(|other| {
+                        chord_starts_with(chord, other) || chord_starts_with(other, chord)
+                    });
+                let content = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+                content.append(&gtk::Label::new(Some(&format_key_chord(chord))));
+                content.append(&gtk::Image::from_icon_name("window-close-symbolic"));
+                let button = gtk::Button::new();
+                button.set_child(Some(&content));
+                button.add_css_class("flat");
+                button.set_focusable(false);
+                button.set_tooltip_text(Some("Remove this binding"));
+                if conflict {
+                    button.add_css_class("error");
+                }
+                let weak_editor = Rc::downgrade(self);
+                button.connect_clicked(move |_| {
+                    if let Some(editor) = weak_editor.upgrade() {
+                        editor.remove_binding(index, position);
+                    }
+                });
+                shortcuts.append(&button);
+            }
+            let status = if self.capturing.get() == Some(index) {
+                let pending = self.pending.borrow();
+                if pending.is_empty() {
+                    Some("Press keys...".to_string())
+                } else {
+                    Some(format!("{}, ...", format_key_chord(&pending)))
+                }
+            } else if chords.is_empty() {
+                Some("Unbound".to_string())
+            } else {
+                None
+            };
+            if let Some(status) = status {
+                let label = gtk::Label::new(Some(&status));
+                label.add_css_class("dim-label");
+                shortcuts.append(&label);
+            }
+            reset.set_visible(chords.as_slice() != self.defaults.bindings(action));
+        }
+
+        let configured: Vec<Action> = bindings
+            .entries
+            .iter()
+            .filter(|(action, chords)| chords.as_slice() != self.defaults.bindings(action))
+            .map(|(action, _)| action.clone())
+            .collect();
+        let problems = check_keybindings(&bindings, &configured, &ConfigLocations::new());
+        let messages: Vec<String> = problems.iter().map(format_config_problem).collect();
+        self.problems.set_text(&messages.join("\n"));
+        self.problems.set_visible(!problems.is_empty());
+        let has_errors = problems
+            .iter()
+            .any(|problem| problem.severity == Severity::Error);
+        self.save_button.set_sensitive(!has_errors);
+    }
+
+    // Returns whether the key was used for the row being captured. Keys
+    // are collected into a chord until Enter or a pause of the chord
+    // timeout; the chord is added to the action's other bindings.
+    fn capture(self: &Rc<Self>, key: gdk::Key, state: gdk::ModifierType) -> bool {
+        let Some(index) = self.capturing.get() else { return false };
+        if is_modifier_key(key) {
+            return true;
+        }
+        let modifiers = state
+            & (gdk::ModifierType::CONTROL_MASK
+                | gdk::ModifierType::SHIFT_MASK
+                | gdk::ModifierType::ALT_MASK
+                | gdk::ModifierType::SUPER_MASK);
+        let started = !self.pending.borrow().is_empty();
+        match key {
+            gdk::Key::Escape if modifiers.is_empty() => self.finish_capture(false),
+            gdk::Key::BackSpace if modifiers.is_empty() && !started => {
+                if let Some((_, chords)) = self.bindings.borrow_mut().entries.get_mut(index) {
+                    chords.clear();
+                }
+                self.finish_capture(false);
+            }
+            gdk::Key::Return | gdk::Key::KP_Enter if modifiers.is_empty() && started => {
+                self.finish_capture(true);
+            }
+            _ => {
+                self.pending
+                    .borrow_mut()
+                    .push(KeyBinding { key, modifiers });
+                if let Some(source) = self.pending_timeout.take() {
+                    source.remove();
+                }
+                let weak_editor = Rc::downgrade(self);
+                let source = gtk::glib::timeout_add_local_once(self.chord_timeout, move || {
+                    let Some(editor) = weak_editor.upgrade() else { return };
+                    editor.pending_timeout.take();
+                    editor.finish_capture(true);
+                });
+                *self.pending_timeout.borrow_mut() = Some(source);
+                self.refresh();
+            }
+        }
+        true
+    }
+
+    // Ends capturing, adding the keys pressed so far if `keep` is set.
+    fn finish_capture(self: &Rc<Self>, keep: bool) {
+        if let Some(source) = self.pending_timeout.take() {
+            source.remove();
+        }
+        let chord = self.pending.take();
+        if keep
+            && !chord.is_empty()
+            && let Some(index) = self.capturing.get()
+            && let Some((_, chords)) = self.bindings.borrow_mut().entries.get_mut(index)
+            && !chords.contains(&chord)
+        {
+            chords.push(chord);
+        }
+        self.capturing.set(None);
+        self.refresh();
+    }
+
+    fn remove_binding(self: &Rc<Self>, index: usize, position: usize) {
+        if let Some((_, chords)) = self.bindings.borrow_mut().entries.get_mut(index)
+            && position < chords.len()
+        {
+            chords.remove(position);
+        }
+        self.refresh();
+    }
+
+    // Resets one action, or all of them, to the default bindings.
+    fn reset(self: &Rc<Self>, index: Option<usize>) {
+        {
+            let mut bindings = self.bindings.borrow_mut();
+            for (position, (action, chords)) in bindings.entries.iter_mut().enumerate() {
+                if index.is_none_or(|index| index == position) {
+                    *chords = self.defaults.bindings(action).to_vec();
+                }
+            }
+        }
+        self.finish_capture(false);
+    }
+
+    // Only the actions changed in the dialog are written. Bindings equal to
+    // the defaults are removed from the file, unless an included file could
+    // still override them.
+    fn save(&self) -> Result<(), String> {
+        let bindings = self.bindings.borrow();
+        update_config_file(|document| {
+            let table = document
+                .entry("keybindings")
+                .or_insert(toml_edit::table())
+                .as_table_like_mut()
+                .ok_or("`keybindings` is not a table")?;
+            for (action, chords) in &bindings.entries {
+                if chords.as_slice() == self.loaded.bindings(action) {
+                    continue;
+                }
+                let name = action.name();
+                if chords.as_slice() == self.defaults.bindings(action) && !self.includes {
+                    table.remove(&name);
+                } else {
+                    table.insert(&name, toml_edit::value(keybinding_value(chords)));
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+// `"none"` for no bindings, a string for one and an array for several.
+fn keybinding_value(chords: &[KeyChord]) -> toml_edit::Value {
+    let mut keys: Vec<String> = chords.iter().map(|chord| format_key_chord(chord)).collect();
+    match keys.len() {
+        0 => "none".into(),
+        1 => keys.remove(0).into(),
+        _ => keys.into_iter().collect::<toml_edit::Array>().into(),
+    }
+}
+
+// Edits config.toml in place, so comments, ordering and formatting of the
+// rest of the file are kept.
+fn update_config_file(
+    edit: impl FnOnce(&mut toml_edit::DocumentMut) -> Result<(), String>,
+) -> Result<(), String> {
+    let path = config_path().ok_or("no config directory")?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(format!("{}: {err}", path.display())),
+    };
+    let mut document = contents
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|err| format!("{}: {}", path.display(), err.message()))?;
+    edit(&mut document)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("{}: {err}", parent.display()))?;
+    }
+    fs::write(&path, document.to_string()).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+fn format_key_chord(chord: &[KeyBinding]) -> String {
+    let keys: Vec<String> = chord.iter().map(format_keybinding).collect();
+    keys.join(", ")
+}
+
+fn format_keybinding(binding: &KeyBinding) -> String {
+    let mut parts = Vec::new();
+    let modifiers = binding.modifiers;
+    if modifiers.contains(gdk::ModifierType::CONTROL_MASK) {
+        parts.push("Ctrl".to_string());
+    }
+    if modifiers.contains(gdk::ModifierType::SHIFT_MASK) {
+        parts.push("Shift".to_string());
+    }
+    if modifiers.contains(gdk::ModifierType::ALT_MASK) {
+        parts.push("Alt".to_string());
+    }
+    if modifiers.contains(gdk::ModifierType::SUPER_MASK) {
+        parts.push("Super".to_string());
+    }
+
+    let key_name = binding
+        .key
+        .name()
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("{:?}", binding.key));
+    let key_display = if key_name.len() == 1 {
+        key_name.to_ascii_uppercase()
+    } else {
+        key_name
+    };
+    parts.push(key_display);
+    parts.join("+")
+}
+
+fn clamp01(value: f32) -> f32 {
+    value.max(0.0).min(1.0)
+}
+
+fn adjust_luma(color: &gdk::RGBA, factor: f32) -> gdk::RGBA {
+    gdk::RGBA::new(
+        clamp01(color.red() * factor),
+        clamp01(color.green() * factor),
+        clamp01(color.blue() * factor),
+        color.alpha(),
+    )
+}
+
+fn with_alpha(color: &gdk::RGBA, alpha: f32) -> gdk::RGBA {
+    gdk::RGBA::new(color.red(), color.green(), color.blue(), clamp01(alpha))
+}
+
+fn contrast_text_color(background: &gdk::RGBA) -> gdk::RGBA {
+    let luminance = 0.2126 * background.red()
+        + 0.7152 * background.green()
+        + 0.0722 * background.blue();
+    if luminance < 0.5 {
+        gdk::RGBA::new(1.0, 1.0, 1.0, 1.0)
+    } else {
+        gdk::RGBA::new(0.0, 0.0, 0.0, 1.0)
+    }
+}
+
+fn parse_palette(values: &[String]) -> Option<[gdk::RGBA; 16]> {
+    if values.len() != 16 {
+        return None;
+    }
+    let mut colors = values.iter().map(|v| rgba(v)).collect::<Vec<_>>();
+    Some([
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+        colors.remove(0),
+    ])
+}
+
+fn derive_key(secret: &str) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(secret.as_bytes());
+    hasher.finalize().into()
+}
+
+fn encrypt_password(plaintext: &str, secret: &str) -> Result<String, String> {
+    use aes_gcm::aead::{Aead, KeyInit, OsRng};
+    use aes_gcm::aead::rand_core::RngCore;
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let key_bytes = derive_key(secret);
+    let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
+    let cipher = Aes256Gcm::new(key);
+
+    let mut nonce_bytes = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce_bytes);
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext.as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    let mut combined = nonce_bytes.to_vec();
+    combined.extend_from_slice(&ciphertext);
+    Ok(STANDARD.encode(&combined))
+}
+
+fn decrypt_password(encrypted: &str, secret: &str) -> Result<String, String> {
+    use aes_gcm::aead::{Aead, KeyInit};
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let data = STANDARD.decode(encrypted).map_err(|e| e.to_string())?;
+    if data.len() < 13 {
+        return Err("Geçersiz şifreli veri".to_string());
+    }
+    let (nonce_bytes, ciphertext) = data.split_at(12);
+    let key_bytes = derive_key(secret);
+    let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
+    let cipher = Aes256Gcm::new(key);
+    let nonce = Nonce::from_slice(nonce_bytes);
+
+    let plaintext = cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|_| "Şifre çözme başarısız — secret anahtarı yanlış olabilir".to_string())?;
+    String::from_utf8(plaintext).map_err(|e| e.to_string())
+}
+
+fn first_visible_index(list_box: &gtk::ListBox) -> Option<i32> {
+    let mut idx = 0;
+    loop {
+        match list_box.row_at_index(idx) {
+            None => return None,
+            Some(row) if row.is_visible() => return Some(idx),
+            _ => idx += 1,
+        }
+    }
+}
+
+fn select_first_visible(list_box: &gtk::ListBox) {
+    let mut idx = 0;
+    loop {
+        match list_box.row_at_index(idx) {
+            None => {
+                list_box.select_row(None::<&gtk::ListBoxRow>);
+                break;
+            }
+            Some(row) if row.is_child_visible() => {
+                list_box.select_row(Some(&row));
+                break;
+            }
+            _ => idx += 1,
+        }
+    }
+}
+
+fn passwords_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "termilyon")
+        .map(|dirs| dirs.config_dir().join("passwords.toml"))
+}
+
+fn load_passwords(secret: &str) -> Vec<Password> {
+    let Some(path) = passwords_path() else { return Vec::new() };
+    let Ok(content) = fs::read_to_string(&path) else { return Vec::new() };
+    let encrypted_list = toml::from_str::<Passwords>(&content)
+        .map(|p| p.passwords)
+        .unwrap_or_default();
+    encrypted_list
+        .into_iter()
+        .filter_map(|p| {
+            decrypt_password(&p.password, secret)
+                .ok()
+                .map(|plaintext| Password { name: p.name, password: plaintext })
+        })
+        .collect()
+}
+
+fn save_passwords(passwords: &[Password], secret: &str) {
+    let Some(path) = passwords_path() else { return };
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let encrypted: Vec<Password> = passwords
+        .iter()
+        .filter_map(|p| {
+            encrypt_password(&p.password, secret)
+                .ok()
+                .map(|enc| Password { name: p.name.clone(), password: enc })
+        })
+        .collect();
+    let data = Passwords { passwords: encrypted };
+    if let Ok(content) = toml::to_string(&data) {
+        let _ = fs::write(&path, content);
+    }
+}
+
+fn populate_password_list(list_box: &gtk::ListBox, passwords: &[Password]) {
+    while let Some(row) = list_box.row_at_index(0) {
+        list_box.remove(&row);
+    }
+    for pwd in passwords {
+        let label = gtk::Label::new(Some(&pwd.name));
+        label.set_xalign(0.0);
+        label.set_margin_top(6);
+        label.set_margin_bottom(6);
+        label.set_margin_start(8);
+        label.set_margin_end(8);
+        let row = gtk::ListBoxRow::new();
+        row.set_child(Some(&label));
+        list_box.append(&row);
+    }
+    if let Some(first) = list_box.row_at_index(0) {
+        list_box.select_row(Some(&first));
+    }
+}
+
+fn password_paste_selected(
+    dialog: &gtk::Dialog,
+    notebook: &gtk::Notebook,
+    list_box: &gtk::ListBox,
+    passwords: &Rc<RefCell<Vec<Password>>>,
+) {
+    let Some(row) = list_box.selected_row() else { return };
+    let index = row.index() as usize;
+    let passwords_ref = passwords.borrow();
+    let Some(pwd) = passwords_ref.get(index) else { return };
+    let text = format!("{}\n", pwd.password);
+    let Some(page) = notebook.current_page() else { return };
+    let Some(child) = notebook.nth_page(Some(page)) else { return };
+    if let Some(terminal) = find_terminal_in_widget(&child) {
+        terminal.feed_child(text.as_bytes());
+    }
+    dialog.close();
+}
+
+fn show_add_password_dialog(
+    parent: &gtk::Dialog,
+    list_box: &gtk::ListBox,
+    passwords: &Rc<RefCell<Vec<Password>>>,
+    secret: Rc<String>,
+) {
+    let dialog = gtk::Dialog::new();
+    dialog.set_title(Some("Add Password"));
+    dialog.set_modal(true);
+    dialog.set_transient_for(Some(parent));
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Add", gtk::ResponseType::Ok);
+    dialog.set_default_response(gtk::ResponseType::Ok);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_margin_top(12);
+    content.set_margin_bottom(12);
+    content.set_margin_start(12);
+    content.set_margin_end(12);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(8);
+    grid.set_column_spacing(8);
+
+    let name_label = gtk::Label::new(Some("Name:"));
+    name_label.set_xalign(1.0);
+    let name_entry = gtk::Entry::new();
+    name_entry.set_placeholder_text(Some("sudo, API key, ..."));
+    name_entry.set_activates_default(true);
+
+    let pass_label = gtk::Label::new(Some("Password:"));
+    pass_label.set_xalign(1.0);
+    let pass_entry = gtk::Entry::new();
+    pass_entry.set_visibility(false);
+    pass_entry.set_input_purpose(gtk::InputPurpose::Password);
+    pass_entry.set_activates_default(true);
+
+    grid.attach(&name_label, 0, 0, 1, 1);
+    grid.attach(&name_entry, 1, 0, 1, 1);
+    grid.attach(&pass_label, 0, 1, 1, 1);
+    grid.attach(&pass_entry, 1, 1, 1, 1);
+    content.append(&grid);
+
+    let list_box = list_box.clone();
+    let passwords = passwords.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Ok {
+            let name = name_entry.text().trim().to_string();
+            let password = pass_entry.text().to_string();
+            if !name.is_empty() && !password.is_empty() {
+                let entry = Password { name, password };
+                {
+                    let mut pwds = passwords.borrow_mut();
+                    pwds.push(entry);
+                    save_passwords(&pwds, &secret);
+                }
+                populate_password_list(&list_box, &passwords.borrow());
+            }
+        }
+        dialog.close();
+    });
+
+    dialog.present();
+}
+
+fn show_password_manager_dialog(window: &gtk::ApplicationWindow, notebook: &gtk::Notebook, secret: String) {
+    if secret.is_empty() {
+        let dialog = gtk::Dialog::new();
+        dialog.set_title(Some("Şifre Yöneticisi"));
+        dialog.set_modal(true);
+        dialog.set_transient_for(Some(window));
+        dialog.add_button("Tamam", gtk::ResponseType::Ok);
+        let content = dialog.content_area();
+        content.set_margin_top(16);
+        content.set_margin_bottom(16);
+        content.set_margin_start(16);
+        content.set_margin_end(16);
+        let label = gtk::Label::new(Some(
+            "Şifre yöneticisini kullanmak için config.toml dosyasına\n\
+             secret = \"gizli-anahtarınız\" satırını ekleyin.",
+        ));
+        label.set_wrap(true);
+        content.append(&label);
+        dialog.connect_response(|d, _| d.close());
+        dialog.present();
+        return;
+    }
+
+    let secret = Rc::new(secret);
+    let passwords = Rc::new(RefCell::new(load_passwords(&secret)));
+
+    let dialog = gtk::Dialog::new();
+    dialog.set_title(Some("Passwords"));
+    dialog.set_modal(true);
+    dialog.set_transient_for(Some(window));
+    dialog.set_default_size(400, 340);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_margin_top(12);
+    content.set_margin_bottom(12);
+    content.set_margin_start(12);
+    content.set_margin_end(12);
+
+    let search_entry = gtk::SearchEntry::new();
+    search_entry.set_placeholder_text(Some("Ara..."));
+    content.append(&search_entry);
+
+    let list_box = gtk::ListBox::new();
+    list_box.set_selection_mode(gtk::SelectionMode::Single);
+    list_box.set_vexpand(true);
+
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_child(Some(&list_box));
+    scrolled.set_vexpand(true);
+    scrolled.set_min_content_height(200);
+    content.append(&scrolled);
+
+    populate_password_list(&list_box, &passwords.borrow());
+
+    // Filtre fonksiyonu
+    {
+        let search = search_entry.clone();
+        list_box.set_filter_func(move |row| {
+            let text = search.text().to_lowercase();
+            if text.is_empty() { return true; }
+            row.child()
+                .and_then(|c| c.downcast::<gtk::Label>().ok())
+                .is_some_and(|l| l.text().to_lowercase().contains(&text))
+        });
+    }
+
+    // Arama değiştiğinde filtrele ve ilk görünür satırı seç
+    {
+        let list_box = list_box.clone();
+        search_entry.connect_search_changed(move |_| {
+            list_box.invalidate_filter();
+            select_first_visible(&list_box);
+        });
+    }
+
+    // Arama kutusunda ↓ → listeye geç; Escape → kapat
+    {
+        let ctrl = gtk::EventControllerKey::new();
+        let list_box = list_box.clone();
+        let dialog = dialog.clone();
+        ctrl.connect_key_pressed(move |_, key, _, _| match key {
+            gdk::Key::Down => {
+                if let Some(row) = list_box.selected_row() {
+                    row.grab_focus();
+                } else {
+                    select_first_visible(&list_box);
+                    if let Some(row) = list_box.selected_row() {
+                        row.grab_focus();
+                    }
+                }
+                gtk::glib::Propagation::Stop
+            }
+            gdk::Key::Escape => {
+                dialog.close();
+                gtk::glib::Propagation::Stop
+            }
+            _ => gtk::glib::Propagation::Proceed,
+        });
+        search_entry.add_controller(ctrl);
+    }
+
+    // Listede ↑ + ilk satır → arama kutusuna dön
+    {
+        let ctrl = gtk::EventControllerKey::new();
+        ctrl.set_propagation_phase(gtk::PropagationPhase::Capture);
+        let search = search_entry.clone();
+        let list_box_mv = list_box.clone();
+        let list_box_ac = list_box.clone();
+        ctrl.connect_key_pressed(move |_, key, _, _| {
+            if key == gdk::Key::Up {
+                if let Some(row) = list_box_mv.selected_row() {
+                    if Some(row.index()) == first_visible_index(&list_box_mv) {
+                        search.grab_focus();
+                        return gtk::glib::Propagation::Stop;
+                    }
+                }
+            }
+            gtk::glib::Propagation::Proceed
+        });
+        list_box_ac.add_controller(ctrl);
+    }
+
+    let btn_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    btn_box.set_margin_top(4);
+    btn_box.set_halign(gtk::Align::End);
+
+    let add_btn = gtk::Button::with_label("Add");
+    let delete_btn = gtk::Button::with_label("Delete");
+    let paste_btn = gtk::Button::with_label("Paste");
+    let close_btn = gtk::Button::with_label("Close");
+    btn_box.append(&add_btn);
+    btn_box.append(&delete_btn);
+    btn_box.append(&paste_btn);
+    btn_box.append(&close_btn);
+    content.append(&btn_box);
+
+    // Add button
+    {
+        let dialog = dialog.clone();
+        let list_box = list_box.clone();
+        let passwords = passwords.clone();
+        let secret = secret.clone();
+        add_btn.connect_clicked(move |_| {
+            show_add_password_dialog(&dialog, &list_box, &passwords, secret.clone());
+        });
+    }
+
+    // Delete button
+    {
+        let list_box = list_box.clone();
+        let passwords = passwords.clone();
+        let secret = secret.clone();
+        delete_btn.connect_clicked(move |_| {
+            let Some(row) = list_box.selected_row() else { return };
+            let index = row.index() as usize;
+            {
+                let mut pwds = passwords.borrow_mut();
+                if index < pwds.len() {
+                    pwds.remove(index);
+                }
+                save_passwords(&pwds, &secret);
+            }
+            populate_password_list(&list_box, &passwords.borrow());
+            list_box.invalidate_filter();
+            select_first_visible(&list_box);
+        });
+    }
+
+    // Paste button
+    {
+        let dialog = dialog.clone();
+        let notebook = notebook.clone();
+        let passwords = passwords.clone();
+        let list_box = list_box.clone();
+        paste_btn.connect_clicked(move |_| {
+            password_paste_selected(&dialog, &notebook, &list_box, &passwords);
+        });
+    }
+
+    // Close button
+    {
+        let dialog = dialog.clone();
+        close_btn.connect_clicked(move |_| dialog.close());
+    }
+
+    // Row activated: Enter or double-click → paste
+    {
+        let dialog = dialog.clone();
+        let notebook = notebook.clone();
+        let passwords = passwords.clone();
+        list_box.connect_row_activated(move |lb, _| {
+            password_paste_selected(&dialog, &notebook, lb, &passwords);
+        });
+    }
+
+    // Delete key removes selected entry
+    {
+        let key_ctrl = gtk::EventControllerKey::new();
+        let passwords = passwords.clone();
+        let list_box = list_box.clone();
+        let secret = secret.clone();
+        key_ctrl.connect_key_pressed(move |_, key, _, _| {
+            if key == gdk::Key::Delete {
+                let Some(row) = list_box.selected_row() else {
+                    return gtk::glib::Propagation::Proceed;
+                };
+                let index = row.index() as usize;
+                {
+                    let mut pwds = passwords.borrow_mut();
+                    if index < pwds.len() {
+                        pwds.remove(index);
+                    }
+                    save_passwords(&pwds, &secret);
+                }
+                populate_password_list(&list_box, &passwords.borrow());
+                list_box.invalidate_filter();
+                select_first_visible(&list_box);
+                return gtk::glib::Propagation::Stop;
+            }
+            gtk::glib::Propagation::Proceed
+        });
+        dialog.add_controller(key_ctrl);
+    }
+
+    search_entry.grab_focus();
+    dialog.present();
+}
+
+fn ssh_servers_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "termilyon")
+        .map(|dirs| dirs.config_dir().join("ssh_servers.toml"))
+}
+
+fn load_ssh_servers() -> Vec<SshServer> {
+    let Some(path) = ssh_servers_path() else { return Vec::new() };
+    let Ok(content) = fs::read_to_string(&path) else { return Vec::new() };
+    toml::from_str::<SshServers>(&content)
+        .map(|s| s.servers)
+        .unwrap_or_default()
+}
+
+fn save_ssh_servers(servers: &[SshServer]) {
+    let Some(path) = ssh_servers_path() else { return };
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let data = SshServers { servers: servers.to_vec() };
+    if let Ok(content) = toml::to_string(&data) {
+        let _ = fs::write(&path, content);
+    }
+}
+
+fn populate_server_list(list_box: &gtk::ListBox, servers: &[SshServer]) {
+    while let Some(row) = list_box.row_at_index(0) {
+        list_box.remove(&row);
+    }
+    for srv in servers {
+        let text = if srv.port == 22 {
+            format!("{}  —  {}@{}", srv.name, srv.user, srv.host)
+        } else {
+            format!("{}  —  {}@{}:{}", srv.name, srv.user, srv.host, srv.port)
+        };
+        let label = gtk::Label::new(Some(&text));
+        label.set_xalign(0.0);
+        label.set_margin_top(6);
+        label.set_margin_bottom(6);
+        label.set_margin_start(8);
+        label.set_margin_end(8);
+        let row = gtk::ListBoxRow::new();
+        row.set_child(Some(&label));
+        list_box.append(&row);
+    }
+    if let Some(first) = list_box.row_at_index(0) {
+        list_box.select_row(Some(&first));
+    }
+}
+
+fn ssh_connect_selected(
+    dialog: &gtk::Dialog,
+    notebook: &gtk::Notebook,
+    list_box: &gtk::ListBox,
+    servers: &Rc<RefCell<Vec<SshServer>>>,
+) {
     let Some(row) = list_box.selected_row() else { return };
     let index = row.index() as usize;
-    let passwords_ref = passwords.borrow();
-    let Some(pwd) = passwords_ref.get(index) else { return };
-    let text = format!("{}\n", pwd.password);
+    let servers_ref = servers.borrow();
+    let Some(srv) = servers_ref.get(index) else { return };
+    let cmd = ssh_command(srv);
     let Some(page) = notebook.current_page() else { return };
     let Some(child) = notebook.nth_page(Some(page)) else { return };
     if let Some(terminal) = find_terminal_in_widget(&child) {
-        terminal.feed_child(text.as_bytes());
+        terminal.feed_child(cmd.as_bytes());
+    }
+    dialog.close();
+}
+
+fn ssh_command(srv: &SshServer) -> String {
+    if srv.port == 22 {
+        format!("ssh {}@{}\n", srv.user, srv.host)
+    } else {
+        format!("ssh -p {} {}@{}\n", srv.port, srv.user, srv.host)
+    }
+}
+
+fn show_add_server_dialog(
+    parent: &gtk::Dialog,
+    list_box: &gtk::ListBox,
+    servers: &Rc<RefCell<Vec<SshServer>>>,
+) {
+    let dialog = gtk::Dialog::new();
+    dialog.set_title(Some("Add SSH Server"));
+    dialog.set_modal(true);
+    dialog.set_transient_for(Some(parent));
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Add", gtk::ResponseType::Ok);
+    dialog.set_default_response(gtk::ResponseType::Ok);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_margin_top(12);
+    content.set_margin_bottom(12);
+    content.set_margin_start(12);
+    content.set_margin_end(12);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(8);
+    grid.set_column_spacing(8);
+
+    let make_label = |text: &str| {
+        let l = gtk::Label::new(Some(text));
+        l.set_xalign(1.0);
+        l
+    };
+    let make_entry = |placeholder: &str, default: &str| {
+        let e = gtk::Entry::new();
+        e.set_placeholder_text(Some(placeholder));
+        if !default.is_empty() {
+            e.set_text(default);
+        }
+        e.set_activates_default(true);
+        e
+    };
+
+    let name_entry = make_entry("My Server", "");
+    let host_entry = make_entry("192.168.1.1", "");
+    let user_entry = make_entry("root", "");
+    let port_entry = make_entry("22", "22");
+
+    grid.attach(&make_label("Name:"), 0, 0, 1, 1);
+    grid.attach(&name_entry, 1, 0, 1, 1);
+    grid.attach(&make_label("Host:"), 0, 1, 1, 1);
+    grid.attach(&host_entry, 1, 1, 1, 1);
+    grid.attach(&make_label("User:"), 0, 2, 1, 1);
+    grid.attach(&user_entry, 1, 2, 1, 1);
+    grid.attach(&make_label("Port:"), 0, 3, 1, 1);
+    grid.attach(&port_entry, 1, 3, 1, 1);
+
+    content.append(&grid);
+
+    let list_box = list_box.clone();
+    let servers = servers.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Ok {
+            let name = name_entry.text().trim().to_string();
+            let host = host_entry.text().trim().to_string();
+            let user = user_entry.text().trim().to_string();
+            let port: u16 = port_entry.text().trim().parse().unwrap_or(22);
+            if !name.is_empty() && !host.is_empty() && !user.is_empty() {
+                let srv = SshServer { name, host, user, port };
+                {
+                    let mut svs = servers.borrow_mut();
+                    svs.push(srv);
+                    save_ssh_servers(&svs);
+                }
+                populate_server_list(&list_box, &servers.borrow());
+            }
+        }
+        dialog.close();
+    });
+
+    dialog.present();
+}
+
+fn show_ssh_manager_dialog(window: &gtk::ApplicationWindow, notebook: &gtk::Notebook) {
+    let servers = Rc::new(RefCell::new(load_ssh_servers()));
+
+    let dialog = gtk::Dialog::new();
+    dialog.set_title(Some("SSH Servers"));
+    dialog.set_modal(true);
+    dialog.set_transient_for(Some(window));
+    dialog.set_default_size(520, 360);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_margin_top(12);
+    content.set_margin_bottom(12);
+    content.set_margin_start(12);
+    content.set_margin_end(12);
+
+    let search_entry = gtk::SearchEntry::new();
+    search_entry.set_placeholder_text(Some("Ara..."));
+    content.append(&search_entry);
+
+    let list_box = gtk::ListBox::new();
+    list_box.set_selection_mode(gtk::SelectionMode::Single);
+    list_box.set_vexpand(true);
+
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_child(Some(&list_box));
+    scrolled.set_vexpand(true);
+    scrolled.set_min_content_height(220);
+    content.append(&scrolled);
+
+    populate_server_list(&list_box, &servers.borrow());
+
+    // Filtre fonksiyonu
+    {
+        let search = search_entry.clone();
+        list_box.set_filter_func(move |row| {
+            let text = search.text().to_lowercase();
+            if text.is_empty() { return true; }
+            row.child()
+                .and_then(|c| c.downcast::<gtk::Label>().ok())
+                .is_some_and(|l| l.text().to_lowercase().contains(&text))
+        });
+    }
+
+    // Arama değiştiğinde filtrele ve ilk görünür satırı seç
+    {
+        let list_box = list_box.clone();
+        search_entry.connect_search_changed(move |_| {
+            list_box.invalidate_filter();
+            select_first_visible(&list_box);
+        });
+    }
+
+    // Arama kutusunda ↓ → listeye geç; Escape → dialog'u kapat
+    {
+        let ctrl = gtk::EventControllerKey::new();
+        let list_box = list_box.clone();
+        let dialog = dialog.clone();
+        ctrl.connect_key_pressed(move |_, key, _, _| match key {
+            gdk::Key::Down => {
+                if let Some(row) = list_box.selected_row() {
+                    row.grab_focus();
+                } else {
+                    select_first_visible(&list_box);
+                    if let Some(row) = list_box.selected_row() {
+                        row.grab_focus();
+                    }
+                }
+                gtk::glib::Propagation::Stop
+            }
+            gdk::Key::Escape => {
+                dialog.close();
+                gtk::glib::Propagation::Stop
+            }
+            _ => gtk::glib::Propagation::Proceed,
+        });
+        search_entry.add_controller(ctrl);
+    }
+
+    // Listede ↑ + ilk satır → arama kutusuna dön
+    {
+        let ctrl = gtk::EventControllerKey::new();
+        ctrl.set_propagation_phase(gtk::PropagationPhase::Capture);
+        let search = search_entry.clone();
+        let list_box_mv = list_box.clone();
+        let list_box_ac = list_box.clone();
+        ctrl.connect_key_pressed(move |_, key, _, _| {
+            if key == gdk::Key::Up {
+                if let Some(row) = list_box_mv.selected_row() {
+                    if Some(row.index()) == first_visible_index(&list_box_mv) {
+                        search.grab_focus();
+                        return gtk::glib::Propagation::Stop;
+                    }
+                }
+            }
+            gtk::glib::Propagation::Proceed
+        });
+        list_box_ac.add_controller(ctrl);
     }
-    dialog.close();
+
+    let btn_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    btn_box.set_margin_top(4);
+    btn_box.set_halign(gtk::Align::End);
+
+    let add_btn = gtk::Button::with_label("Add");
+    let delete_btn = gtk::Button::with_label("Delete");
+    let connect_btn = gtk::Button::with_label("Connect");
+    let close_btn = gtk::Button::with_label("Close");
+    btn_box.append(&add_btn);
+    btn_box.append(&delete_btn);
+    btn_box.append(&connect_btn);
+    btn_box.append(&close_btn);
+    content.append(&btn_box);
+
+    // Add button
+    {
+        let dialog = dialog.clone();
+        let list_box = list_box.clone();
+        let servers = servers.clone();
+        add_btn.connect_clicked(move |_| {
+            show_add_server_dialog(&dialog, &list_box, &servers);
+        });
+    }
+
+    // Delete button
+    {
+        let list_box = list_box.clone();
+        let servers = servers.clone();
+        delete_btn.connect_clicked(move |_| {
+            let Some(row) = list_box.selected_row() else { return };
+            let index = row.index() as usize;
+            {
+                let mut svs = servers.borrow_mut();
+                if index < svs.len() {
+                    svs.remove(index);
+                }
+                save_ssh_servers(&svs);
+            }
+            populate_server_list(&list_box, &servers.borrow());
+            list_box.invalidate_filter();
+            select_first_visible(&list_box);
+        });
+    }
+
+    // Connect button
+    {
+        let dialog = dialog.clone();
+        let notebook = notebook.clone();
+        let servers = servers.clone();
+        let list_box = list_box.clone();
+        connect_btn.connect_clicked(move |_| {
+            ssh_connect_selected(&dialog, &notebook, &list_box, &servers);
+        });
+    }
+
+    // Close button
+    {
+        let dialog = dialog.clone();
+        close_btn.connect_clicked(move |_| dialog.close());
+    }
+
+    // Row activated: Enter key or double-click
+    {
+        let dialog = dialog.clone();
+        let notebook = notebook.clone();
+        let servers = servers.clone();
+        list_box.connect_row_activated(move |lb, _| {
+            ssh_connect_selected(&dialog, &notebook, lb, &servers);
+        });
+    }
+
+    // Delete tuşu seçili sunucuyu siler
+    {
+        let key_ctrl = gtk::EventControllerKey::new();
+        let servers = servers.clone();
+        let list_box = list_box.clone();
+        key_ctrl.connect_key_pressed(move |_, key, _, _| {
+            if key == gdk::Key::Delete {
+                let Some(row) = list_box.selected_row() else {
+                    return gtk::glib::Propagation::Proceed;
+                };
+                let index = row.index() as usize;
+                {
+                    let mut svs = servers.borrow_mut();
+                    if index < svs.len() {
+                        svs.remove(index);
+                    }
+                    save_ssh_servers(&svs);
+                }
+                populate_server_list(&list_box, &servers.borrow());
+                list_box.invalidate_filter();
+                select_first_visible(&list_box);
+                return gtk::glib::Propagation::Stop;
+            }
+            gtk::glib::Propagation::Proceed
+        });
+        dialog.add_controller(key_ctrl);
+    }
+
+    search_entry.grab_focus();
+    dialog.present();
 }
 
-fn show_add_password_dialog(
-    parent: &gtk::Dialog,
-    list_box: &gtk::ListBox,
-    passwords: &Rc<RefCell<Vec<Password>>>,
-    secret: Rc<String>,
+fn terminal_pane_id(terminal: &Terminal) -> Option<u32> {
+    terminal
+        .widget_name()
+        .strip_prefix(PANE_NAME_PREFIX)
+        .and_then(|id| id.parse().ok())
+}
+
+fn terminal_profile(terminal: &Terminal) -> Option<String> {
+    terminal_pane_profile(terminal).map(|profile| profile.name)
+}
+
+fn terminal_pane_profile(terminal: &Terminal) -> Option<PaneProfile> {
+    let id = terminal_pane_id(terminal)?;
+    PANE_PROFILES.with_borrow(|profiles| profiles.get(&id).cloned())
+}
+
+fn set_terminal_profile(terminal: &Terminal, config: &Config, profile: Option<&str>) {
+    let Some(id) = terminal_pane_id(terminal) else { return };
+    PANE_PROFILES.with_borrow_mut(|profiles| match profile {
+        Some(profile) => {
+            let color = profile_marker_color(config, profile);
+            let name = profile.to_string();
+            profiles.insert(id, PaneProfile { name, color });
+        }
+        None => {
+            profiles.remove(&id);
+        }
+    });
+}
+
+// A profile's `tab_color`, else the background of its theme, else a color
+// picked by its position among the profiles.
+fn profile_marker_color(config: &Config, name: &str) -> String {
+    let Some(profile) = config.profiles.get(name) else {
+        return PROFILE_MARKER_COLORS[0].to_string();
+    };
+    if let Some(color) = profile
+        .tab_color
+        .as_ref()
+        .filter(|color| is_hex_color(color))
+    {
+        return color.clone();
+    }
+    if let Some(theme) = profile.theme_source.as_ref().and_then(theme_from_source) {
+        return hex_color(&theme.background);
+    }
+    let index = config
+        .profiles
+        .keys()
+        .position(|key| key == name)
+        .unwrap_or(0);
+    PROFILE_MARKER_COLORS[index % PROFILE_MARKER_COLORS.len()].to_string()
+}
+
+fn hex_color(color: &gdk::RGBA) -> String {
+    let channel = |value: f32| (clamp01(value) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.red()),
+        channel(color.green()),
+        channel(color.blue())
+    )
+}
+
+// Shows a dot per profile used by the panes of a tab in front of its title;
+// tabs where every pane uses the global config have none.
+fn update_profile_marker(page: &gtk::Widget) {
+    let Some(notebook) = find_parent_notebook(page) else { return };
+    let Some(tab_widget) = notebook.tab_label(page) else { return };
+    let mut child = tab_widget.first_child();
+    let marker = loop {
+        let Some(widget) = child else { return };
+        if widget.widget_name() == PROFILE_MARKER_NAME {
+            break widget;
+        }
+        child = widget.next_sibling();
+    };
+    let Ok(marker) = marker.downcast::<gtk::Label>() else { return };
+
+    let mut terminals = Vec::new();
+    collect_terminals(page, &mut terminals);
+    let mut profiles: Vec<PaneProfile> = Vec::new();
+    for profile in terminals.iter().filter_map(terminal_pane_profile) {
+        if !profiles.iter().any(|known| known.name == profile.name) {
+            profiles.push(profile);
+        }
+    }
+    let dots: String = profiles
+        .iter()
+        .map(|profile| format!("<span foreground=\"{}\">●</span>", profile.color))
+        .collect();
+    let names: Vec<&str> = profiles
+        .iter()
+        .map(|profile| profile.name.as_str())
+        .collect();
+    marker.set_markup(&dots);
+    marker.set_tooltip_text(Some(&format!("Profile: {}", names.join(", "))));
+    marker.set_visible(!profiles.is_empty());
+}
+
+const CONTROL_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The socket lets anyone who can connect type into a shell, so it lives in
+// a directory only the user can enter: `$XDG_RUNTIME_DIR/termilyon`, or
+// `termilyon-$UID` in the temp dir when there is no runtime dir.
+fn control_socket_path() -> Result<PathBuf, String> {
+    let uid = current_uid()?;
+    let dir = ProjectDirs::from("io", "termilyon", "termilyon")
+        .and_then(|dirs| dirs.runtime_dir().map(Path::to_path_buf))
+        .unwrap_or_else(|| env::temp_dir().join(format!("termilyon-{uid}")));
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(format!("{}: {err}", dir.display())),
+    }
+    // Someone else may have created it first, or replaced it by a link.
+    let metadata = fs::symlink_metadata(&dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    if !metadata.is_dir() || metadata.uid() != uid {
+        return Err(format!(
+            "{} is not a directory owned by the current user",
+            dir.display()
+        ));
+    }
+    // Older versions created it with the default umask.
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))
+            .map_err(|err| format!("{}: {err}", dir.display()))?;
+    }
+    Ok(dir.join("termilyon.sock"))
+}
+
+fn current_uid() -> Result<u32, String> {
+    gtk::gio::Credentials::new()
+        .unix_user()
+        .map_err(|err| err.to_string())
+}
+
+fn start_control_server(
+    app: &gtk::Application,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
 ) {
-    let dialog = gtk::Dialog::new();
-    dialog.set_title(Some("Add Password"));
-    dialog.set_modal(true);
-    dialog.set_transient_for(Some(parent));
-    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-    dialog.add_button("Add", gtk::ResponseType::Ok);
-    dialog.set_default_response(gtk::ResponseType::Ok);
+    let path = match control_socket_path() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("control socket failed: {err}");
+            return;
+        }
+    };
+    // Only the primary instance gets here, so a leftover socket in the
+    // private directory is stale.
+    let _ = fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("control socket failed: {}: {err}", path.display());
+            return;
+        }
+    };
+    if let Err(err) = fs::set_permissions(&path, fs::Permissions::from_mode(0o600)) {
+        eprintln!("control socket failed: {}: {err}", path.display());
+        let _ = fs::remove_file(&path);
+        return;
+    }
+    if listener.set_nonblocking(true).is_err() {
+        return;
+    }
+    let _ = CONTROL_SOCKET.set(path);
 
-    let content = dialog.content_area();
-    content.set_spacing(8);
-    content.set_margin_top(12);
-    content.set_margin_bottom(12);
-    content.set_margin_start(12);
-    content.set_margin_end(12);
+    let app = app.clone();
+    let config = config.clone();
+    let counter = counter.clone();
+    let fd = listener.as_raw_fd();
+    gtk::glib::unix_fd_add_local(fd, gtk::glib::IOCondition::IN, move |_, _| {
+        while let Ok((stream, _)) = listener.accept() {
+            serve_control_connection(&app, &config, &counter, stream);
+        }
+        gtk::glib::ControlFlow::Continue
+    });
+}
+
+// The request is read asynchronously on the main loop, so a slow or idle
+// client never holds up the UI; one that sends nothing is dropped after
+// CONTROL_READ_TIMEOUT.
+fn serve_control_connection(
+    app: &gtk::Application,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+    stream: UnixStream,
+) {
+    let socket = match gtk::gio::Socket::from_fd(OwnedFd::from(stream)) {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("control connection failed: {err}");
+            return;
+        }
+    };
+    // SO_PEERCRED: only processes of the same user may send requests.
+    let peer = socket
+        .credentials()
+        .and_then(|credentials| credentials.unix_user());
+    match (peer, current_uid()) {
+        (Ok(peer), Ok(uid)) if peer == uid => {}
+        _ => return,
+    }
+    let connection = socket.connection_factory_create_connection();
+    let app = app.clone();
+    let config = config.clone();
+    let counter = counter.clone();
+    gtk::glib::MainContext::default().spawn_local(async move {
+        let input = gtk::gio::DataInputStream::new(&connection.input_stream());
+        let read = input.read_line_utf8_future(gtk::glib::Priority::DEFAULT);
+        let Ok(Ok(Some(line))) = gtk::glib::future_with_timeout(CONTROL_READ_TIMEOUT, read).await
+        else {
+            return;
+        };
+        let response = control_response(&app, &config, &counter, &line);
+        if let Ok(mut text) = serde_json::to_string(&response) {
+            text.push('\n');
+            let output = connection.output_stream();
+            let _ = output
+                .write_all_future(text.into_bytes(), gtk::glib::Priority::DEFAULT)
+                .await;
+        }
+        let _ = connection.close_future(gtk::glib::Priority::DEFAULT).await;
+    });
+}
+
+fn control_response(
+    app: &gtk::Application,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+    line: &str,
+) -> ControlResponse {
+    let outcome = serde_json::from_str::<ControlRequest>(line)
+        .map_err(|err| format!("invalid request: {err}"))
+        .and_then(|request| handle_control_request(app, config, counter, request));
+    match outcome {
+        Ok(result) => ControlResponse {
+            ok: true,
+            result: Some(result).filter(|value| !value.is_null()),
+            error: None,
+        },
+        Err(error) => ControlResponse {
+            ok: false,
+            result: None,
+            error: Some(error),
+        },
+    }
+}
+
+fn handle_control_request(
+    app: &gtk::Application,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+    request: ControlRequest,
+) -> Result<serde_json::Value, String> {
+    match request {
+        ControlRequest::List => {
+            serde_json::to_value(list_control_windows(app)).map_err(|err| err.to_string())
+        }
+        ControlRequest::NewTab { cwd, title, argv } => {
+            let window = app.active_window().ok_or("no window")?;
+            let notebook = window_notebook(&window).ok_or("no window")?;
+            let options = SpawnOptions {
+                cwd,
+                command: Some(argv).filter(|argv| !argv.is_empty()),
+                ..SpawnOptions::default()
+            };
+            let terminal = create_tab(&notebook, config, counter, &options);
+            if let Some(title) = title.as_deref() {
+                set_current_tab_title(&notebook, title);
+            }
+            Ok(serde_json::json!({ "pane": terminal_pane_id(&terminal) }))
+        }
+        ControlRequest::Split {
+            pane,
+            direction,
+            cwd,
+            argv,
+        } => {
+            let (notebook, terminal) = resolve_control_pane(app, pane)?;
+            let orientation = parse_split_direction(&direction)
+                .ok_or_else(|| format!("unknown split direction: {direction}"))?;
+            let options = (cwd.is_some() || !argv.is_empty()).then(|| SpawnOptions {
+                cwd,
+                command: Some(argv).filter(|argv| !argv.is_empty()),
+                ..SpawnOptions::default()
+            });
+            focus_pane(&notebook, &terminal);
+            split_current_tab(&notebook, config, orientation, options.as_ref());
+            let new_pane = find_root_window(notebook.upcast_ref())
+                .and_then(|window| focused_terminal(&window))
+                .and_then(|terminal| terminal_pane_id(&terminal));
+            Ok(serde_json::json!({ "pane": new_pane }))
+        }
+        ControlRequest::SendText { pane, text } => {
+            let (_, terminal) = resolve_control_pane(app, pane)?;
+            terminal.feed_child(text.as_bytes());
+            Ok(serde_json::Value::Null)
+        }
+        ControlRequest::GetText { pane } => {
+            let (_, terminal) = resolve_control_pane(app, pane)?;
+            let text = terminal.text_format(Format::Text).unwrap_or_default();
+            Ok(serde_json::Value::String(text.to_string()))
+        }
+        ControlRequest::RenameTab { pane, title } => {
+            let (notebook, terminal) = resolve_control_pane(app, pane)?;
+            let page = notebook_page_of(&notebook, terminal.upcast_ref()).ok_or("pane not found")?;
+            set_tab_title(&notebook, page, &title);
+            Ok(serde_json::Value::Null)
+        }
+        ControlRequest::Focus { pane } => {
+            let (notebook, terminal) = resolve_control_pane(app, Some(pane))?;
+            focus_pane(&notebook, &terminal);
+            if let Some(window) = find_root_window(notebook.upcast_ref()) {
+                window.present();
+            }
+            Ok(serde_json::Value::Null)
+        }
+    }
+}
+
+// Without an explicit id, requests act on the focused pane of the active
+// window.
+fn resolve_control_pane(
+    app: &gtk::Application,
+    pane: Option<u32>,
+) -> Result<(gtk::Notebook, Terminal), String> {
+    if let Some(id) = pane {
+        return find_pane(app, id).ok_or_else(|| format!("no pane with id {id}"));
+    }
+    let window = app.active_window().ok_or("no window")?;
+    let notebook = window_notebook(&window).ok_or("no window")?;
+    let terminal = focused_terminal(&window)
+        .or_else(|| find_first_terminal_in_notebook(&notebook))
+        .ok_or("no focused pane")?;
+    Ok((notebook, terminal))
+}
+
+fn find_pane(app: &gtk::Application, id: u32) -> Option<(gtk::Notebook, Terminal)> {
+    for notebook in app_notebooks(app) {
+        for index in 0..notebook.n_pages() {
+            let Some(page) = notebook.nth_page(Some(index)) else { continue };
+            let mut terminals = Vec::new();
+            collect_terminals(&page, &mut terminals);
+            if let Some(terminal) = terminals
+                .into_iter()
+                .find(|terminal| terminal_pane_id(terminal) == Some(id))
+            {
+                return Some((notebook, terminal));
+            }
+        }
+    }
+    None
+}
+
+fn notebook_page_of(notebook: &gtk::Notebook, widget: &gtk::Widget) -> Option<u32> {
+    let mut current = Some(widget.clone());
+    while let Some(node) = current {
+        if let Some(page) = notebook.page_num(&node) {
+            return Some(page);
+        }
+        current = node.parent();
+    }
+    None
+}
+
+fn focus_pane(notebook: &gtk::Notebook, terminal: &Terminal) {
+    if let Some(page) = notebook_page_of(notebook, terminal.upcast_ref()) {
+        notebook.set_current_page(Some(page));
+    }
+    terminal.grab_focus();
+}
+
+fn list_control_windows(app: &gtk::Application) -> Vec<ControlWindow> {
+    let active = app.active_window();
+    let mut windows = Vec::new();
+    for (window_index, window) in app.windows().iter().enumerate() {
+        let Some(notebook) = window_notebook(window) else { continue };
+        let focused = focused_terminal(window);
+        let mut tabs = Vec::new();
+        for index in 0..notebook.n_pages() {
+            let Some(page) = notebook.nth_page(Some(index)) else { continue };
+            let title = notebook
+                .tab_label(&page)
+                .and_then(|tab_widget| find_tab_label(&tab_widget))
+                .map(|label| label.text().to_string())
+                .unwrap_or_default();
+            let mut terminals = Vec::new();
+            collect_terminals(&page, &mut terminals);
+            let panes = terminals
+                .iter()
+                .filter_map(|terminal| {
+                    Some(ControlPane {
+                        id: terminal_pane_id(terminal)?,
+                        focused: focused.as_ref() == Some(terminal),
+                        title: terminal.window_title().map(|title| title.to_string()),
+                        cwd: terminal_working_directory(terminal),
+                    })
+                })
+                .collect();
+            tabs.push(ControlTab {
+                index,
+                title,
+                active: notebook.current_page() == Some(index),
+                panes,
+            });
+        }
+        windows.push(ControlWindow {
+            id: window_index as u32,
+            focused: active.as_ref() == Some(window),
+            tabs,
+        });
+    }
+    windows
+}
+
+fn run_control_client(socket: Option<PathBuf>, mut request: ControlRequest) -> i32 {
+    // Inside a Termilyon pane, requests default to that pane.
+    let own_pane = env::var("TERMILYON_PANE_ID")
+        .ok()
+        .and_then(|id| id.parse::<u32>().ok());
+    match &mut request {
+        ControlRequest::Split { pane, .. }
+        | ControlRequest::SendText { pane, .. }
+        | ControlRequest::GetText { pane }
+        | ControlRequest::RenameTab { pane, .. } => {
+            if pane.is_none() {
+                *pane = own_pane;
+            }
+        }
+        ControlRequest::List | ControlRequest::NewTab { .. } | ControlRequest::Focus { .. } => {}
+    }
+
+    let path = match socket.or_else(|| env::var_os("TERMILYON_SOCKET").map(PathBuf::from)) {
+        Some(path) => path,
+        None => match control_socket_path() {
+            Ok(path) => path,
+            Err(err) => {
+                eprintln!("{err}");
+                return 1;
+            }
+        },
+    };
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(err) => {
+            eprintln!("cannot connect to {}: {err}", path.display());
+            return 1;
+        }
+    };
+    let Ok(mut text) = serde_json::to_string(&request) else { return 1 };
+    text.push('\n');
+    if let Err(err) = stream.write_all(text.as_bytes()) {
+        eprintln!("request failed: {err}");
+        return 1;
+    }
 
-    let grid = gtk::Grid::new();
-    grid.set_row_spacing(8);
-    grid.set_column_spacing(8);
+    let mut line = String::new();
+    if let Err(err) = BufReader::new(stream).read_line(&mut line) {
+        eprintln!("no response: {err}");
+        return 1;
+    }
+    let response = match serde_json::from_str::<ControlResponse>(&line) {
+        Ok(response) => response,
+        Err(err) => {
+            eprintln!("invalid response: {err}");
+            return 1;
+        }
+    };
+    if !response.ok {
+        eprintln!("{}", response.error.unwrap_or_default());
+        return 1;
+    }
+    match response.result {
+        Some(serde_json::Value::String(text)) => print!("{text}"),
+        Some(value) => println!(
+            "{}",
+            serde_json::to_string_pretty(&value).unwrap_or_default()
+        ),
+        None => {}
+    }
+    0
+}
 
-    let name_label = gtk::Label::new(Some("Name:"));
-    name_label.set_xalign(1.0);
-    let name_entry = gtk::Entry::new();
-    name_entry.set_placeholder_text(Some("sudo, API key, ..."));
-    name_entry.set_activates_default(true);
+fn close_tab_or_window(window: &gtk::Window, notebook: &gtk::Notebook) {
+    if let Some(page) = notebook.current_page() {
+        if notebook.n_pages() <= 1 {
+            window.close();
+        } else {
+            notebook.remove_page(Some(page));
+            focus_previous_tab(notebook, page);
+        }
+    }
+}
 
-    let pass_label = gtk::Label::new(Some("Password:"));
-    pass_label.set_xalign(1.0);
-    let pass_entry = gtk::Entry::new();
-    pass_entry.set_visibility(false);
-    pass_entry.set_input_purpose(gtk::InputPurpose::Password);
-    pass_entry.set_activates_default(true);
+impl Config {
+    // The config as seen by the panes of a profile. Without a profile, or
+    // with an unknown one, this is the config itself.
+    fn with_profile(&self, name: Option<&str>) -> Config {
+        let mut config = self.clone();
+        let Some(profile) = name.and_then(|name| self.profiles.get(name)) else { return config };
+        if let Some(lines) = profile.scrollback_lines {
+            config.scrollback_lines = lines;
+        }
+        if let Some(font) = &profile.font {
+            config.font = font.clone();
+        }
+        if let Some(size) = profile.font_size {
+            config.font_size = size;
+        }
+        if let Some(shell) = &profile.shell {
+            config.shell = shell.clone();
+        }
+        if let Some(theme) = &profile.theme_source {
+            config.theme = Some(theme.clone());
+        }
+        if let Some(cwd) = &profile.new_tab_cwd {
+            config.new_tab_cwd = parse_new_tab_cwd(cwd);
+        }
+        config.env.extend(profile.env.clone());
+        config
+    }
+}
 
-    grid.attach(&name_label, 0, 0, 1, 1);
-    grid.attach(&name_entry, 1, 0, 1, 1);
-    grid.attach(&pass_label, 0, 1, 1, 1);
-    grid.attach(&pass_entry, 1, 1, 1, 1);
-    content.append(&grid);
+// Parses a config file key by key, so a mistake in one setting is reported
+// and only that setting falls back to its default. Files listed in
+// `include` are read first and the including file overrides them.
+fn parse_config_file(
+    path: &Path,
+    problems: &mut Vec<ConfigProblem>,
+    files: &mut Vec<PathBuf>,
+    locations: &mut ConfigLocations,
+) -> toml::Table {
+    parse_included_config_file(path, &mut Vec::new(), problems, files, locations)
+}
 
-    let list_box = list_box.clone();
-    let passwords = passwords.clone();
-    dialog.connect_response(move |dialog, response| {
-        if response == gtk::ResponseType::Ok {
-            let name = name_entry.text().trim().to_string();
-            let password = pass_entry.text().to_string();
-            if !name.is_empty() && !password.is_empty() {
-                let entry = Password { name, password };
-                {
-                    let mut pwds = passwords.borrow_mut();
-                    pwds.push(entry);
-                    save_passwords(&pwds, &secret);
+// `including` holds the canonical paths of the files whose includes are being
+// read, from config.toml down to this one, to tell cycles from files that two
+// others include.
+fn parse_included_config_file(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    problems: &mut Vec<ConfigProblem>,
+    files: &mut Vec<PathBuf>,
+    locations: &mut ConfigLocations,
+) -> toml::Table {
+    files.push(path.to_path_buf());
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            problems.push(ConfigProblem {
+                severity: Severity::Error,
+                file: Some(path.to_path_buf()),
+                position: None,
+                message: format!("cannot read file: {err}"),
+            });
+            return toml::Table::new();
+        }
+    };
+    let table = match toml::from_str::<BTreeMap<String, toml::Spanned<toml::Value>>>(&contents) {
+        Ok(table) => table,
+        Err(err) => {
+            problems.push(ConfigProblem {
+                severity: Severity::Error,
+                file: Some(path.to_path_buf()),
+                position: err.span().map(|span| line_column(&contents, span.start)),
+                message: err.message().trim().replace('\n', ", "),
+            });
+            return toml::Table::new();
+        }
+    };
+    // Nested keys such as `[keybindings]` entries only get a position from
+    // the span-preserving parser.
+    let mut own_locations = ConfigLocations::new();
+    if let Ok(document) = toml_edit::ImDocument::parse(contents.as_str()) {
+        record_config_locations(document.as_table(), "", path, &contents, &mut own_locations);
+    }
+
+    let mut merged = toml::Table::new();
+    let mut valid = toml::Table::new();
+    including.push(canonical_path(path));
+    for (key, value) in table {
+        let position = line_column(&contents, value.span().start);
+        let problem = |message: String| ConfigProblem {
+            severity: Severity::Error,
+            file: Some(path.to_path_buf()),
+            position: Some(position),
+            message,
+        };
+        if key == "include" {
+            let Ok(includes) = value.into_inner().try_into::<Vec<String>>() else {
+                problems.push(problem(
+                    "`include` must be a list of file names".to_string(),
+                ));
+                continue;
+            };
+            let base = path.parent().unwrap_or_else(|| Path::new("."));
+            for include in includes {
+                let include_path = base.join(expand_home(&include));
+                let canonical = canonical_path(&include_path);
+                if including.contains(&canonical) {
+                    problems.push(problem(format!("`{include}` includes itself")));
+                    continue;
                 }
-                populate_password_list(&list_box, &passwords.borrow());
+                // Already merged through another file.
+                if files.iter().any(|file| canonical_path(file) == canonical) {
+                    continue;
+                }
+                let included = parse_included_config_file(
+                    &include_path,
+                    including,
+                    problems,
+                    files,
+                    locations,
+                );
+                merge_config_tables(&mut merged, included);
             }
+            continue;
         }
-        dialog.close();
+        if !config_keys().contains(&key.as_str()) {
+            let hint = closest_name(&key, config_keys().iter().copied())
+                .map(|name| format!("; did you mean `{name}`?"))
+                .unwrap_or_default();
+            problems.push(problem(format!("unknown key `{key}`{hint}")));
+            continue;
+        }
+        let single = toml::Table::from_iter([(key.clone(), value.get_ref().clone())]);
+        match toml::Value::Table(single).try_into::<RawConfig>() {
+            Ok(_) => {
+                valid.insert(key, value.into_inner());
+            }
+            Err(err) => problems.push(problem(format!("`{key}`: {}", err.message()))),
+        }
+    }
+    including.pop();
+    // Set after the includes, like the values, so this file's keys win.
+    own_locations.retain(|key, _| {
+        let top = key.split('.').next().unwrap_or_default();
+        valid.contains_key(top)
     });
+    locations.extend(own_locations);
+    merge_config_tables(&mut merged, valid);
+    merged
+}
 
-    dialog.present();
+// Spellings such as `../conf/config.toml` and symlinks resolve to the same
+// path. A file that does not exist is left as it is and fails to be read.
+fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
 }
 
-fn show_password_manager_dialog(window: &gtk::ApplicationWindow, notebook: &gtk::Notebook, secret: String) {
-    if secret.is_empty() {
-        let dialog = gtk::Dialog::new();
-        dialog.set_title(Some("Şifre Yöneticisi"));
-        dialog.set_modal(true);
-        dialog.set_transient_for(Some(window));
-        dialog.add_button("Tamam", gtk::ResponseType::Ok);
-        let content = dialog.content_area();
-        content.set_margin_top(16);
-        content.set_margin_bottom(16);
-        content.set_margin_start(16);
-        content.set_margin_end(16);
-        let label = gtk::Label::new(Some(
-            "Şifre yöneticisini kullanmak için config.toml dosyasına\n\
-             secret = \"gizli-anahtarınız\" satırını ekleyin.",
-        ));
-        label.set_wrap(true);
-        content.append(&label);
-        dialog.connect_response(|d, _| d.close());
-        dialog.present();
-        return;
+// Records where each key of `table` and of the tables nested in it is set.
+fn record_config_locations(
+    table: &dyn toml_edit::TableLike,
+    prefix: &str,
+    path: &Path,
+    contents: &str,
+    locations: &mut ConfigLocations,
+) {
+    for (key, item) in table.iter() {
+        let name = if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        if let Some(span) = item.span() {
+            let location = ConfigLocation {
+                file: path.to_path_buf(),
+                position: line_column(contents, span.start),
+            };
+            locations.insert(name.clone(), location);
+        }
+        if let Some(table) = item.as_table_like() {
+            record_config_locations(table, &name, path, contents, locations);
+        }
     }
+}
 
-    let secret = Rc::new(secret);
-    let passwords = Rc::new(RefCell::new(load_passwords(&secret)));
-
-    let dialog = gtk::Dialog::new();
-    dialog.set_title(Some("Passwords"));
-    dialog.set_modal(true);
-    dialog.set_transient_for(Some(window));
-    dialog.set_default_size(400, 340);
+// Tables such as `[keybindings]` or `[profiles.x]` are merged key by key;
+// any other value replaces the earlier one.
+fn merge_config_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => {
+                merge_config_tables(base_table, table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
 
-    let content = dialog.content_area();
-    content.set_spacing(8);
-    content.set_margin_top(12);
-    content.set_margin_bottom(12);
-    content.set_margin_start(12);
-    content.set_margin_end(12);
+fn invalid_value_problem(
+    locations: &ConfigLocations,
+    key: &str,
+    value: &str,
+    expected: &str,
+) -> ConfigProblem {
+    ConfigProblem::error(format!(
+        "`{key}`: invalid value \"{value}\", expected {expected}"
+    ))
+    .at(locations, key)
+}
 
-    let search_entry = gtk::SearchEntry::new();
-    search_entry.set_placeholder_text(Some("Ara..."));
-    content.append(&search_entry);
+fn describe_toml_error(contents: &str, err: &toml::de::Error) -> String {
+    let message = err.message().trim().replace('\n', ", ");
+    match err.span() {
+        Some(span) => {
+            let (line, column) = line_column(contents, span.start);
+            format!("line {line}, column {column}: {}", message)
+        }
+        None => message,
+    }
+}
 
-    let list_box = gtk::ListBox::new();
-    list_box.set_selection_mode(gtk::SelectionMode::Single);
-    list_box.set_vexpand(true);
+// 1-based line and column of a byte offset.
+fn line_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+    (line, before[line_start..].chars().count() + 1)
+}
 
-    let scrolled = gtk::ScrolledWindow::new();
-    scrolled.set_child(Some(&list_box));
-    scrolled.set_vexpand(true);
-    scrolled.set_min_content_height(200);
-    content.append(&scrolled);
+// The candidate closest to a misspelled name, if any is close enough.
+fn closest_name<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= 2.max(candidate.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
 
-    populate_password_list(&list_box, &passwords.borrow());
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
 
-    // Filtre fonksiyonu
-    {
-        let search = search_entry.clone();
-        list_box.set_filter_func(move |row| {
-            let text = search.text().to_lowercase();
-            if text.is_empty() { return true; }
-            row.child()
-                .and_then(|c| c.downcast::<gtk::Label>().ok())
-                .is_some_and(|l| l.text().to_lowercase().contains(&text))
-        });
+fn default_keybindings() -> KeyBindings {
+    let mut defaults: Vec<(Action, Vec<&str>)> = vec![
+        (Action::NewTab, vec!["Ctrl+Shift+T"]),
+        (Action::CloseTab, vec!["Ctrl+Shift+W"]),
+        (Action::RenameTab, vec!["Ctrl+Shift+R"]),
+        (Action::ClosePanel, vec!["Ctrl+D"]),
+        (Action::SplitVertical, vec!["Ctrl+Shift+P"]),
+        (Action::SplitHorizontal, vec!["Ctrl+Shift+H"]),
+        (Action::Copy, vec!["Ctrl+Shift+C"]),
+        (Action::Paste, vec!["Ctrl+Shift+V"]),
+        (Action::ReloadConfig, vec!["Ctrl+Shift+L"]),
+        (Action::ShowKeybindings, vec!["Ctrl+Shift+K"]),
+        (Action::SshManager, vec!["Ctrl+Shift+S"]),
+        (Action::PasswordManager, vec!["Ctrl+Shift+A"]),
+        (Action::NewWindow, vec!["Ctrl+Shift+N"]),
+        (Action::SaveSession, vec!["Ctrl+Shift+O"]),
+        (Action::LayoutPicker, vec!["Ctrl+Shift+Y"]),
+        (Action::ProfilePicker, vec!["Ctrl+Shift+E"]),
+        (Action::SwitchTabProfile, vec!["Ctrl+Shift+B"]),
+        (Action::SwitchPaneProfile, vec!["Ctrl+Alt+B"]),
+        (Action::CommandPalette, vec!["Ctrl+Shift+space"]),
+        (Action::Preferences, vec!["Ctrl+comma"]),
+        (Action::ThemePicker, vec!["Ctrl+Shift+M"]),
+        (Action::Search, vec!["Ctrl+Shift+F"]),
+        (Action::ToggleFullscreen, vec!["F11"]),
+        (Action::Focus(FocusDirection::Left), vec!["Alt+Left"]),
+        (Action::Focus(FocusDirection::Right), vec!["Alt+Right"]),
+        (Action::Focus(FocusDirection::Up), vec!["Alt+Up"]),
+        (Action::Focus(FocusDirection::Down), vec!["Alt+Down"]),
+        (Action::NextTab, vec!["Ctrl+Page_Down"]),
+        (Action::PreviousTab, vec!["Ctrl+Page_Up"]),
+        (Action::IncreaseFontSize, vec!["Ctrl+plus", "Ctrl+equal"]),
+        (Action::DecreaseFontSize, vec!["Ctrl+minus"]),
+        (Action::ResetFontSize, vec!["Ctrl+0"]),
+    ];
+    let tab_keys = [
+        "Alt+1", "Alt+2", "Alt+3", "Alt+4", "Alt+5", "Alt+6", "Alt+7", "Alt+8", "Alt+9",
+    ];
+    for (index, key) in tab_keys.into_iter().enumerate() {
+        defaults.push((Action::SelectTab(index as u32 + 1), vec![key]));
     }
 
-    // Arama değiştiğinde filtrele ve ilk görünür satırı seç
-    {
-        let list_box = list_box.clone();
-        search_entry.connect_search_changed(move |_| {
-            list_box.invalidate_filter();
-            select_first_visible(&list_box);
-        });
+    let entries = defaults
+        .into_iter()
+        .map(|(action, keys)| {
+            let bindings = keys
+                .into_iter()
+                .filter_map(|keys| parse_key_chord(keys).ok())
+                .collect();
+            (action, bindings)
+        })
+        .collect();
+    KeyBindings { entries }
+}
+
+fn apply_keybindings(
+    bindings: &mut KeyBindings,
+    raw: RawKeyBindings,
+    locations: &ConfigLocations,
+) -> Vec<ConfigProblem> {
+    let mut problems = Vec::new();
+    let mut configured: Vec<Action> = Vec::new();
+    // The same locations by action name, which the key in the file may
+    // spell differently.
+    let mut action_locations = ConfigLocations::new();
+    for (name, keys) in raw {
+        let Some(action) = parse_action(&name) else {
+            let base = name.split(':').next().unwrap_or_default();
+            let hint = closest_name(base, action_names())
+                .map(|name| format!("; did you mean `{name}`?"))
+                .unwrap_or_default();
+            problems.push(
+                ConfigProblem::error(format!("[keybindings] unknown action `{name}`{hint}"))
+                    .at(locations, &format!("keybindings.{name}")),
+            );
+            continue;
+        };
+        if let Some(location) = locations.get(&format!("keybindings.{name}")) {
+            action_locations.insert(action.name(), location.clone());
+        }
+        let keys = match keys {
+            RawKeys::One(key) => vec![key],
+            RawKeys::Many(keys) => keys,
+        };
+        let mut chords: Vec<KeyChord> = Vec::new();
+        for key in keys
+            .iter()
+            .filter(|key| !key.trim().eq_ignore_ascii_case("none"))
+        {
+            match parse_key_chord(key) {
+                Ok(chord) => chords.push(chord),
+                Err(err) => problems.push(
+                    ConfigProblem::error(format!("[keybindings] {name} = \"{key}\": {err}"))
+                        .at(locations, &format!("keybindings.{name}")),
+                ),
+            }
+        }
+
+        // A combination taken by the user is removed from the defaults of
+        // other actions, so custom bindings win over built-in ones.
+        for (other, other_keys) in &mut bindings.entries {
+            if *other != action && !configured.contains(other) {
+                other_keys.retain(|binding| !chords.contains(binding));
+            }
+        }
+        bindings.set(action.clone(), chords);
+        configured.push(action);
     }
+    problems.extend(check_keybindings(bindings, &configured, &action_locations));
+    problems
+}
 
-    // Arama kutusunda ↓ → listeye geç; Escape → kapat
-    {
-        let ctrl = gtk::EventControllerKey::new();
-        let list_box = list_box.clone();
-        let dialog = dialog.clone();
-        ctrl.connect_key_pressed(move |_, key, _, _| match key {
-            gdk::Key::Down => {
-                if let Some(row) = list_box.selected_row() {
-                    row.grab_focus();
+// Reports bindings that can never trigger because another one takes the
+// same keys first, and user bindings that hide a common shell shortcut.
+// `locations` is keyed by action name.
+fn check_keybindings(
+    bindings: &KeyBindings,
+    configured: &[Action],
+    locations: &ConfigLocations,
+) -> Vec<ConfigProblem> {
+    let mut problems = Vec::new();
+    let all: Vec<(&Action, &KeyChord)> = bindings
+        .entries
+        .iter()
+        .flat_map(|(action, chords)| chords.iter().map(move |chord| (action, chord)))
+        .collect();
+
+    for (index, (action, chord)) in all.iter().enumerate() {
+        for (other, other_chord) in &all[index + 1..] {
+            let message = if other_chord.len() == chord.len() {
+                if !chord_starts_with(other_chord, chord) {
+                    continue;
+                }
+                // Lookup goes through the entries in order, so the first wins.
+                format!(
+                    "{} is bound to both {} and {}; only {} is used",
+                    format_key_chord(chord),
+                    action.name(),
+                    other.name(),
+                    action.name()
+                )
+            } else {
+                let (short, short_action, long, long_action) = if chord.len() < other_chord.len() {
+                    (chord, action, other_chord, other)
                 } else {
-                    select_first_visible(&list_box);
-                    if let Some(row) = list_box.selected_row() {
-                        row.grab_focus();
-                    }
+                    (other_chord, other, chord, action)
+                };
+                if !chord_starts_with(long, short) {
+                    continue;
                 }
-                gtk::glib::Propagation::Stop
-            }
-            gdk::Key::Escape => {
-                dialog.close();
-                gtk::glib::Propagation::Stop
+                format!(
+                    "{} ({}) hides the chord {} ({})",
+                    format_key_chord(short),
+                    short_action.name(),
+                    format_key_chord(long),
+                    long_action.name()
+                )
+            };
+            // Points at the second binding, or the first if only that one
+            // was set in the config.
+            let located = [other, action]
+                .into_iter()
+                .map(|entry| entry.name())
+                .find(|name| locations.contains_key(name))
+                .unwrap_or_default();
+            problems.push(ConfigProblem::error(message).at(locations, &located));
+        }
+    }
+
+    for (action, chord) in all.iter().filter(|(action, _)| configured.contains(action)) {
+        let Some(first) = chord.first() else { continue };
+        for (shortcut, meaning) in SHELL_SHORTCUTS {
+            let Ok(shortcut) = parse_keybinding(shortcut) else { continue };
+            if shortcut.matches(first.key, first.modifiers) {
+                let problem = ConfigProblem {
+                    severity: Severity::Warning,
+                    file: None,
+                    position: None,
+                    message: format!(
+                        "{} ({}) takes {} away from the shell ({meaning})",
+                        format_key_chord(chord),
+                        action.name(),
+                        format_keybinding(first)
+                    ),
+                };
+                problems.push(problem.at(locations, &action.name()));
             }
-            _ => gtk::glib::Propagation::Proceed,
-        });
-        search_entry.add_controller(ctrl);
+        }
     }
+    problems
+}
 
-    // Listede ↑ + ilk satır → arama kutusuna dön
-    {
-        let ctrl = gtk::EventControllerKey::new();
-        ctrl.set_propagation_phase(gtk::PropagationPhase::Capture);
-        let search = search_entry.clone();
-        let list_box_mv = list_box.clone();
-        let list_box_ac = list_box.clone();
-        ctrl.connect_key_pressed(move |_, key, _, _| {
-            if key == gdk::Key::Up {
-                if let Some(row) = list_box_mv.selected_row() {
-                    if Some(row.index()) == first_visible_index(&list_box_mv) {
-                        search.grab_focus();
-                        return gtk::glib::Propagation::Stop;
-                    }
+impl KeyBindings {
+    // Looks up the keys pressed so far. A complete chord wins over a longer
+    // one that starts with the same keys.
+    fn lookup(&self, keys: &[KeyBinding]) -> ChordMatch {
+        let mut prefix = false;
+        for (action, chords) in &self.entries {
+            for chord in chords {
+                if !chord_starts_with(chord, keys) {
+                    continue;
+                }
+                if chord.len() == keys.len() {
+                    return ChordMatch::Action(action.clone());
                 }
+                prefix = true;
             }
-            gtk::glib::Propagation::Proceed
-        });
-        list_box_ac.add_controller(ctrl);
+        }
+        if prefix {
+            ChordMatch::Prefix
+        } else {
+            ChordMatch::None
+        }
     }
 
-    let btn_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-    btn_box.set_margin_top(4);
-    btn_box.set_halign(gtk::Align::End);
+    fn bindings(&self, action: &Action) -> &[KeyChord] {
+        self.entries
+            .iter()
+            .find(|(existing, _)| existing == action)
+            .map(|(_, chords)| chords.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn set(&mut self, action: Action, bindings: Vec<KeyChord>) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|(existing, _)| *existing == action)
+        {
+            Some(entry) => entry.1 = bindings,
+            None => self.entries.push((action, bindings)),
+        }
+    }
+}
+
+impl Action {
+    // The name used as key in `[keybindings]`.
+    fn name(&self) -> String {
+        if let Some((name, _)) = SIMPLE_ACTIONS.iter().find(|(_, action)| action == self) {
+            return name.to_string();
+        }
+        match self {
+            Action::NewTabWithProfile(profile) => format!("new_tab:{profile}"),
+            Action::SelectTab(number) => format!("select_tab:{number}"),
+            Action::SendText(text) => format!("send_text:{}", escape_text(text)),
+            Action::RunCommand(command) => format!("run_command:{command}"),
+            Action::ScrollLines(lines) => format!("scroll_lines:{lines}"),
+            Action::ScrollPages(pages) => format!("scroll_pages:{pages}"),
+            _ => unreachable!("{self:?} is missing from SIMPLE_ACTIONS"),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Action::NewTab => "New tab".to_string(),
+            Action::NewWindow => "New window".to_string(),
+            Action::CloseTab => "Close tab".to_string(),
+            Action::RenameTab => "Rename tab".to_string(),
+            Action::ClosePanel => "Close panel".to_string(),
+            Action::SplitVertical => "Split vertical".to_string(),
+            Action::SplitHorizontal => "Split horizontal".to_string(),
+            Action::Copy => "Copy".to_string(),
+            Action::Paste => "Paste".to_string(),
+            Action::ReloadConfig => "Reload config/theme".to_string(),
+            Action::ShowKeybindings => "Show keybindings".to_string(),
+            Action::SshManager => "SSH manager".to_string(),
+            Action::PasswordManager => "Password manager".to_string(),
+            Action::SaveSession => "Save session".to_string(),
+            Action::LayoutPicker => "Open layout".to_string(),
+            Action::ProfilePicker => "New tab with profile".to_string(),
+            Action::NewTabWithProfile(profile) => format!("New tab ({profile})"),
+            Action::SwitchTabProfile => "Switch tab profile".to_string(),
+            Action::SwitchPaneProfile => "Switch pane profile".to_string(),
+            Action::CommandPalette => "Command palette".to_string(),
+            Action::Preferences => "Preferences".to_string(),
+            Action::ThemePicker => "Choose theme".to_string(),
+            Action::Search => "Search".to_string(),
+            Action::ToggleFullscreen => "Toggle fullscreen".to_string(),
+            Action::Focus(FocusDirection::Left) => "Focus left".to_string(),
+            Action::Focus(FocusDirection::Right) => "Focus right".to_string(),
+            Action::Focus(FocusDirection::Up) => "Focus up".to_string(),
+            Action::Focus(FocusDirection::Down) => "Focus down".to_string(),
+            Action::SelectTab(number) => format!("Switch tab {number}"),
+            Action::NextTab => "Next tab".to_string(),
+            Action::PreviousTab => "Previous tab".to_string(),
+            Action::SendText(text) => format!("Send \"{}\"", escape_text(text)),
+            Action::RunCommand(command) => format!("Run \"{command}\""),
+            Action::ScrollLines(lines) => format!("Scroll {lines} lines"),
+            Action::ScrollPages(pages) => format!("Scroll {pages} pages"),
+            Action::ScrollToTop => "Scroll to top".to_string(),
+            Action::ScrollToBottom => "Scroll to bottom".to_string(),
+            Action::IncreaseFontSize => "Increase font size".to_string(),
+            Action::DecreaseFontSize => "Decrease font size".to_string(),
+            Action::ResetFontSize => "Reset font size".to_string(),
+        }
+    }
+}
 
-    let add_btn = gtk::Button::with_label("Add");
-    let delete_btn = gtk::Button::with_label("Delete");
-    let paste_btn = gtk::Button::with_label("Paste");
-    let close_btn = gtk::Button::with_label("Close");
-    btn_box.append(&add_btn);
-    btn_box.append(&delete_btn);
-    btn_box.append(&paste_btn);
-    btn_box.append(&close_btn);
-    content.append(&btn_box);
+// Actions without an argument by their `[keybindings]` name, shared by
+// `parse_action` and `Action::name`.
+const SIMPLE_ACTIONS: &[(&str, Action)] = &[
+    ("new_tab", Action::NewTab),
+    ("new_window", Action::NewWindow),
+    ("close_tab", Action::CloseTab),
+    ("rename_tab", Action::RenameTab),
+    ("close_panel", Action::ClosePanel),
+    ("split_vertical", Action::SplitVertical),
+    ("split_horizontal", Action::SplitHorizontal),
+    ("copy", Action::Copy),
+    ("paste", Action::Paste),
+    ("reload_config", Action::ReloadConfig),
+    ("show_keybindings", Action::ShowKeybindings),
+    ("ssh_manager", Action::SshManager),
+    ("password_manager", Action::PasswordManager),
+    ("save_session", Action::SaveSession),
+    ("layout_picker", Action::LayoutPicker),
+    ("profile_picker", Action::ProfilePicker),
+    ("switch_tab_profile", Action::SwitchTabProfile),
+    ("switch_pane_profile", Action::SwitchPaneProfile),
+    ("command_palette", Action::CommandPalette),
+    ("preferences", Action::Preferences),
+    ("theme_picker", Action::ThemePicker),
+    ("search", Action::Search),
+    ("fullscreen", Action::ToggleFullscreen),
+    ("focus_left", Action::Focus(FocusDirection::Left)),
+    ("focus_right", Action::Focus(FocusDirection::Right)),
+    ("focus_up", Action::Focus(FocusDirection::Up)),
+    ("focus_down", Action::Focus(FocusDirection::Down)),
+    ("next_tab", Action::NextTab),
+    ("previous_tab", Action::PreviousTab),
+    ("scroll_to_top", Action::ScrollToTop),
+    ("scroll_to_bottom", Action::ScrollToBottom),
+    ("increase_font_size", Action::IncreaseFontSize),
+    ("decrease_font_size", Action::DecreaseFontSize),
+    ("reset_font_size", Action::ResetFontSize),
+];
+
+// Actions that take their argument after a colon.
+const ARGUMENT_ACTIONS: &[&str] = &[
+    "new_tab",
+    "select_tab",
+    "send_text",
+    "run_command",
+    "scroll_lines",
+    "scroll_pages",
+];
+
+// Action names without their argument, for "did you mean" hints.
+fn action_names() -> impl Iterator<Item = &'static str> {
+    let simple = SIMPLE_ACTIONS.iter().map(|(name, _)| *name);
+    simple.chain(ARGUMENT_ACTIONS.iter().copied())
+}
 
-    // Add button
+// Parametrised actions take their argument after a colon, e.g.
+// `select_tab:3`, `send_text:\e[A` or `run_command:htop`.
+fn parse_action(name: &str) -> Option<Action> {
+    let (name, argument) = match name.split_once(':') {
+        Some((name, argument)) => (name.trim(), Some(argument)),
+        None => (name.trim(), None),
+    };
+    if argument.is_none()
+        && let Some((_, action)) = SIMPLE_ACTIONS.iter().find(|(simple, _)| *simple == name)
     {
-        let dialog = dialog.clone();
-        let list_box = list_box.clone();
-        let passwords = passwords.clone();
-        let secret = secret.clone();
-        add_btn.connect_clicked(move |_| {
-            show_add_password_dialog(&dialog, &list_box, &passwords, secret.clone());
-        });
+        return Some(action.clone());
     }
+    let action = match (name, argument) {
+        ("new_tab", Some(profile)) if !profile.trim().is_empty() => {
+            Action::NewTabWithProfile(profile.trim().to_string())
+        }
+        ("select_tab", Some(number)) => {
+            Action::SelectTab(number.trim().parse().ok().filter(|number| *number > 0)?)
+        }
+        ("send_text", Some(text)) => Action::SendText(unescape_text(text)),
+        ("run_command", Some(command)) if !command.trim().is_empty() => {
+            Action::RunCommand(command.to_string())
+        }
+        ("scroll_lines", Some(lines)) => Action::ScrollLines(lines.trim().parse().ok()?),
+        ("scroll_pages", Some(pages)) => Action::ScrollPages(pages.trim().parse().ok()?),
+        // `tab_1` .. `tab_9` from older configs.
+        (name, None) => Action::SelectTab(name.strip_prefix("tab_")?.parse().ok()?),
+        _ => return None,
+    };
+    Some(action)
+}
 
-    // Delete button
-    {
-        let list_box = list_box.clone();
-        let passwords = passwords.clone();
-        let secret = secret.clone();
-        delete_btn.connect_clicked(move |_| {
-            let Some(row) = list_box.selected_row() else { return };
-            let index = row.index() as usize;
-            {
-                let mut pwds = passwords.borrow_mut();
-                if index < pwds.len() {
-                    pwds.remove(index);
+// Escapes understood in `send_text`: \e, \n, \r, \t, \\ and \xHH. `\xHH`
+// is a single byte, not a character.
+fn unescape_text(text: &str) -> Vec<u8> {
+    let mut unescaped = Vec::new();
+    let mut chars = text.chars();
+    let push = |unescaped: &mut Vec<u8>, ch: char| {
+        unescaped.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+    };
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            push(&mut unescaped, ch);
+            continue;
+        }
+        match chars.next() {
+            Some('e') => unescaped.push(0x1b),
+            Some('n') => unescaped.push(b'\n'),
+            Some('r') => unescaped.push(b'\r'),
+            Some('t') => unescaped.push(b'\t'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) => unescaped.push(byte),
+                    Err(_) => {
+                        unescaped.extend_from_slice(b"\\x");
+                        unescaped.extend_from_slice(hex.as_bytes());
+                    }
                 }
-                save_passwords(&pwds, &secret);
             }
-            populate_password_list(&list_box, &passwords.borrow());
-            list_box.invalidate_filter();
-            select_first_visible(&list_box);
-        });
+            Some(other) => push(&mut unescaped, other),
+            None => unescaped.push(b'\\'),
+        }
     }
+    unescaped
+}
 
-    // Paste button
-    {
-        let dialog = dialog.clone();
-        let notebook = notebook.clone();
-        let passwords = passwords.clone();
-        let list_box = list_box.clone();
-        paste_btn.connect_clicked(move |_| {
-            password_paste_selected(&dialog, &notebook, &list_box, &passwords);
-        });
+// Bytes that are not valid UTF-8 are written as `\xHH`.
+fn escape_text(text: &[u8]) -> String {
+    let mut escaped = String::new();
+    for chunk in text.utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            match ch {
+                '\x1b' => escaped.push_str("\\e"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                '\\' => escaped.push_str("\\\\"),
+                ch if ch.is_control() => {
+                    for byte in ch.encode_utf8(&mut [0; 4]).bytes() {
+                        escaped.push_str(&format!("\\x{byte:02x}"));
+                    }
+                }
+                ch => escaped.push(ch),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{byte:02x}"));
+        }
     }
+    escaped
+}
 
-    // Close button
-    {
-        let dialog = dialog.clone();
-        close_btn.connect_clicked(move |_| dialog.close());
+// Chord keys are separated by commas: `Ctrl+A, c`. A comma right after `+`
+// or on its own is the comma key: `Ctrl+,` and `Ctrl+A, ,`.
+fn parse_key_chord(text: &str) -> Result<KeyChord, String> {
+    let mut keys = Vec::new();
+    let mut start = 0;
+    for (index, ch) in text.char_indices() {
+        let key = text[start..index].trim();
+        if ch == ',' && !key.is_empty() && !key.ends_with('+') {
+            keys.push(&text[start..index]);
+            start = index + 1;
+        }
     }
+    keys.push(&text[start..]);
+    keys.into_iter().map(parse_keybinding).collect()
+}
 
-    // Row activated: Enter or double-click → paste
-    {
-        let dialog = dialog.clone();
-        let notebook = notebook.clone();
-        let passwords = passwords.clone();
-        list_box.connect_row_activated(move |lb, _| {
-            password_paste_selected(&dialog, &notebook, lb, &passwords);
-        });
-    }
+// Punctuation keys that can be written as themselves, e.g. `Ctrl+,`.
+const PUNCTUATION_KEYS: &[(&str, &str)] = &[
+    (",", "comma"),
+    (".", "period"),
+    (";", "semicolon"),
+    ("/", "slash"),
+    ("\\", "backslash"),
+    ("-", "minus"),
+    ("=", "equal"),
+    ("[", "bracketleft"),
+    ("]", "bracketright"),
+    ("'", "apostrophe"),
+    ("`", "grave"),
+];
+
+fn parse_keybinding(text: &str) -> Result<KeyBinding, String> {
+    let mut modifiers = gdk::ModifierType::empty();
+    let mut key: Option<gdk::Key> = None;
 
-    // Delete key removes selected entry
-    {
-        let key_ctrl = gtk::EventControllerKey::new();
-        let passwords = passwords.clone();
-        let list_box = list_box.clone();
-        let secret = secret.clone();
-        key_ctrl.connect_key_pressed(move |_, key, _, _| {
-            if key == gdk::Key::Delete {
-                let Some(row) = list_box.selected_row() else {
-                    return gtk::glib::Propagation::Proceed;
-                };
-                let index = row.index() as usize;
-                {
-                    let mut pwds = passwords.borrow_mut();
-                    if index < pwds.len() {
-                        pwds.remove(index);
-                    }
-                    save_passwords(&pwds, &secret);
+    for part in text.split('+') {
+        let token = part.trim();
+        if token.is_empty() {
+            continue;
+        }
+
+        match token.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => modifiers |= gdk::ModifierType::CONTROL_MASK,
+            "shift" => modifiers |= gdk::ModifierType::SHIFT_MASK,
+            "alt" | "option" => modifiers |= gdk::ModifierType::ALT_MASK,
+            "super" | "meta" | "win" => modifiers |= gdk::ModifierType::SUPER_MASK,
+            _ => {
+                let parsed = gdk::Key::from_name(token)
+                    .or_else(|| gdk::Key::from_name(token.to_ascii_uppercase()))
+                    .or_else(|| {
+                        let (_, name) = PUNCTUATION_KEYS.iter().find(|(ch, _)| *ch == token)?;
+                        gdk::Key::from_name(*name)
+                    })
+                    .ok_or_else(|| format!("unknown key name `{token}`"))?;
+                if key.is_some() {
+                    return Err(format!("more than one key in `{}`", text.trim()));
                 }
-                populate_password_list(&list_box, &passwords.borrow());
-                list_box.invalidate_filter();
-                select_first_visible(&list_box);
-                return gtk::glib::Propagation::Stop;
+                key = Some(parsed);
             }
-            gtk::glib::Propagation::Proceed
-        });
-        dialog.add_controller(key_ctrl);
+        }
     }
 
-    search_entry.grab_focus();
-    dialog.present();
+    key.map(|key| KeyBinding { key, modifiers })
+        .ok_or_else(|| format!("no key in `{}`", text.trim()))
 }
 
-fn ssh_servers_path() -> Option<PathBuf> {
-    ProjectDirs::from("", "", "termilyon")
-        .map(|dirs| dirs.config_dir().join("ssh_servers.toml"))
+// Whether the chord begins with the given keys (or is equal to them).
+fn chord_starts_with(chord: &[KeyBinding], keys: &[KeyBinding]) -> bool {
+    chord.len() >= keys.len()
+        && chord
+            .iter()
+            .zip(keys)
+            .all(|(binding, pressed)| binding.matches(pressed.key, pressed.modifiers))
 }
 
-fn load_ssh_servers() -> Vec<SshServer> {
-    let Some(path) = ssh_servers_path() else { return Vec::new() };
-    let Ok(content) = fs::read_to_string(&path) else { return Vec::new() };
-    toml::from_str::<SshServers>(&content)
-        .map(|s| s.servers)
-        .unwrap_or_default()
+impl KeyBinding {
+    fn matches(&self, key: gdk::Key, state: gdk::ModifierType) -> bool {
+        let relevant = gdk::ModifierType::CONTROL_MASK
+            | gdk::ModifierType::SHIFT_MASK
+            | gdk::ModifierType::ALT_MASK
+            | gdk::ModifierType::SUPER_MASK;
+        let key_matches = key == self.key
+            || key.to_lower() == self.key.to_lower()
+            || key.to_upper() == self.key.to_upper();
+        // Symbols such as `bar` or `percent` already imply Shift on most
+        // layouts, so it is only compared when the binding asks for it.
+        let shifted_symbol = self
+            .key
+            .to_unicode()
+            .is_some_and(|ch| ch.is_ascii_punctuation());
+        let mut state = state & relevant;
+        if shifted_symbol && !self.modifiers.contains(gdk::ModifierType::SHIFT_MASK) {
+            state.remove(gdk::ModifierType::SHIFT_MASK);
+        }
+        key_matches && state == self.modifiers
+    }
 }
 
-fn save_ssh_servers(servers: &[SshServer]) {
-    let Some(path) = ssh_servers_path() else { return };
-    if let Some(parent) = path.parent() {
-        let _ = fs::create_dir_all(parent);
+impl PendingChord {
+    fn take(&self) -> Vec<KeyBinding> {
+        if let Some(source) = self.timeout.take() {
+            source.remove();
+        }
+        self.indicator.set_visible(false);
+        self.keys.take()
     }
-    let data = SshServers { servers: servers.to_vec() };
-    if let Ok(content) = toml::to_string(&data) {
-        let _ = fs::write(&path, content);
+
+    fn start(self: &Rc<Self>, keys: Vec<KeyBinding>, timeout_ms: u32) {
+        self.indicator
+            .set_label(&format!("{}, \u{2026}", format_key_chord(&keys)));
+        self.indicator.set_visible(true);
+        *self.keys.borrow_mut() = keys;
+        if timeout_ms == 0 {
+            return;
+        }
+        let chord = self.clone();
+        let source = gtk::glib::timeout_add_local_once(
+            Duration::from_millis(u64::from(timeout_ms)),
+            move || {
+                // The source is gone once it has fired.
+                chord.timeout.take();
+                chord.take();
+            },
+        );
+        *self.timeout.borrow_mut() = Some(source);
     }
 }
 
-fn populate_server_list(list_box: &gtk::ListBox, servers: &[SshServer]) {
-    while let Some(row) = list_box.row_at_index(0) {
-        list_box.remove(&row);
-    }
-    for srv in servers {
-        let text = if srv.port == 22 {
-            format!("{}  —  {}@{}", srv.name, srv.user, srv.host)
-        } else {
-            format!("{}  —  {}@{}:{}", srv.name, srv.user, srv.host, srv.port)
-        };
-        let label = gtk::Label::new(Some(&text));
-        label.set_xalign(0.0);
-        label.set_margin_top(6);
-        label.set_margin_bottom(6);
-        label.set_margin_start(8);
-        label.set_margin_end(8);
-        let row = gtk::ListBoxRow::new();
-        row.set_child(Some(&label));
-        list_box.append(&row);
+fn is_modifier_key(key: gdk::Key) -> bool {
+    matches!(
+        key,
+        gdk::Key::Control_L
+            | gdk::Key::Control_R
+            | gdk::Key::Shift_L
+            | gdk::Key::Shift_R
+            | gdk::Key::Alt_L
+            | gdk::Key::Alt_R
+            | gdk::Key::Super_L
+            | gdk::Key::Super_R
+            | gdk::Key::Meta_L
+            | gdk::Key::Meta_R
+            | gdk::Key::ISO_Level3_Shift
+            | gdk::Key::Caps_Lock
+    )
+}
+
+fn parse_new_tab_cwd(value: &str) -> NewTabCwd {
+    match value.trim() {
+        "" | "home" => NewTabCwd::Home,
+        "current" => NewTabCwd::Current,
+        path => NewTabCwd::Path(expand_home(path)),
     }
-    if let Some(first) = list_box.row_at_index(0) {
-        list_box.select_row(Some(&first));
+}
+
+fn parse_decorations(value: &str) -> Option<Decorations> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "none" => Some(Decorations::None),
+        "system" => Some(Decorations::System),
+        "headerbar" => Some(Decorations::HeaderBar),
+        _ => None,
     }
 }
 
-fn ssh_connect_selected(
-    dialog: &gtk::Dialog,
-    notebook: &gtk::Notebook,
-    list_box: &gtk::ListBox,
-    servers: &Rc<RefCell<Vec<SshServer>>>,
-) {
-    let Some(row) = list_box.selected_row() else { return };
-    let index = row.index() as usize;
-    let servers_ref = servers.borrow();
-    let Some(srv) = servers_ref.get(index) else { return };
-    let cmd = if srv.port == 22 {
-        format!("ssh {}@{}\n", srv.user, srv.host)
-    } else {
-        format!("ssh -p {} {}@{}\n", srv.port, srv.user, srv.host)
-    };
-    let Some(page) = notebook.current_page() else { return };
-    let Some(child) = notebook.nth_page(Some(page)) else { return };
-    if let Some(terminal) = find_terminal_in_widget(&child) {
-        terminal.feed_child(cmd.as_bytes());
+fn parse_tab_bar_position(value: &str) -> Option<gtk::PositionType> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "top" => Some(gtk::PositionType::Top),
+        "bottom" => Some(gtk::PositionType::Bottom),
+        _ => None,
     }
-    dialog.close();
 }
 
-fn show_add_server_dialog(
-    parent: &gtk::Dialog,
-    list_box: &gtk::ListBox,
-    servers: &Rc<RefCell<Vec<SshServer>>>,
-) {
-    let dialog = gtk::Dialog::new();
-    dialog.set_title(Some("Add SSH Server"));
-    dialog.set_modal(true);
-    dialog.set_transient_for(Some(parent));
-    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-    dialog.add_button("Add", gtk::ResponseType::Ok);
-    dialog.set_default_response(gtk::ResponseType::Ok);
+fn find_layout(config: &Config, name: &str) -> Option<Layout> {
+    config
+        .layouts
+        .iter()
+        .find(|layout| layout.name == name)
+        .cloned()
+}
 
-    let content = dialog.content_area();
-    content.set_spacing(8);
-    content.set_margin_top(12);
-    content.set_margin_bottom(12);
-    content.set_margin_start(12);
-    content.set_margin_end(12);
+fn open_layout(
+    notebook: &gtk::Notebook,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+    layout: &Layout,
+) -> Option<Terminal> {
+    let first_page = notebook.n_pages();
+    let mut first_terminal = None;
+    for tab in &layout.tabs {
+        let (root, terminal) = build_layout_pane(&tab.pane, None, config);
+        append_tab(notebook, config, counter, &root, tab.title.as_deref());
+        first_terminal.get_or_insert(terminal);
+    }
 
-    let grid = gtk::Grid::new();
-    grid.set_row_spacing(8);
-    grid.set_column_spacing(8);
+    if first_page < notebook.n_pages() {
+        notebook.set_current_page(Some(first_page));
+        focus_terminal_in_page(notebook, first_page);
+    }
+    first_terminal
+}
 
-    let make_label = |text: &str| {
-        let l = gtk::Label::new(Some(text));
-        l.set_xalign(1.0);
-        l
-    };
-    let make_entry = |placeholder: &str, default: &str| {
-        let e = gtk::Entry::new();
-        e.set_placeholder_text(Some(placeholder));
-        if !default.is_empty() {
-            e.set_text(default);
-        }
-        e.set_activates_default(true);
-        e
-    };
+// A pane's `cwd` is inherited by the panes nested inside it unless they set
+// their own.
+fn build_layout_pane(
+    pane: &LayoutPane,
+    inherited_cwd: Option<&Path>,
+    config: &Rc<RefCell<Config>>,
+) -> (gtk::Widget, Terminal) {
+    let cwd = pane
+        .cwd
+        .as_deref()
+        .map(expand_home)
+        .or_else(|| inherited_cwd.map(Path::to_path_buf));
+
+    if pane.panes.is_empty() {
+        let shell = config.borrow().shell.clone();
+        let options = SpawnOptions {
+            cwd,
+            command: pane
+                .command
+                .clone()
+                .map(|command| vec![shell, "-c".to_string(), command]),
+            ..SpawnOptions::default()
+        };
+        let terminal_widget = create_terminal_widget(&config.borrow(), &options);
+        attach_terminal_handlers(&terminal_widget.terminal, config);
+        return (terminal_widget.scrolled.upcast(), terminal_widget.terminal);
+    }
 
-    let name_entry = make_entry("My Server", "");
-    let host_entry = make_entry("192.168.1.1", "");
-    let user_entry = make_entry("root", "");
-    let port_entry = make_entry("22", "22");
+    let orientation = pane
+        .split
+        .as_deref()
+        .and_then(parse_split_direction)
+        .unwrap_or(gtk::Orientation::Horizontal);
+    build_layout_split(&pane.panes, orientation, pane.ratio, cwd.as_deref(), config)
+}
 
-    grid.attach(&make_label("Name:"), 0, 0, 1, 1);
-    grid.attach(&name_entry, 1, 0, 1, 1);
-    grid.attach(&make_label("Host:"), 0, 1, 1, 1);
-    grid.attach(&host_entry, 1, 1, 1, 1);
-    grid.attach(&make_label("User:"), 0, 2, 1, 1);
-    grid.attach(&user_entry, 1, 2, 1, 1);
-    grid.attach(&make_label("Port:"), 0, 3, 1, 1);
-    grid.attach(&port_entry, 1, 3, 1, 1);
+// Paned only holds two children, so a split with more panes nests the
+// remaining ones and divides the space evenly unless a ratio is given for the
+// first divider.
+fn build_layout_split(
+    panes: &[LayoutPane],
+    orientation: gtk::Orientation,
+    ratio: Option<f64>,
+    inherited_cwd: Option<&Path>,
+    config: &Rc<RefCell<Config>>,
+) -> (gtk::Widget, Terminal) {
+    let (start, terminal) = build_layout_pane(&panes[0], inherited_cwd, config);
+    if panes.len() == 1 {
+        return (start, terminal);
+    }
 
-    content.append(&grid);
+    let (end, _) = build_layout_split(&panes[1..], orientation, None, inherited_cwd, config);
+    let paned = new_split_paned(orientation);
+    paned.set_start_child(Some(&start));
+    paned.set_end_child(Some(&end));
+    set_paned_ratio(&paned, ratio.unwrap_or(1.0 / panes.len() as f64));
+    (paned.upcast(), terminal)
+}
 
-    let list_box = list_box.clone();
-    let servers = servers.clone();
-    dialog.connect_response(move |dialog, response| {
-        if response == gtk::ResponseType::Ok {
-            let name = name_entry.text().trim().to_string();
-            let host = host_entry.text().trim().to_string();
-            let user = user_entry.text().trim().to_string();
-            let port: u16 = port_entry.text().trim().parse().unwrap_or(22);
-            if !name.is_empty() && !host.is_empty() && !user.is_empty() {
-                let srv = SshServer { name, host, user, port };
-                {
-                    let mut svs = servers.borrow_mut();
-                    svs.push(srv);
-                    save_ssh_servers(&svs);
-                }
-                populate_server_list(&list_box, &servers.borrow());
-            }
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(format!("{home}{rest}"))
         }
-        dialog.close();
-    });
+        _ => PathBuf::from(path),
+    }
+}
 
+// A searchable list of names; `on_pick` gets the index of the chosen one.
+fn show_name_picker_dialog(
+    window: &gtk::ApplicationWindow,
+    title: &str,
+    names: &[String],
+    on_pick: impl Fn(usize) + 'static,
+) {
+    let (dialog, _) = build_name_picker_dialog(window, title, names, on_pick);
     dialog.present();
 }
 
-fn show_ssh_manager_dialog(window: &gtk::ApplicationWindow, notebook: &gtk::Notebook) {
-    let servers = Rc::new(RefCell::new(load_ssh_servers()));
-
+fn build_name_picker_dialog(
+    window: &gtk::ApplicationWindow,
+    title: &str,
+    names: &[String],
+    on_pick: impl Fn(usize) + 'static,
+) -> (gtk::Dialog, gtk::ListBox) {
     let dialog = gtk::Dialog::new();
-    dialog.set_title(Some("SSH Servers"));
+    dialog.set_title(Some(title));
     dialog.set_modal(true);
     dialog.set_transient_for(Some(window));
-    dialog.set_default_size(520, 360);
+    dialog.set_default_size(400, 300);
 
     let content = dialog.content_area();
     content.set_spacing(8);
@@ -1802,10 +7357,23 @@ fn show_ssh_manager_dialog(window: &gtk::ApplicationWindow, notebook: &gtk::Note
     let scrolled = gtk::ScrolledWindow::new();
     scrolled.set_child(Some(&list_box));
     scrolled.set_vexpand(true);
-    scrolled.set_min_content_height(220);
+    scrolled.set_min_content_height(180);
     content.append(&scrolled);
 
-    populate_server_list(&list_box, &servers.borrow());
+    for name in names {
+        let label = gtk::Label::new(Some(name));
+        label.set_xalign(0.0);
+        label.set_margin_top(6);
+        label.set_margin_bottom(6);
+        label.set_margin_start(8);
+        label.set_margin_end(8);
+        let row = gtk::ListBoxRow::new();
+        row.set_child(Some(&label));
+        list_box.append(&row);
+    }
+    if let Some(first) = list_box.row_at_index(0) {
+        list_box.select_row(Some(&first));
+    }
 
     // Filtre fonksiyonu
     {
@@ -1828,21 +7396,25 @@ fn show_ssh_manager_dialog(window: &gtk::ApplicationWindow, notebook: &gtk::Note
         });
     }
 
-    // Arama kutusunda ↓ → listeye geç; Escape → dialog'u kapat
+    let open_selected = {
+        let dialog = dialog.clone();
+        let list_box = list_box.clone();
+        move || {
+            let Some(row) = list_box.selected_row() else { return };
+            on_pick(row.index() as usize);
+            dialog.close();
+        }
+    };
+    let open_selected = Rc::new(open_selected);
+
+    // Enter in the search box opens the selected entry; Escape closes
     {
         let ctrl = gtk::EventControllerKey::new();
-        let list_box = list_box.clone();
         let dialog = dialog.clone();
+        let open_selected = open_selected.clone();
         ctrl.connect_key_pressed(move |_, key, _, _| match key {
-            gdk::Key::Down => {
-                if let Some(row) = list_box.selected_row() {
-                    row.grab_focus();
-                } else {
-                    select_first_visible(&list_box);
-                    if let Some(row) = list_box.selected_row() {
-                        row.grab_focus();
-                    }
-                }
+            gdk::Key::Return | gdk::Key::KP_Enter => {
+                open_selected();
                 gtk::glib::Propagation::Stop
             }
             gdk::Key::Escape => {
@@ -1854,275 +7426,926 @@ fn show_ssh_manager_dialog(window: &gtk::ApplicationWindow, notebook: &gtk::Note
         search_entry.add_controller(ctrl);
     }
 
-    // Listede ↑ + ilk satır → arama kutusuna dön
-    {
-        let ctrl = gtk::EventControllerKey::new();
-        ctrl.set_propagation_phase(gtk::PropagationPhase::Capture);
-        let search = search_entry.clone();
-        let list_box_mv = list_box.clone();
-        let list_box_ac = list_box.clone();
-        ctrl.connect_key_pressed(move |_, key, _, _| {
-            if key == gdk::Key::Up {
-                if let Some(row) = list_box_mv.selected_row() {
-                    if Some(row.index()) == first_visible_index(&list_box_mv) {
-                        search.grab_focus();
-                        return gtk::glib::Propagation::Stop;
-                    }
-                }
-            }
-            gtk::glib::Propagation::Proceed
-        });
-        list_box_ac.add_controller(ctrl);
-    }
-
     let btn_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
     btn_box.set_margin_top(4);
     btn_box.set_halign(gtk::Align::End);
 
-    let add_btn = gtk::Button::with_label("Add");
-    let delete_btn = gtk::Button::with_label("Delete");
-    let connect_btn = gtk::Button::with_label("Connect");
+    let open_btn = gtk::Button::with_label("Open");
     let close_btn = gtk::Button::with_label("Close");
-    btn_box.append(&add_btn);
-    btn_box.append(&delete_btn);
-    btn_box.append(&connect_btn);
+    btn_box.append(&open_btn);
     btn_box.append(&close_btn);
     content.append(&btn_box);
 
-    // Add button
+    // Open button
+    {
+        let open_selected = open_selected.clone();
+        open_btn.connect_clicked(move |_| open_selected());
+    }
+
+    // Close button
     {
         let dialog = dialog.clone();
-        let list_box = list_box.clone();
-        let servers = servers.clone();
-        add_btn.connect_clicked(move |_| {
-            show_add_server_dialog(&dialog, &list_box, &servers);
-        });
+        close_btn.connect_clicked(move |_| dialog.close());
     }
 
-    // Delete button
+    // Row activated: Enter or double-click → open
     {
-        let list_box = list_box.clone();
-        let servers = servers.clone();
-        delete_btn.connect_clicked(move |_| {
-            let Some(row) = list_box.selected_row() else { return };
-            let index = row.index() as usize;
-            {
-                let mut svs = servers.borrow_mut();
-                if index < svs.len() {
-                    svs.remove(index);
-                }
-                save_ssh_servers(&svs);
-            }
-            populate_server_list(&list_box, &servers.borrow());
-            list_box.invalidate_filter();
-            select_first_visible(&list_box);
+        let open_selected = open_selected.clone();
+        list_box.connect_row_activated(move |_, _| open_selected());
+    }
+
+    search_entry.grab_focus();
+    (dialog, list_box)
+}
+
+fn show_layout_picker_dialog(
+    window: &gtk::ApplicationWindow,
+    notebook: &gtk::Notebook,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+) {
+    let layouts = config.borrow().layouts.clone();
+    let names: Vec<String> = layouts.iter().map(|layout| layout.name.clone()).collect();
+    let notebook = notebook.clone();
+    let config = config.clone();
+    let counter = counter.clone();
+    show_name_picker_dialog(window, "Layouts", &names, move |index| {
+        if let Some(layout) = layouts.get(index) {
+            open_layout(&notebook, &config, &counter, layout);
+        }
+    });
+}
+
+fn show_profile_picker_dialog(
+    window: &gtk::ApplicationWindow,
+    notebook: &gtk::Notebook,
+    config: &Rc<RefCell<Config>>,
+    counter: &Rc<Cell<u32>>,
+) {
+    let names: Vec<String> = config.borrow().profiles.keys().cloned().collect();
+    let source = focused_terminal(window.upcast_ref());
+    let notebook = notebook.clone();
+    let config = config.clone();
+    let counter = counter.clone();
+    let picked = names.clone();
+    show_name_picker_dialog(window, "Profiles", &names, move |index| {
+        let Some(profile) = picked.get(index) else { return };
+        let options =
+            profile_terminal_options(&config.borrow(), source.as_ref(), Some(profile.clone()));
+        create_tab(&notebook, &config, &counter, &options);
+    });
+}
+
+// Switches the profile of one pane, or of every pane in its tab, in place:
+// the shells keep running and only the font, scrollback and colors change.
+fn show_switch_profile_dialog(
+    window: &gtk::ApplicationWindow,
+    terminal: &Terminal,
+    config: &Rc<RefCell<Config>>,
+    whole_tab: bool,
+) {
+    let mut names = vec!["(default)".to_string()];
+    names.extend(config.borrow().profiles.keys().cloned());
+    let title = if whole_tab {
+        "Tab Profile"
+    } else {
+        "Pane Profile"
+    };
+    let terminal = terminal.clone();
+    let config = config.clone();
+    let picked = names.clone();
+    show_name_picker_dialog(window, title, &names, move |index| {
+        let profile = picked.get(index).filter(|_| index > 0).map(String::as_str);
+        switch_profile(&terminal, &config.borrow(), profile, whole_tab);
+    });
+}
+
+fn switch_profile(terminal: &Terminal, config: &Config, profile: Option<&str>, whole_tab: bool) {
+    let Some(notebook) = find_parent_notebook(terminal.upcast_ref()) else { return };
+    let Some(page) = find_notebook_page(&notebook, terminal.upcast_ref()) else { return };
+    let mut terminals = Vec::new();
+    if whole_tab {
+        collect_terminals(&page, &mut terminals);
+    } else {
+        terminals.push(terminal.clone());
+    }
+    let theme = config.theme.as_ref().and_then(theme_from_source);
+    for terminal in &terminals {
+        set_terminal_profile(terminal, config, profile);
+        apply_config_to_terminal(terminal, config, theme.as_ref());
+    }
+    update_profile_marker(&page);
+}
+
+// One row of the command palette.
+struct PaletteEntry {
+    kind: &'static str,
+    title: String,
+    detail: String,
+    target: PaletteTarget,
+}
+
+enum PaletteTarget {
+    Action(Action),
+    Tab(gtk::Notebook, gtk::Widget),
+    SshServer(SshServer),
+    Password(Password),
+}
+
+fn command_palette_entries(context: &WindowContext) -> Vec<PaletteEntry> {
+    let config = context.config.borrow();
+    let mut entries = Vec::new();
+
+    let mut actions: Vec<Action> = Vec::new();
+    for (action, bindings) in &config.keybindings.entries {
+        if *action == Action::CommandPalette || actions.contains(action) {
+            continue;
+        }
+        let shortcuts: Vec<String> = bindings
+            .iter()
+            .map(|chord| format_key_chord(chord))
+            .collect();
+        actions.push(action.clone());
+        entries.push(PaletteEntry {
+            kind: "Action",
+            title: action.label(),
+            detail: shortcuts.join(" or "),
+            target: PaletteTarget::Action(action.clone()),
         });
     }
+    for name in config.profiles.keys() {
+        let action = Action::NewTabWithProfile(name.clone());
+        if !actions.contains(&action) {
+            entries.push(PaletteEntry {
+                kind: "Action",
+                title: action.label(),
+                detail: String::new(),
+                target: PaletteTarget::Action(action),
+            });
+        }
+    }
 
-    // Connect button
-    {
-        let dialog = dialog.clone();
-        let notebook = notebook.clone();
-        let servers = servers.clone();
-        let list_box = list_box.clone();
-        connect_btn.connect_clicked(move |_| {
-            ssh_connect_selected(&dialog, &notebook, &list_box, &servers);
+    let notebooks = app_notebooks(&context.app);
+    for (window_index, notebook) in notebooks.iter().enumerate() {
+        for index in 0..notebook.n_pages() {
+            let Some(page) = notebook.nth_page(Some(index)) else { continue };
+            let title = notebook
+                .tab_label(&page)
+                .and_then(|tab_widget| find_tab_label(&tab_widget))
+                .map(|label| label.text().to_string())
+                .unwrap_or_default();
+            let detail = if notebooks.len() > 1 {
+                format!("Window {}, tab {}", window_index + 1, index + 1)
+            } else {
+                format!("Tab {}", index + 1)
+            };
+            entries.push(PaletteEntry {
+                kind: "Tab",
+                title,
+                detail,
+                target: PaletteTarget::Tab(notebook.clone(), page),
+            });
+        }
+    }
+
+    for srv in load_ssh_servers() {
+        let detail = if srv.port == 22 {
+            format!("{}@{}", srv.user, srv.host)
+        } else {
+            format!("{}@{}:{}", srv.user, srv.host, srv.port)
+        };
+        entries.push(PaletteEntry {
+            kind: "SSH",
+            title: srv.name.clone(),
+            detail,
+            target: PaletteTarget::SshServer(srv),
         });
     }
 
-    // Close button
-    {
-        let dialog = dialog.clone();
-        close_btn.connect_clicked(move |_| dialog.close());
+    // Passwords are only listed by name and need the configured secret.
+    if !config.secret.is_empty() {
+        for password in load_passwords(&config.secret) {
+            entries.push(PaletteEntry {
+                kind: "Password",
+                title: password.name.clone(),
+                detail: String::new(),
+                target: PaletteTarget::Password(password),
+            });
+        }
+    }
+    entries
+}
+
+fn show_command_palette(context: &WindowContext) {
+    let entries = Rc::new(command_palette_entries(context));
+
+    let dialog = gtk::Dialog::new();
+    dialog.set_title(Some("Command Palette"));
+    dialog.set_modal(true);
+    dialog.set_transient_for(Some(&context.window));
+    dialog.set_default_size(560, 420);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_margin_top(12);
+    content.set_margin_bottom(12);
+    content.set_margin_start(12);
+    content.set_margin_end(12);
+
+    let search_entry = gtk::SearchEntry::new();
+    search_entry.set_placeholder_text(Some("Ara..."));
+    content.append(&search_entry);
+
+    let list_box = gtk::ListBox::new();
+    list_box.set_selection_mode(gtk::SelectionMode::Single);
+    list_box.set_vexpand(true);
+
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_child(Some(&list_box));
+    scrolled.set_vexpand(true);
+    scrolled.set_min_content_height(280);
+    content.append(&scrolled);
+
+    let mut rows = Vec::new();
+    for entry in entries.iter() {
+        let kind = gtk::Label::new(Some(entry.kind));
+        kind.add_css_class("dim-label");
+        kind.set_width_chars(8);
+        kind.set_xalign(0.0);
+        let title = gtk::Label::new(Some(&entry.title));
+        title.set_xalign(0.0);
+        title.set_hexpand(true);
+        title.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        let detail = gtk::Label::new(Some(&entry.detail));
+        detail.add_css_class("dim-label");
+        detail.set_xalign(1.0);
+        let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+        row_box.set_margin_top(6);
+        row_box.set_margin_bottom(6);
+        row_box.set_margin_start(8);
+        row_box.set_margin_end(8);
+        row_box.append(&kind);
+        row_box.append(&title);
+        row_box.append(&detail);
+        let row = gtk::ListBoxRow::new();
+        row.set_child(Some(&row_box));
+        list_box.append(&row);
+        rows.push(row);
+    }
+    let rows = Rc::new(rows);
+    if let Some(first) = list_box.row_at_index(0) {
+        list_box.select_row(Some(&first));
     }
 
-    // Row activated: Enter key or double-click
+    // Fuzzy match scores by entry; `None` hides the row. Better matches sort
+    // first and ties keep the original order.
+    let scores: Rc<RefCell<Vec<Option<i32>>>> = Rc::new(RefCell::new(vec![Some(0); rows.len()]));
+    let row_entry = {
+        let rows = rows.clone();
+        move |row: &gtk::ListBoxRow| rows.iter().position(|known| known == row)
+    };
     {
-        let dialog = dialog.clone();
-        let notebook = notebook.clone();
-        let servers = servers.clone();
-        list_box.connect_row_activated(move |lb, _| {
-            ssh_connect_selected(&dialog, &notebook, lb, &servers);
+        let scores = scores.clone();
+        let row_entry = row_entry.clone();
+        list_box.set_filter_func(move |row| {
+            row_entry(row).is_some_and(|index| scores.borrow()[index].is_some())
+        });
+    }
+    {
+        let scores = scores.clone();
+        let row_entry = row_entry.clone();
+        list_box.set_sort_func(move |a, b| {
+            let (Some(a), Some(b)) = (row_entry(a), row_entry(b)) else {
+                return gtk::Ordering::Equal;
+            };
+            let scores = scores.borrow();
+            scores[b].cmp(&scores[a]).then(a.cmp(&b)).into()
         });
     }
 
-    // Delete tuşu seçili sunucuyu siler
+    // Arama değiştiğinde puanla, filtrele ve ilk görünür satırı seç
     {
-        let key_ctrl = gtk::EventControllerKey::new();
-        let servers = servers.clone();
-        let list_box = list_box.clone();
-        key_ctrl.connect_key_pressed(move |_, key, _, _| {
-            if key == gdk::Key::Delete {
-                let Some(row) = list_box.selected_row() else {
-                    return gtk::glib::Propagation::Proceed;
-                };
-                let index = row.index() as usize;
-                {
-                    let mut svs = servers.borrow_mut();
-                    if index < svs.len() {
-                        svs.remove(index);
-                    }
-                    save_ssh_servers(&svs);
-                }
-                populate_server_list(&list_box, &servers.borrow());
-                list_box.invalidate_filter();
-                select_first_visible(&list_box);
-                return gtk::glib::Propagation::Stop;
+        let list_box = list_box.clone();
+        let entries = entries.clone();
+        search_entry.connect_search_changed(move |search| {
+            let query = search.text();
+            *scores.borrow_mut() = entries
+                .iter()
+                .map(|entry| palette_score(&query, entry))
+                .collect();
+            list_box.invalidate_filter();
+            list_box.invalidate_sort();
+            select_first_visible(&list_box);
+        });
+    }
+
+    let activate_selected = {
+        let dialog = dialog.clone();
+        let list_box = list_box.clone();
+        let context = context.clone();
+        move || {
+            let Some(index) = list_box.selected_row().and_then(|row| row_entry(&row)) else {
+                return;
+            };
+            // Close first, so actions that open a dialog or act on the
+            // focused pane see the terminal window again.
+            dialog.close();
+            activate_palette_entry(&context, &entries[index].target);
+        }
+    };
+    let activate_selected = Rc::new(activate_selected);
+
+    // Enter runs the selected entry, Up/Down move the selection while
+    // typing and Escape closes.
+    {
+        let ctrl = gtk::EventControllerKey::new();
+        let dialog = dialog.clone();
+        let list_box = list_box.clone();
+        let activate_selected = activate_selected.clone();
+        ctrl.connect_key_pressed(move |_, key, _, _| match key {
+            gdk::Key::Return | gdk::Key::KP_Enter => {
+                activate_selected();
+                gtk::glib::Propagation::Stop
             }
-            gtk::glib::Propagation::Proceed
+            gdk::Key::Down | gdk::Key::Up => {
+                move_visible_selection(&list_box, key == gdk::Key::Down);
+                gtk::glib::Propagation::Stop
+            }
+            gdk::Key::Escape => {
+                dialog.close();
+                gtk::glib::Propagation::Stop
+            }
+            _ => gtk::glib::Propagation::Proceed,
         });
-        dialog.add_controller(key_ctrl);
+        search_entry.add_controller(ctrl);
     }
 
+    // Row activated: Enter or double-click → run
+    list_box.connect_row_activated(move |_, _| activate_selected());
+
     search_entry.grab_focus();
     dialog.present();
 }
 
-fn close_tab_or_window(window: &gtk::Window, notebook: &gtk::Notebook) {
-    if let Some(page) = notebook.current_page() {
-        if notebook.n_pages() <= 1 {
-            window.close();
+fn activate_palette_entry(context: &WindowContext, target: &PaletteTarget) {
+    let focused = || {
+        focused_terminal(context.window.upcast_ref())
+            .or_else(|| find_first_terminal_in_notebook(&context.notebook))
+    };
+    match target {
+        PaletteTarget::Action(action) => {
+            run_action(context, action);
+        }
+        PaletteTarget::Tab(notebook, page) => {
+            let Some(index) = notebook.page_num(page) else { return };
+            notebook.set_current_page(Some(index));
+            focus_terminal_in_page(notebook, index);
+            if let Some(window) = find_root_window(notebook.upcast_ref()) {
+                window.present();
+            }
+        }
+        PaletteTarget::SshServer(srv) => {
+            if let Some(terminal) = focused() {
+                terminal.feed_child(ssh_command(srv).as_bytes());
+            }
+        }
+        PaletteTarget::Password(password) => {
+            if let Some(terminal) = focused() {
+                terminal.feed_child(format!("{}\n", password.password).as_bytes());
+            }
+        }
+    }
+}
+
+// Every character of the query has to appear in the text in order.
+// Consecutive characters and word starts score higher, the very start of the
+// text a little more, skipped text lower. The best alignment wins, so "new"
+// finds the word in "Action New tab" rather than the first `n`.
+fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let query: Vec<char> = query
+        .to_lowercase()
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+    let bonus = |index: usize| {
+        if index == 0 {
+            5
+        } else if !text[index - 1].is_alphanumeric() {
+            4
         } else {
-            notebook.remove_page(Some(page));
-            focus_previous_tab(notebook, page);
+            1
         }
+    };
+    // best[j]: the best score for the query so far with its last character
+    // matched at text[j].
+    let mut best: Vec<Option<i32>> = text
+        .iter()
+        .enumerate()
+        .map(|(index, ch)| (*ch == query[0]).then(|| bonus(index)))
+        .collect();
+    for ch in &query[1..] {
+        let mut next = vec![None; text.len()];
+        for (index, known) in text.iter().enumerate() {
+            if known != ch {
+                continue;
+            }
+            next[index] = (0..index)
+                .filter_map(|previous| {
+                    let score = best[previous]?;
+                    let skipped = index - previous - 1;
+                    let run = if skipped == 0 { 5 } else { 0 };
+                    Some(score + run - skipped.min(5) as i32)
+                })
+                .max()
+                .map(|score| score + bonus(index));
+        }
+        best = next;
     }
+    best.into_iter().flatten().max()
 }
 
-fn default_keybindings() -> KeyBindings {
-    KeyBindings {
-        new_tab: parse_keybinding("Ctrl+Shift+T").unwrap(),
-        close_tab: parse_keybinding("Ctrl+Shift+W").unwrap(),
-        rename_tab: parse_keybinding("Ctrl+Shift+R").unwrap(),
-        close_panel: parse_keybinding("Ctrl+D").unwrap(),
-        split_vertical: parse_keybinding("Ctrl+Shift+P").unwrap(),
-        split_horizontal: parse_keybinding("Ctrl+Shift+H").unwrap(),
-        copy: parse_keybinding("Ctrl+Shift+C").unwrap(),
-        paste: parse_keybinding("Ctrl+Shift+V").unwrap(),
-        reload_config: parse_keybinding("Ctrl+Shift+L").unwrap(),
-        show_keybindings: parse_keybinding("Ctrl+Shift+K").unwrap(),
-        focus_left: parse_keybinding("Alt+Left").unwrap(),
-        focus_right: parse_keybinding("Alt+Right").unwrap(),
-        focus_up: parse_keybinding("Alt+Up").unwrap(),
-        focus_down: parse_keybinding("Alt+Down").unwrap(),
-        ssh_manager: parse_keybinding("Ctrl+Shift+S").unwrap(),
-        password_manager: parse_keybinding("Ctrl+Shift+A").unwrap(),
-        tab_switch: (1..=9)
-            .map(|n| parse_keybinding(&format!("Alt+{n}")).unwrap())
-            .collect(),
+// The palette ranks by title; the detail (shortcut, host, tab number) only
+// counts at half weight and the kind column is not searched.
+fn palette_score(query: &str, entry: &PaletteEntry) -> Option<i32> {
+    let title = fuzzy_score(query, &entry.title);
+    let detail = fuzzy_score(query, &entry.detail).map(|score| score / 2 - 1);
+    title.max(detail)
+}
+
+fn move_visible_selection(list_box: &gtk::ListBox, forward: bool) {
+    let Some(current) = list_box.selected_row() else {
+        select_first_visible(list_box);
+        return;
+    };
+    let mut index = current.index();
+    loop {
+        index += if forward { 1 } else { -1 };
+        if index < 0 {
+            return;
+        }
+        let Some(row) = list_box.row_at_index(index) else { return };
+        if row.is_child_visible() {
+            list_box.select_row(Some(&row));
+            scroll_to_row(list_box, &row);
+            return;
+        }
     }
 }
 
-fn apply_keybindings(bindings: &mut KeyBindings, raw: RawKeyBindings) {
-    if let Some(value) = raw.new_tab.and_then(|s| parse_keybinding(&s)) {
-        bindings.new_tab = value;
+// Keeps a selected row in view without moving the focus to it, so typing
+// still goes to the search entry.
+fn scroll_to_row(list_box: &gtk::ListBox, row: &gtk::ListBoxRow) {
+    let scrolled = list_box
+        .ancestor(gtk::ScrolledWindow::static_type())
+        .and_downcast::<gtk::ScrolledWindow>();
+    let Some(scrolled) = scrolled else { return };
+    let Some(bounds) = row.compute_bounds(list_box) else { return };
+    let top = f64::from(bounds.y());
+    scrolled
+        .vadjustment()
+        .clamp_page(top, top + f64::from(bounds.height()));
+}
+
+// The widgets of the preferences dialog. Every change is previewed on all
+// windows; only saving writes config.toml.
+struct PreferencesForm {
+    original: Config,
+    font: gtk::FontButton,
+    // The font as the button reports it before any change. `original.font`
+    // does not survive the round trip through pango unchanged.
+    original_font: Option<gtk::pango::FontDescription>,
+    scrollback: gtk::SpinButton,
+    shell: gtk::ComboBoxText,
+    tab_title: gtk::Entry,
+    tab_position: gtk::DropDown,
+    theme: gtk::DropDown,
+    themes: Vec<Option<ThemeSource>>,
+}
+
+const TAB_POSITIONS: [(&str, gtk::PositionType); 2] = [
+    ("top", gtk::PositionType::Top),
+    ("bottom", gtk::PositionType::Bottom),
+];
+
+fn show_preferences_dialog(context: &WindowContext) {
+    let original = context.config.borrow().clone();
+    let dialog = gtk::Dialog::new();
+    dialog.set_title(Some("Preferences"));
+    dialog.set_modal(true);
+    dialog.set_transient_for(Some(&context.window));
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Save", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_margin_top(12);
+    content.set_margin_bottom(12);
+    content.set_margin_start(12);
+    content.set_margin_end(12);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(8);
+    grid.set_column_spacing(12);
+    content.append(&grid);
+
+    let mut font_desc = gtk::pango::FontDescription::from_string(&original.font);
+    if original.font_size > 0 {
+        font_desc.set_size(original.font_size * gtk::pango::SCALE);
     }
-    if let Some(value) = raw.close_tab.and_then(|s| parse_keybinding(&s)) {
-        bindings.close_tab = value;
+    let font = gtk::FontButton::new();
+    font.set_font_desc(&font_desc);
+    font.set_hexpand(true);
+
+    let scrollback = gtk::SpinButton::with_range(0.0, 1_000_000.0, 1000.0);
+    scrollback.set_value(original.scrollback_lines.into());
+
+    // Shells from /etc/shells, but any command can be typed in.
+    let shell = gtk::ComboBoxText::with_entry();
+    let mut shells: Vec<String> = fs::read_to_string("/etc/shells")
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if !shells.contains(&original.shell) {
+        shells.insert(0, original.shell.clone());
     }
-    if let Some(value) = raw.rename_tab.and_then(|s| parse_keybinding(&s)) {
-        bindings.rename_tab = value;
+    for known in &shells {
+        shell.append_text(known);
     }
-    if let Some(value) = raw.close_panel.and_then(|s| parse_keybinding(&s)) {
-        bindings.close_panel = value;
+    if let Some(entry) = shell.child().and_downcast::<gtk::Entry>() {
+        entry.set_text(&original.shell);
     }
-    if let Some(value) = raw.split_vertical.and_then(|s| parse_keybinding(&s)) {
-        bindings.split_vertical = value;
+
+    let tab_title = gtk::Entry::new();
+    tab_title.set_text(&original.tab_title);
+
+    let tab_position = gtk::DropDown::from_strings(&["Top", "Bottom"]);
+    let position = TAB_POSITIONS
+        .iter()
+        .position(|(_, position)| *position == original.tab_bar_position)
+        .unwrap_or(0);
+    tab_position.set_selected(position as u32);
+
+    let mut themes: Vec<Option<ThemeSource>> = vec![None];
+    themes.extend(available_themes().into_iter().map(Some));
+    if original.theme.is_some() && !themes.contains(&original.theme) {
+        themes.push(original.theme.clone());
     }
-    if let Some(value) = raw.split_horizontal.and_then(|s| parse_keybinding(&s)) {
-        bindings.split_horizontal = value;
+    let theme_names: Vec<String> = themes
+        .iter()
+        .map(|source| match source {
+            Some(source) => source.name(),
+            None => "(none)".to_string(),
+        })
+        .collect();
+    let theme_refs: Vec<&str> = theme_names.iter().map(String::as_str).collect();
+    let theme = gtk::DropDown::from_strings(&theme_refs);
+    let selected = themes
+        .iter()
+        .position(|source| *source == original.theme)
+        .unwrap_or(0);
+    theme.set_selected(selected as u32);
+
+    // Running shells and existing tab titles are left alone, so these two
+    // are not previewed.
+    let rows: [(&str, gtk::Widget); 6] = [
+        ("Font", font.clone().upcast()),
+        ("Scrollback lines", scrollback.clone().upcast()),
+        ("Shell (new tabs)", shell.clone().upcast()),
+        ("Tab title (new tabs)", tab_title.clone().upcast()),
+        ("Tab bar position", tab_position.clone().upcast()),
+        ("Theme", theme.clone().upcast()),
+    ];
+    for (row, (title, widget)) in rows.iter().enumerate() {
+        let label = gtk::Label::new(Some(title));
+        label.set_xalign(0.0);
+        grid.attach(&label, 0, row as i32, 1, 1);
+        grid.attach(widget, 1, row as i32, 1, 1);
     }
-    if let Some(value) = raw.copy.and_then(|s| parse_keybinding(&s)) {
-        bindings.copy = value;
+
+    let error = gtk::Label::new(None);
+    error.set_wrap(true);
+    error.set_xalign(0.0);
+    error.add_css_class("error");
+    error.set_visible(false);
+    content.append(&error);
+
+    let original_font = font.font_desc();
+    let form = Rc::new(PreferencesForm {
+        original,
+        font,
+        original_font,
+        scrollback,
+        shell,
+        tab_title,
+        tab_position,
+        theme,
+        themes,
+    });
+
+    let preview = {
+        let form = Rc::downgrade(&form);
+        let app = context.app.clone();
+        move || {
+            if let Some(form) = form.upgrade() {
+                preview_config(&app, &form.config());
+            }
+        }
+    };
+    let preview = Rc::new(preview);
+    {
+        let preview = preview.clone();
+        form.font.connect_font_set(move |_| preview());
     }
-    if let Some(value) = raw.paste.and_then(|s| parse_keybinding(&s)) {
-        bindings.paste = value;
+    {
+        let preview = preview.clone();
+        form.scrollback.connect_value_changed(move |_| preview());
     }
-    if let Some(value) = raw.reload_config.and_then(|s| parse_keybinding(&s)) {
-        bindings.reload_config = value;
+    {
+        let preview = preview.clone();
+        form.tab_position
+            .connect_selected_notify(move |_| preview());
     }
-    if let Some(value) = raw.show_keybindings.and_then(|s| parse_keybinding(&s)) {
-        bindings.show_keybindings = value;
+    form.theme.connect_selected_notify(move |_| preview());
+
+    let context = context.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            match form.save() {
+                Ok(()) => {
+                    dialog.close();
+                    run_action(&context, &Action::ReloadConfig);
+                }
+                Err(err) => {
+                    error.set_text(&format!("Could not save the preferences: {err}"));
+                    error.set_visible(true);
+                }
+            }
+            return;
+        }
+        // Cancelled: undo the preview.
+        preview_config(&context.app, &context.config.borrow());
+        dialog.close();
+    });
+    dialog.present();
+}
+
+impl PreferencesForm {
+    // The config as it would be after saving.
+    fn config(&self) -> Config {
+        let mut config = self.original.clone();
+        if let Some(mut desc) = self
+            .font
+            .font_desc()
+            .filter(|desc| Some(desc) != self.original_font.as_ref())
+        {
+            if desc.size() > 0 {
+                config.font_size = desc.size() / gtk::pango::SCALE;
+            }
+            desc.unset_fields(gtk::pango::FontMask::SIZE);
+            config.font = desc.to_str().to_string();
+        }
+        config.scrollback_lines = self.scrollback.value_as_int();
+        if let Some(shell) = self
+            .shell
+            .active_text()
+            .filter(|shell| !shell.trim().is_empty())
+        {
+            config.shell = shell.trim().to_string();
+        }
+        let tab_title = self.tab_title.text();
+        if !tab_title.trim().is_empty() {
+            config.tab_title = tab_title.trim().to_string();
+        }
+        if let Some((_, position)) = TAB_POSITIONS.get(self.tab_position.selected() as usize) {
+            config.tab_bar_position = *position;
+        }
+        if let Some(theme) = self.themes.get(self.theme.selected() as usize) {
+            config.theme = theme.clone();
+        }
+        config
     }
-    if let Some(value) = raw.focus_left.and_then(|s| parse_keybinding(&s)) {
-        bindings.focus_left = value;
+
+    // Writes the settings that were changed and leaves the rest of
+    // config.toml alone.
+    fn save(&self) -> Result<(), String> {
+        let original = &self.original;
+        let config = self.config();
+        update_config_file(|document| {
+            if config.font != original.font || config.font_size != original.font_size {
+                document["font"] = toml_edit::value(config.font.as_str());
+                document["font_size"] = toml_edit::value(i64::from(config.font_size));
+            }
+            if config.scrollback_lines != original.scrollback_lines {
+                document["scrollback_lines"] = toml_edit::value(i64::from(config.scrollback_lines));
+            }
+            if config.shell != original.shell {
+                document["shell"] = toml_edit::value(config.shell.as_str());
+            }
+            if config.tab_title != original.tab_title {
+                document["tab_title"] = toml_edit::value(config.tab_title.as_str());
+            }
+            if config.tab_bar_position != original.tab_bar_position {
+                let name = TAB_POSITIONS
+                    .iter()
+                    .find(|(_, position)| *position == config.tab_bar_position)
+                    .map_or("top", |(name, _)| name);
+                document["tab_bar_position"] = toml_edit::value(name);
+            }
+            if config.theme != original.theme {
+                set_theme_setting(document, config.theme.as_ref());
+            }
+            Ok(())
+        })
     }
-    if let Some(value) = raw.focus_right.and_then(|s| parse_keybinding(&s)) {
-        bindings.focus_right = value;
+}
+
+// Bundled themes and themes in a user theme directory are written as
+// `theme = "name"`, other files as `theme_file`.
+fn set_theme_setting(document: &mut toml_edit::DocumentMut, theme: Option<&ThemeSource>) {
+    document.remove("theme");
+    document.remove("theme_file");
+    let Some(theme) = theme else { return };
+    let by_name = resolve_theme_name(&theme.name()).is_ok_and(|resolved| resolved == *theme);
+    match theme {
+        _ if by_name => document["theme"] = toml_edit::value(theme.name()),
+        ThemeSource::File(path) => {
+            // Files in the config directory are written relative to it.
+            let config_dir = config_path().and_then(|path| path.parent().map(Path::to_path_buf));
+            let relative = config_dir
+                .as_deref()
+                .and_then(|dir| path.strip_prefix(dir).ok())
+                .unwrap_or(path);
+            document["theme_file"] = toml_edit::value(relative.to_string_lossy().as_ref());
+        }
+        ThemeSource::Bundled(name) => document["theme"] = toml_edit::value(*name),
     }
-    if let Some(value) = raw.focus_up.and_then(|s| parse_keybinding(&s)) {
-        bindings.focus_up = value;
+}
+
+fn preview_config(app: &gtk::Application, config: &Config) {
+    let theme = config.theme.as_ref().and_then(theme_from_source);
+    for notebook in app_notebooks(app) {
+        apply_config_to_window(&notebook, config, theme.as_ref());
     }
-    if let Some(value) = raw.focus_down.and_then(|s| parse_keybinding(&s)) {
-        bindings.focus_down = value;
+}
+
+// `themes` next to config.toml and in the data directory
+// (`~/.local/share/termilyon/themes`).
+fn theme_directories() -> Vec<PathBuf> {
+    let mut directories = Vec::new();
+    if let Some(path) = config_path() {
+        directories.push(path.with_file_name("themes"));
     }
-    if let Some(value) = raw.ssh_manager.and_then(|s| parse_keybinding(&s)) {
-        bindings.ssh_manager = value;
+    if let Some(dirs) = ProjectDirs::from("io", "termilyon", "termilyon") {
+        directories.push(dirs.data_dir().join("themes"));
     }
-    if let Some(value) = raw.password_manager.and_then(|s| parse_keybinding(&s)) {
-        bindings.password_manager = value;
+    directories
+}
+
+// Theme files in the user theme directories, sorted by name within each.
+fn find_theme_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for directory in theme_directories() {
+        let Ok(entries) = fs::read_dir(&directory) else { continue };
+        let mut found: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "toml")
+            })
+            .collect();
+        found.sort();
+        files.extend(found);
     }
+    files
+}
 
-    let tabs = [
-        raw.tab_1, raw.tab_2, raw.tab_3, raw.tab_4, raw.tab_5, raw.tab_6, raw.tab_7, raw.tab_8,
-        raw.tab_9,
-    ];
-    for (index, entry) in tabs.into_iter().enumerate() {
-        if let Some(value) = entry.and_then(|s| parse_keybinding(&s)) {
-            if index < bindings.tab_switch.len() {
-                bindings.tab_switch[index] = value;
-            }
+// Every theme `theme = "name"` can select: user theme files first, then the
+// bundled themes they do not replace.
+fn available_themes() -> Vec<ThemeSource> {
+    let mut themes: Vec<ThemeSource> = Vec::new();
+    for path in find_theme_files() {
+        let source = ThemeSource::File(path);
+        if !themes.iter().any(|known| known.name() == source.name()) {
+            themes.push(source);
         }
     }
+    for (name, _) in BUNDLED_THEMES {
+        if !themes.iter().any(|known| known.name() == *name) {
+            themes.push(ThemeSource::Bundled(name));
+        }
+    }
+    themes
 }
 
-fn parse_keybinding(text: &str) -> Option<KeyBinding> {
-    let mut modifiers = gdk::ModifierType::empty();
-    let mut key: Option<gdk::Key> = None;
-
-    for part in text.split('+') {
-        let token = part.trim();
-        if token.is_empty() {
-            continue;
-        }
+// A file in a user theme directory wins over a bundled theme of the same
+// name, so bundled themes can be customised.
+fn resolve_theme_name(name: &str) -> Result<ThemeSource, String> {
+    let name = name.trim();
+    let themes = available_themes();
+    if let Some(theme) = themes.iter().find(|theme| theme.name() == name) {
+        return Ok(theme.clone());
+    }
+    let names: Vec<String> = themes.iter().map(ThemeSource::name).collect();
+    let hint = closest_name(name, names.iter().map(String::as_str))
+        .map(|name| format!("; did you mean `{name}`?"))
+        .unwrap_or_default();
+    Err(format!("unknown theme `{name}`{hint}"))
+}
 
-        match token.to_ascii_lowercase().as_str() {
-            "ctrl" | "control" => modifiers |= gdk::ModifierType::CONTROL_MASK,
-            "shift" => modifiers |= gdk::ModifierType::SHIFT_MASK,
-            "alt" | "option" => modifiers |= gdk::ModifierType::ALT_MASK,
-            "super" | "meta" | "win" => modifiers |= gdk::ModifierType::SUPER_MASK,
-            _ => {
-                let try_name = gdk::Key::from_name(token)
-                    .or_else(|| gdk::Key::from_name(&token.to_ascii_uppercase()));
-                key = try_name.or(key);
+// Lists every available theme and previews the selected one on the focused
+// pane. Choosing a theme makes it the global theme in config.toml; closing
+// the gallery otherwise puts the pane's colors back.
+fn show_theme_gallery(context: &WindowContext) {
+    let Some(terminal) = focused_terminal(context.window.upcast_ref())
+        .or_else(|| find_first_terminal_in_notebook(&context.notebook))
+    else {
+        return;
+    };
+    let themes = available_themes();
+    let names: Vec<String> = themes.iter().map(ThemeSource::name).collect();
+    let committed = Rc::new(Cell::new(false));
+
+    let on_pick = {
+        let themes = themes.clone();
+        let committed = committed.clone();
+        let context = context.clone();
+        move |index: usize| {
+            let Some(theme) = themes.get(index) else { return };
+            let saved = update_config_file(|document| {
+                set_theme_setting(document, Some(theme));
+                Ok(())
+            });
+            match saved {
+                Ok(()) => {
+                    committed.set(true);
+                    run_action(&context, &Action::ReloadConfig);
+                }
+                Err(err) => show_config_problems(
+                    context.window.upcast_ref(),
+                    "Could not save the theme:",
+                    &[ConfigProblem::error(err)],
+                ),
             }
         }
+    };
+    let (dialog, list_box) = build_name_picker_dialog(&context.window, "Themes", &names, on_pick);
+    let current = context.config.borrow().theme.clone();
+    if let Some(row) = themes
+        .iter()
+        .position(|theme| Some(theme) == current.as_ref())
+        .and_then(|index| list_box.row_at_index(index as i32))
+    {
+        list_box.select_row(Some(&row));
     }
 
-    key.map(|key| KeyBinding { key, modifiers })
-}
-
-impl KeyBinding {
-    fn matches(&self, key: gdk::Key, state: gdk::ModifierType) -> bool {
-        let relevant = gdk::ModifierType::CONTROL_MASK
-            | gdk::ModifierType::SHIFT_MASK
-            | gdk::ModifierType::ALT_MASK
-            | gdk::ModifierType::SUPER_MASK;
-        let key_matches = key == self.key
-            || key.to_lower() == self.key.to_lower()
-            || key.to_upper() == self.key.to_upper();
-        key_matches && state & relevant == self.modifiers
+    {
+        let terminal = terminal.clone();
+        list_box.connect_row_selected(move |_, row| {
+            let Some(theme) = row.and_then(|row| themes.get(row.index() as usize)) else { return };
+            if let Some(theme) = theme_from_source(theme) {
+                apply_theme(&terminal, &theme);
+            }
+        });
     }
+    {
+        let config = context.config.clone();
+        dialog.connect_close_request(move |_| {
+            if !committed.get() {
+                let config = config.borrow();
+                let theme = config.theme.as_ref().and_then(theme_from_source);
+                apply_config_to_terminal(&terminal, &config, theme.as_ref());
+            }
+            gtk::glib::Propagation::Proceed
+        });
+    }
+    dialog.present();
 }
 
-fn parse_tab_bar_position(value: &str) -> Option<gtk::PositionType> {
+// Split directions follow the keybinding names: a "vertical" split places
+// panes side by side, a "horizontal" split stacks them.
+fn parse_split_direction(value: &str) -> Option<gtk::Orientation> {
     match value.trim().to_ascii_lowercase().as_str() {
-        "top" => Some(gtk::PositionType::Top),
-        "bottom" => Some(gtk::PositionType::Bottom),
+        "vertical" => Some(gtk::Orientation::Horizontal),
+        "horizontal" => Some(gtk::Orientation::Vertical),
         _ => None,
     }
 }
 
+fn split_direction_name(orientation: gtk::Orientation) -> &'static str {
+    match orientation {
+        gtk::Orientation::Horizontal => "vertical",
+        _ => "horizontal",
+    }
+}
+
 fn focus_previous_tab(notebook: &gtk::Notebook, closed_index: u32) {
     let pages = notebook.n_pages();
     if pages == 0 {
@@ -2143,3 +8366,345 @@ fn focus_terminal_in_page(notebook: &gtk::Notebook, page: u32) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_text_handles_escapes() {
+        assert_eq!(unescape_text(r"a\e[A\n\r\t\\"), b"a\x1b[A\n\r\t\\");
+        assert_eq!(unescape_text("é"), "é".as_bytes());
+    }
+
+    #[test]
+    fn unescape_text_reads_hex_as_single_bytes() {
+        assert_eq!(unescape_text(r"\xff\x1b"), [0xff, 0x1b]);
+        assert_eq!(unescape_text(r"\xc3\xa9"), "é".as_bytes());
+    }
+
+    #[test]
+    fn unescape_text_keeps_unknown_escapes() {
+        assert_eq!(unescape_text(r"\xzz"), br"\xzz");
+        assert_eq!(unescape_text(r"\q"), b"q");
+        assert_eq!(unescape_text("end\\"), b"end\\");
+    }
+
+    #[test]
+    fn escape_text_round_trips() {
+        assert_eq!(escape_text(b"\x1b[A\x01\xff"), r"\e[A\x01\xff");
+        let texts: [&[u8]; 5] = [
+            b"ls -l\n",
+            b"\x1b[A",
+            b"\xff\xfe",
+            b"tab\there\\",
+            "caf\u{e9} \u{7f}".as_bytes(),
+        ];
+        for text in texts {
+            assert_eq!(unescape_text(&escape_text(text)), text);
+        }
+    }
+
+    fn binding(name: &str, modifiers: gdk::ModifierType) -> KeyBinding {
+        let key = gdk::Key::from_name(name).expect("known key name");
+        KeyBinding { key, modifiers }
+    }
+
+    #[test]
+    fn parse_key_chord_reads_modifiers() {
+        let modifiers = gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::SHIFT_MASK;
+        assert_eq!(
+            parse_key_chord("Ctrl+Shift+T"),
+            Ok(vec![binding("T", modifiers)])
+        );
+        assert_eq!(
+            parse_key_chord(" control + shift + t "),
+            Ok(vec![binding("t", modifiers)])
+        );
+    }
+
+    #[test]
+    fn parse_key_chord_splits_on_commas() {
+        let ctrl = gdk::ModifierType::CONTROL_MASK;
+        let none = gdk::ModifierType::empty();
+        assert_eq!(
+            parse_key_chord("Ctrl+A, c"),
+            Ok(vec![binding("A", ctrl), binding("c", none)])
+        );
+        assert_eq!(
+            parse_key_chord("Ctrl+A,Ctrl+B,x"),
+            Ok(vec![
+                binding("A", ctrl),
+                binding("B", ctrl),
+                binding("x", none)
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_key_chord_accepts_the_comma_key() {
+        let ctrl = gdk::ModifierType::CONTROL_MASK;
+        let none = gdk::ModifierType::empty();
+        assert_eq!(parse_key_chord("Ctrl+,"), Ok(vec![binding("comma", ctrl)]));
+        assert_eq!(parse_key_chord(","), Ok(vec![binding("comma", none)]));
+        assert_eq!(
+            parse_key_chord("Ctrl+A, ,"),
+            Ok(vec![binding("A", ctrl), binding("comma", none)])
+        );
+        assert_eq!(parse_key_chord("Ctrl+."), Ok(vec![binding("period", ctrl)]));
+    }
+
+    #[test]
+    fn parse_key_chord_rejects_bad_keys() {
+        assert!(parse_key_chord("Ctrl+Nope").is_err());
+        assert!(parse_key_chord("Ctrl+").is_err());
+        assert!(parse_key_chord("A+B").is_err());
+        assert!(parse_key_chord("Ctrl+A,").is_err());
+    }
+
+    #[test]
+    fn parse_config_file_locates_problems_in_each_file() {
+        let dir = env::temp_dir().join(format!("termilyon-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("temp dir");
+        let main = dir.join("main.toml");
+        let extra = dir.join("extra.toml");
+        let contents = "\
+include = [\"extra.toml\"]
+font_sise = 12
+
+[keybindings]
+copy = \"Ctrl+Shift+X\"
+new_tab = \"Ctrl+Shfit+T\"
+paste = \"Ctrl+Shift+X\"
+";
+        fs::write(&main, contents).expect("write main.toml");
+        fs::write(&extra, "scrollback_lines = \"many\"\n").expect("write extra.toml");
+
+        let mut problems = Vec::new();
+        let mut files = Vec::new();
+        let mut locations = ConfigLocations::new();
+        let table = parse_config_file(&main, &mut problems, &mut files, &mut locations);
+        let raw: RawKeyBindings = table["keybindings"]
+            .clone()
+            .try_into()
+            .expect("keybindings");
+        problems.extend(apply_keybindings(
+            &mut default_keybindings(),
+            raw,
+            &locations,
+        ));
+        fs::remove_dir_all(&dir).ok();
+
+        let placed: Vec<(Option<&Path>, Option<(usize, usize)>)> = problems
+            .iter()
+            .map(|problem| (problem.file.as_deref(), problem.position))
+            .collect();
+        assert_eq!(
+            placed,
+            vec![
+                (Some(main.as_path()), Some((2, 13))),
+                (Some(extra.as_path()), Some((1, 20))),
+                (Some(main.as_path()), Some((6, 11))),
+                (Some(main.as_path()), Some((7, 9))),
+            ]
+        );
+        assert_eq!(
+            format_config_problem(&problems[0]),
+            format!(
+                "{}:2:13: error: unknown key `font_sise`; did you mean `font_size`?",
+                main.display()
+            )
+        );
+    }
+
+    #[test]
+    fn parse_config_file_merges_shared_includes_and_stops_cycles() {
+        let dir = env::temp_dir().join(format!("termilyon-include-test-{}", std::process::id()));
+        let conf = dir.join("conf");
+        fs::create_dir_all(&conf).expect("temp dir");
+        let main = conf.join("config.toml");
+        fs::write(
+            &main,
+            "include = [\"a.toml\", \"b.toml\", \"../conf/config.toml\"]\n",
+        )
+        .expect("write config.toml");
+        fs::write(conf.join("a.toml"), "include = [\"colors.toml\"]\n").expect("write a.toml");
+        fs::write(conf.join("b.toml"), "include = [\"colors.toml\"]\n").expect("write b.toml");
+        fs::write(conf.join("colors.toml"), "font_size = 13\n").expect("write colors.toml");
+
+        let mut problems = Vec::new();
+        let mut files = Vec::new();
+        let mut locations = ConfigLocations::new();
+        let table = parse_config_file(&main, &mut problems, &mut files, &mut locations);
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(table["font_size"].as_integer(), Some(13));
+        let names: Vec<String> = files
+            .iter()
+            .filter_map(|file| file.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["config.toml", "a.toml", "colors.toml", "b.toml"]);
+        let messages: Vec<&str> = problems
+            .iter()
+            .map(|problem| problem.message.as_str())
+            .collect();
+        assert_eq!(messages, ["`../conf/config.toml` includes itself"]);
+    }
+
+    #[test]
+    fn fuzzy_score_needs_every_character_in_order() {
+        assert!(fuzzy_score("nt", "New tab").is_some());
+        assert!(fuzzy_score("NEW TAB", "new tab").is_some());
+        assert_eq!(fuzzy_score("", "New tab"), Some(0));
+        assert_eq!(fuzzy_score("bn", "New tab"), None);
+        assert_eq!(fuzzy_score("xyz", "New tab"), None);
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_word_starts_and_runs() {
+        let score = |text| fuzzy_score("new", text).unwrap_or(i32::MIN);
+        assert!(score("New tab") > score("Rename window"));
+        assert!(score("New window") > score("Send newline"));
+        assert_eq!(fuzzy_score("new", "Action New tab"), Some(16));
+        assert_eq!(fuzzy_score("tab", "Action Switch tab 1"), Some(16));
+    }
+
+    #[test]
+    fn palette_score_ranks_titles_over_details() {
+        let entry = |title: &str, detail: &str| PaletteEntry {
+            kind: "Action",
+            title: title.to_string(),
+            detail: detail.to_string(),
+            target: PaletteTarget::Action(Action::NewTab),
+        };
+        let score = |query, entry: &PaletteEntry| palette_score(query, entry).unwrap_or(i32::MIN);
+        let new_tab = entry("New tab", "Ctrl+Shift+T");
+        let switch_tab = entry("Switch tab 1", "Alt+1");
+        let rename = entry("Rename tab", "");
+        assert!(score("new", &new_tab) > score("new", &rename));
+        assert!(score("tab", &switch_tab) > score("tab", &entry("Start a buffer", "")));
+        // The kind column is not part of what is searched.
+        assert_eq!(palette_score("action", &new_tab), None);
+        assert!(score("ctrl", &new_tab) < score("new", &new_tab));
+        assert_eq!(palette_score("", &rename), Some(0));
+    }
+
+    #[test]
+    fn normalize_color_accepts_hex_forms() {
+        assert_eq!(normalize_color("#ABCDEF").as_deref(), Some("#abcdef"));
+        assert_eq!(normalize_color("#abc").as_deref(), Some("#aabbcc"));
+        assert_eq!(normalize_color("0x102030").as_deref(), Some("#102030"));
+        assert_eq!(normalize_color(" '#102030' ").as_deref(), Some("#102030"));
+        assert_eq!(normalize_color("102030").as_deref(), Some("#102030"));
+        assert_eq!(normalize_color("#12345"), None);
+        assert_eq!(normalize_color("none"), None);
+    }
+
+    #[test]
+    fn normalize_color_scales_x11_channels() {
+        assert_eq!(normalize_color("rgb:ff/80/00").as_deref(), Some("#ff8000"));
+        assert_eq!(normalize_color("rgb:f/8/0").as_deref(), Some("#ff8800"));
+        assert_eq!(
+            normalize_color("rgb:fff/800/000").as_deref(),
+            Some("#ff8000")
+        );
+        assert_eq!(
+            normalize_color("rgb:ffff/8000/0000").as_deref(),
+            Some("#ff8000")
+        );
+        assert_eq!(normalize_color("rgb:ff/80"), None);
+        assert_eq!(normalize_color("rgb:fffff/0/0"), None);
+        assert_eq!(normalize_color("rgb:ff//00"), None);
+    }
+
+    #[test]
+    fn strip_json_comments_keeps_strings() {
+        let text = "{\n  // comment\n  \"url\": \"http://a/*b*/\", /* x */\n  \"q\": \"\\\"//\"\n}";
+        let stripped = strip_json_comments(text);
+        assert_eq!(stripped.len(), text.len());
+        assert_eq!(stripped.lines().count(), text.lines().count());
+        let json: serde_json::Value = serde_json::from_str(&stripped).expect("valid JSON");
+        assert_eq!(json["url"], "http://a/*b*/");
+        assert_eq!(json["q"], "\"//");
+    }
+
+    #[test]
+    fn strip_json_comments_drops_trailing_commas() {
+        let text = "{\"a\": [1, 2,],\n \"b\": \",]\", /* c */ }";
+        let json: serde_json::Value =
+            serde_json::from_str(&strip_json_comments(text)).expect("valid JSON");
+        assert_eq!(json["a"], serde_json::json!([1, 2]));
+        assert_eq!(json["b"], ",]");
+    }
+
+    #[test]
+    fn import_windows_terminal_picks_a_scheme() {
+        let settings = r##"{
+            // Windows Terminal settings
+            "schemes": [
+                { "name": "One", "background": "#000000", },
+                { "name": "Two", "background": "#111111", "purple": "#222222", },
+            ],
+        }"##;
+        let error = import_windows_terminal(settings, None)
+            .err()
+            .unwrap_or_default();
+        assert!(error.contains("One, Two"), "{error}");
+        let error = import_windows_terminal(settings, Some("Three"))
+            .err()
+            .unwrap_or_default();
+        assert!(error.contains("One, Two"), "{error}");
+        let colors = import_windows_terminal(settings, Some("Two")).expect("scheme Two");
+        assert_eq!(colors.colors["background"], "#111111");
+        assert_eq!(colors.colors["color5"], "#222222");
+
+        let single = r##"{ "name": "Only", "background": "#123456" }"##;
+        let colors = import_windows_terminal(single, None).expect("single scheme");
+        assert_eq!(colors.colors["background"], "#123456");
+    }
+
+    #[test]
+    fn flatten_yaml_reads_nested_mappings() {
+        let yaml = "\
+# Alacritty colors
+colors:
+  primary:
+    background: '#1d1f21'
+    foreground: \"#c5c8c6\" # comment
+  cursor:
+    text: CellBackground # comment
+  normal:
+    black: 0x000000
+";
+        let values = flatten_yaml(yaml);
+        assert_eq!(values["colors.primary.background"], "#1d1f21");
+        assert_eq!(values["colors.primary.foreground"], "#c5c8c6");
+        assert_eq!(values["colors.cursor.text"], "CellBackground");
+        assert_eq!(values["colors.normal.black"], "0x000000");
+        assert_eq!(values.len(), 4);
+    }
+
+    #[test]
+    fn import_xresources_substitutes_defines() {
+        let xresources = "\
+! comment
+#define base00 #1d1f21
+#define base05 #c5c8c6
+*.background: base00
+*foreground:  base05
+URxvt*cursorColor: #ffffff
+*.color0: #000000
+*color15: rgb:ff/ff/ff
+*.font: monospace
+";
+        let colors = import_xresources(xresources).expect("valid Xresources");
+        assert_eq!(colors.colors["background"], "#1d1f21");
+        assert_eq!(colors.colors["foreground"], "#c5c8c6");
+        assert_eq!(colors.colors["cursor"], "#ffffff");
+        assert_eq!(colors.colors["color0"], "#000000");
+        assert_eq!(colors.colors["color15"], "#ffffff");
+        assert_eq!(colors.colors.len(), 5);
+        assert!(import_xresources("*.color1: nope").is_err());
+    }
+}